@@ -0,0 +1,46 @@
+//! Measures evaluation speed on a few workloads. Run with `cargo bench`.
+
+use std::time::{Duration, Instant};
+
+use brewlis::Interpreter;
+
+const SETUP: &str = "
+(define (fib n) (if (< n 2) n (+ (fib (- n 1)) (fib (- n 2)))))
+(define (count n acc) (if (= n 0) acc (count (- n 1) (+ acc 1))))
+(define (numbers n) (let loop ((i n) (acc '())) (if (= i 0) acc (loop (- i 1) (cons i acc)))))
+(define big (numbers 2000))
+(define (walk l) (if (null? l) 0 (+ 1 (walk (cdr l)))))
+";
+
+const BENCHES: &[(&str, &str)] = &[
+    ("fib 20", "(fib 20)"),
+    ("tail loop", "(count 100000 0)"),
+    ("build list", "(numbers 2000)"),
+    ("map list", "(map (lambda (x) (* x x)) big)"),
+    ("quoted data", "(length (quote ((1 2 3) (4 5 6) (7 8 9) (10 11 12) (13 14 15) (16 17 18))))"),
+    ("walk list", "(walk big)"),
+];
+
+/// Runs `program` repeatedly for about a second and returns the mean time
+/// of one run.
+fn measure(interpreter: &mut Interpreter, program: &str) -> Duration {
+    let start = Instant::now();
+    let mut runs = 0;
+    while runs < 3 || start.elapsed() < Duration::from_secs(1) {
+        interpreter.eval_str(program).expect("benchmark failed");
+        runs += 1;
+    }
+    start.elapsed() / runs
+}
+
+fn main() {
+    for vm in [false, true] {
+        let mut interpreter = Interpreter::new();
+        interpreter.use_vm(vm);
+        interpreter.eval_str(SETUP).expect("setup failed");
+        for (name, program) in BENCHES {
+            let evaluator = if vm { "vm" } else { "eval" };
+            println!("{:<5} {:<12} {:>12.3?}", evaluator, name, measure(&mut interpreter, program));
+        }
+    }
+}
@@ -0,0 +1,47 @@
+#ifndef BREWLIS_H
+#define BREWLIS_H
+
+/* Generated by cbindgen from src/capi.rs, do not edit. */
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+// The program ran, the buffer holds the value of its last form.
+#define BREWLIS_OK 0
+
+// The program failed, the buffer holds the error.
+#define BREWLIS_ERROR 1
+
+// A pointer was null or the program not valid UTF-8.
+#define BREWLIS_INVALID_ARGUMENT 2
+
+// An interpreter used through the C interface.
+typedef struct BrewlisContext BrewlisContext;
+
+// Creates a context with the standard environment. It must be freed with
+// `brewlis_free`.
+struct BrewlisContext *brewlis_new(void);
+
+// Evaluates the NUL-terminated program `source` in `ctx` and writes the
+// displayed value of the last form, or the error, to `out`. At most
+// `out_len` bytes are written, including the terminating NUL, longer
+// texts are cut off at a character boundary. Returns one of the
+// `BREWLIS_*` codes.
+//
+// # Safety
+//
+// `ctx` must come from `brewlis_new` and not be freed, `source` must be
+// NUL-terminated and `out` point to `out_len` writable bytes.
+int brewlis_eval(struct BrewlisContext *ctx, const char *source, char *out, size_t out_len);
+
+// Frees a context created by `brewlis_new`. Null is ignored.
+//
+// # Safety
+//
+// `ctx` must be null or come from `brewlis_new`, and not be used again.
+void brewlis_free(struct BrewlisContext *ctx);
+
+#endif  /* BREWLIS_H */
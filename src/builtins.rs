@@ -0,0 +1,327 @@
+use std::{collections::HashMap, fmt};
+
+use crate::{env::Env, error::{Result, eval_error}, exp::Exp, symbol::Symbol};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Arity {
+    Exactly(usize),
+    AtLeast(usize),
+    Range(usize, usize),
+}
+
+impl Arity {
+    /// Whether a call with `given` arguments matches.
+    pub fn accepts(&self, given: usize) -> bool {
+        match *self {
+            Arity::Exactly(n) => given == n,
+            Arity::AtLeast(n) => given >= n,
+            Arity::Range(lo, hi) => (lo..=hi).contains(&given),
+        }
+    }
+}
+
+impl fmt::Display for Arity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Arity::Exactly(1) => write!(f, "1 argument"),
+            Arity::Exactly(n) => write!(f, "{} arguments", n),
+            Arity::AtLeast(n) => write!(f, "at least {} argument{}", n, if *n == 1 { "" } else { "s" }),
+            Arity::Range(lo, hi) => write!(f, "{} to {} arguments", lo, hi),
+        }
+    }
+}
+
+pub struct Builtin {
+    pub name: &'static str,
+    pub arity: Arity,
+    pub doc: &'static str,
+    pub special_form: bool,
+}
+
+const fn builtin(name: &'static str, arity: Arity, doc: &'static str) -> Builtin {
+    Builtin { name, arity, doc, special_form: false }
+}
+
+const fn special_form(name: &'static str, arity: Arity, doc: &'static str) -> Builtin {
+    Builtin { name, arity, doc, special_form: true }
+}
+
+pub static BUILTINS: &[Builtin] = &[
+    special_form("if", Arity::Range(2, 3), "(if test conseq [alt]) evaluates alt if test is #f, conseq otherwise; without alt it returns #f"),
+    special_form("define", Arity::AtLeast(2), "(define sym exp) binds the value of exp to sym, (define (f params...) body...) defines a procedure"),
+    special_form("quote", Arity::Exactly(1), "(quote exp) returns exp without evaluating it, also written 'exp"),
+    special_form("quasiquote", Arity::Exactly(1), "(quasiquote exp) returns exp with the parts in (unquote x) and (unquote-splicing l) evaluated, also written `exp, ,x and ,@l"),
+    special_form("define-values", Arity::Exactly(2), "(define-values (sym...) exp) binds each symbol to the matching value returned by exp"),
+    special_form("set!", Arity::Exactly(2), "(set! sym exp) changes the value of the bound symbol sym to the value of exp"),
+    special_form("define-macro", Arity::AtLeast(2), "(define-macro (name params...) body...) defines a macro transforming its unevaluated arguments"),
+    special_form("define-syntax", Arity::Exactly(2), "(define-syntax name (syntax-rules (literal...) (pattern template)...)) defines a hygienic macro"),
+    special_form("when", Arity::AtLeast(2), "(when test body...) evaluates body if test is true, otherwise returns #f"),
+    special_form("unless", Arity::AtLeast(2), "(unless test body...) evaluates body if test is false, otherwise returns #f"),
+    special_form("begin", Arity::AtLeast(1), "(begin exp...) evaluates each expression and returns the value of the last"),
+    special_form("define-test", Arity::AtLeast(2), "(define-test name body...) registers a test evaluating body, run by run-tests or brewlis test"),
+    special_form("assert-error", Arity::Exactly(1), "(assert-error exp) returns #t if evaluating exp raises an error and fails otherwise"),
+    special_form("trace", Arity::AtLeast(1), "(trace f...) prints the arguments of each call of the procedures named f and its value, indented by the traced calls it is nested in"),
+    special_form("untrace", Arity::AtLeast(0), "(untrace f...) stops tracing the procedures named f, or all without names"),
+    special_form("break", Arity::Exactly(0), "(break) stops in the debugger, enabled by --debug, and returns #t"),
+    special_form("time", Arity::Exactly(1), "(time exp) returns the value of exp and prints how long evaluating it took, with the expressions evaluated, VM instructions run and containers allocated"),
+    special_form("define-module", Arity::Exactly(1), "(define-module (my utils)) starts the module that (import (my utils)) evaluates from the file my/utils.brl"),
+    special_form("export", Arity::AtLeast(0), "(export sym...) makes the definitions sym of a module visible to the files importing it"),
+    special_form("import", Arity::AtLeast(1), "(import (name...)...) evaluates each module in an environment of its own and binds the names it exports"),
+    special_form("help", Arity::Range(0, 1), "(help) lists all builtins, (help name) describes a builtin or definition with its documentation string and the number of arguments it takes"),
+    special_form("delay", Arity::Exactly(1), "(delay exp) returns a promise to evaluate exp when it is forced"),
+    special_form("lambda", Arity::AtLeast(1), "(lambda (param... (param default)... #:keyword param... . rest) body...) creates a procedure; optional parameters take the value of their default without an argument, rest the list of the remaining arguments, and keyword parameters, which may have defaults too, the argument after their keyword"),
+    special_form("and", Arity::AtLeast(0), "(and exp...) returns the first false value, or the last value if all are true"),
+    special_form("or", Arity::AtLeast(0), "(or exp...) returns the first true value, or the last value if all are false"),
+    special_form("cond", Arity::AtLeast(0), "(cond (test exp...)... (else exp...)) evaluates the expressions of the first true clause"),
+    special_form("case", Arity::AtLeast(1), "(case key ((datum...) exp...)... (else exp...)) evaluates the expressions of the first clause with a datum equal to key"),
+    special_form("guard", Arity::AtLeast(2), "(guard (var clause...) body...) evaluates body, binding a raised condition to var for the cond clauses"),
+    special_form("while", Arity::AtLeast(1), "(while test body...) evaluates body as long as test is true and returns #t"),
+    special_form("do", Arity::AtLeast(2), "(do ((var init step)...) (test result...) body...) evaluates body and updates each var to its step until test is true, then returns the last result or #t"),
+    special_form("let", Arity::AtLeast(2), "(let ((sym init)...) body...) evaluates body with local bindings; (let name ((sym init)...) body...) also binds name to a procedure over the bindings for looping"),
+    special_form("let*", Arity::AtLeast(2), "(let* ((sym init)...) body...) like let, but each init sees the previous bindings"),
+    special_form("letrec", Arity::AtLeast(2), "(letrec ((sym init)...) body...) like let, but the bindings may be mutually recursive"),
+    builtin("^", Arity::Exactly(2), "(^ x y) raises x to the power y"),
+    builtin("expt", Arity::Exactly(2), "(expt x y) raises x to the power y, exactly for integers"),
+    builtin("*", Arity::AtLeast(0), "(* x...) multiplies its arguments"),
+    builtin("/", Arity::AtLeast(1), "(/ x y...) divides x by each y, (/ x) returns 1/x, exactly for exact numbers"),
+    builtin("+", Arity::AtLeast(0), "(+ x...) adds its arguments"),
+    builtin("-", Arity::AtLeast(1), "(- x y...) subtracts each y from x, (- x) negates x"),
+    builtin(">", Arity::AtLeast(1), "(> x y...) is true if the arguments are strictly decreasing"),
+    builtin("<", Arity::AtLeast(1), "(< x y...) is true if the arguments are strictly increasing"),
+    builtin(">=", Arity::AtLeast(1), "(>= x y...) is true if the arguments are non-increasing"),
+    builtin("<=", Arity::AtLeast(1), "(<= x y...) is true if the arguments are non-decreasing"),
+    builtin("=", Arity::AtLeast(1), "(= x y...) is true if all arguments are equal"),
+    builtin("abs", Arity::Exactly(1), "(abs x) returns the absolute value of x"),
+    builtin("exact->inexact", Arity::Exactly(1), "(exact->inexact x) converts x to a floating point number"),
+    builtin("sqrt", Arity::Exactly(1), "(sqrt x) returns the square root of x, exactly for squares of exact integers"),
+    builtin("floor", Arity::Exactly(1), "(floor x) returns the largest integer not above x"),
+    builtin("ceil", Arity::Exactly(1), "(ceil x) returns the smallest integer not below x"),
+    builtin("ceiling", Arity::Exactly(1), "(ceiling x) is the same as ceil"),
+    builtin("round", Arity::Exactly(1), "(round x) returns the integer nearest to x, the even one if x is halfway between two"),
+    builtin("truncate", Arity::Exactly(1), "(truncate x) returns the integer nearest to x towards zero"),
+    builtin("min", Arity::AtLeast(1), "(min x...) returns the smallest argument, inexact if any argument is"),
+    builtin("max", Arity::AtLeast(1), "(max x...) returns the largest argument, inexact if any argument is"),
+    builtin("quotient", Arity::Exactly(2), "(quotient x y) divides x by y, truncating towards zero"),
+    builtin("gcd", Arity::AtLeast(0), "(gcd n...) returns the greatest common divisor of the integers, 0 without arguments"),
+    builtin("lcm", Arity::AtLeast(0), "(lcm n...) returns the least common multiple of the integers, 1 without arguments"),
+    builtin("even?", Arity::Exactly(1), "(even? n) tests whether the integer n is even"),
+    builtin("odd?", Arity::Exactly(1), "(odd? n) tests whether the integer n is odd"),
+    builtin("bitwise-and", Arity::AtLeast(0), "(bitwise-and n...) returns the bits set in all integers, in two's complement"),
+    builtin("bitwise-or", Arity::AtLeast(0), "(bitwise-or n...) returns the bits set in any of the integers, in two's complement"),
+    builtin("bitwise-xor", Arity::AtLeast(0), "(bitwise-xor n...) returns the bits set in an odd number of the integers, in two's complement"),
+    builtin("bitwise-not", Arity::Exactly(1), "(bitwise-not n) returns n with all bits flipped, which is -n - 1"),
+    builtin("arithmetic-shift", Arity::Exactly(2), "(arithmetic-shift n k) shifts the bits of n left by k, or right by -k rounding down"),
+    builtin("modulo", Arity::Exactly(2), "(modulo x y) returns the remainder of dividing x by y, with the sign of y"),
+    builtin("remainder", Arity::Exactly(2), "(remainder x y) returns the remainder of dividing x by y, with the sign of x"),
+    builtin("log", Arity::Range(1, 2), "(log x base) returns the logarithm of x to base, or the natural logarithm without base"),
+    builtin("log10", Arity::Exactly(1), "(log10 x) returns the base 10 logarithm of x"),
+    builtin("atan2", Arity::Exactly(2), "(atan2 y x) returns the angle of the point (x, y) from the positive x axis"),
+    builtin("sin", Arity::Exactly(1), "(sin x) returns the sine of x"),
+    builtin("cos", Arity::Exactly(1), "(cos x) returns the cosine of x"),
+    builtin("tan", Arity::Exactly(1), "(tan x) returns the tangent of x"),
+    builtin("sinh", Arity::Exactly(1), "(sinh x) returns the hyperbolic sine of x"),
+    builtin("cosh", Arity::Exactly(1), "(cosh x) returns the hyperbolic cosine of x"),
+    builtin("tanh", Arity::Exactly(1), "(tanh x) returns the hyperbolic tangent of x"),
+    builtin("asin", Arity::Exactly(1), "(asin x) returns the arcsine of x"),
+    builtin("acos", Arity::Exactly(1), "(acos x) returns the arccosine of x"),
+    builtin("atan", Arity::Range(1, 2), "(atan x) returns the arctangent of x, (atan y x) the same as (atan2 y x)"),
+    builtin("asinh", Arity::Exactly(1), "(asinh x) returns the inverse hyperbolic sine of x"),
+    builtin("acosh", Arity::Exactly(1), "(acosh x) returns the inverse hyperbolic cosine of x"),
+    builtin("atanh", Arity::Exactly(1), "(atanh x) returns the inverse hyperbolic tangent of x"),
+    builtin("exp", Arity::Exactly(1), "(exp x) returns e raised to the power x"),
+    builtin("ln", Arity::Exactly(1), "(ln x) returns the natural logarithm of x"),
+    builtin("magnitude", Arity::Exactly(1), "(magnitude z) returns the distance of the complex number z from zero"),
+    builtin("angle", Arity::Exactly(1), "(angle z) returns the angle of the complex number z to the positive real axis"),
+    builtin("real-part", Arity::Exactly(1), "(real-part z) returns the real part of the complex number z"),
+    builtin("imag-part", Arity::Exactly(1), "(imag-part z) returns the imaginary part of the complex number z, 0 for real numbers"),
+    builtin("make-rectangular", Arity::Exactly(2), "(make-rectangular x y) returns the complex number x+yi"),
+    builtin("make-polar", Arity::Exactly(2), "(make-polar r a) returns the complex number of magnitude r and angle a"),
+    builtin("car", Arity::Exactly(1), "(car pair) returns the first element of a pair or list"),
+    builtin("cdr", Arity::Exactly(1), "(cdr pair) returns the second element of a pair or the rest of a list"),
+    builtin("cons", Arity::Exactly(2), "(cons x y) returns a pair of x and y, prepending x if y is a list"),
+    builtin("list", Arity::AtLeast(0), "(list x...) returns a list of its arguments"),
+    builtin("caar", Arity::Exactly(1), "(caar x) returns (car (car x))"),
+    builtin("cadr", Arity::Exactly(1), "(cadr x) returns (car (cdr x)), the second element of a list"),
+    builtin("cdar", Arity::Exactly(1), "(cdar x) returns (cdr (car x))"),
+    builtin("cddr", Arity::Exactly(1), "(cddr x) returns (cdr (cdr x))"),
+    builtin("caddr", Arity::Exactly(1), "(caddr x) returns (car (cddr x)), the third element of a list"),
+    builtin("cdddr", Arity::Exactly(1), "(cdddr x) returns (cdr (cddr x))"),
+    builtin("cadddr", Arity::Exactly(1), "(cadddr x) returns (car (cdddr x)), the fourth element of a list"),
+    builtin("length", Arity::Exactly(1), "(length l) returns the number of elements of list l"),
+    builtin("append", Arity::AtLeast(0), "(append l... x) returns the elements of the lists l followed by x, which need not be a list"),
+    builtin("reverse", Arity::Exactly(1), "(reverse l) returns the elements of list l in reverse order"),
+    builtin("list-tail", Arity::Exactly(2), "(list-tail l k) returns list l without its first k elements"),
+    builtin("list-ref", Arity::Exactly(2), "(list-ref l k) returns element k of list l"),
+    builtin("last", Arity::Exactly(1), "(last l) returns the last element of list l"),
+    builtin("member", Arity::Exactly(2), "(member x l) returns the rest of list l starting at the first element equal? to x, or #f"),
+    builtin("memv", Arity::Exactly(2), "(memv x l) is like member, comparing with eqv?"),
+    builtin("memq", Arity::Exactly(2), "(memq x l) is like member, comparing with eq?"),
+    builtin("assoc", Arity::Exactly(2), "(assoc key alist) returns the first pair of alist whose car is equal? to key, or #f"),
+    builtin("assv", Arity::Exactly(2), "(assv key alist) is like assoc, comparing with eqv?"),
+    builtin("assq", Arity::Exactly(2), "(assq key alist) is like assoc, comparing with eq?"),
+    builtin("make-vector", Arity::Range(1, 2), "(make-vector n fill) returns a vector of n elements, all set to fill or 0"),
+    builtin("vector-ref", Arity::Exactly(2), "(vector-ref v i) returns element i of vector v"),
+    builtin("vector-set!", Arity::Exactly(3), "(vector-set! v i x) sets element i of vector v to x"),
+    builtin("vector-length", Arity::Exactly(1), "(vector-length v) returns the number of elements of vector v"),
+    builtin("vector->list", Arity::Exactly(1), "(vector->list v) returns the elements of vector v as a list"),
+    builtin("list->vector", Arity::Exactly(1), "(list->vector l) returns a new vector of the elements of list l"),
+    builtin("make-hash", Arity::Exactly(0), "(make-hash) returns a new, empty hash table"),
+    builtin("hash-set!", Arity::Exactly(3), "(hash-set! h key value) associates key with value in hash table h"),
+    builtin("hash-ref", Arity::Range(2, 3), "(hash-ref h key default) returns the value of key in h, or default if given"),
+    builtin("hash-remove!", Arity::Exactly(2), "(hash-remove! h key) removes key from hash table h"),
+    builtin("hash-keys", Arity::Exactly(1), "(hash-keys h) returns a list of the keys of hash table h"),
+    builtin("json-parse", Arity::Exactly(1), "(json-parse str) converts the JSON text str to lists, hash tables, strings, numbers, booleans and null"),
+    builtin("json-stringify", Arity::Exactly(1), "(json-stringify value) converts value to JSON text"),
+    builtin("current-seconds", Arity::Exactly(0), "(current-seconds) returns the seconds since the Unix epoch as an integer"),
+    builtin("current-time", Arity::Exactly(0), "(current-time) returns the seconds since the Unix epoch with their fraction, for timing durations"),
+    builtin("time-format", Arity::Exactly(2), "(time-format t template) formats the time t, in seconds since the Unix epoch, with strftime directives like \"%Y-%m-%d %H:%M:%S\""),
+    builtin("random", Arity::Range(0, 1), "(random) returns a random float in [0, 1), (random n) a random number in [0, n), an integer if n is one"),
+    builtin("random-seed!", Arity::Exactly(1), "(random-seed! s) seeds the random number generator with the integer s, repeating its sequence for the same s"),
+    builtin("char->integer", Arity::Exactly(1), "(char->integer c) returns the Unicode code point of character c"),
+    builtin("integer->char", Arity::Exactly(1), "(integer->char n) returns the character with Unicode code point n"),
+    builtin("char=?", Arity::AtLeast(1), "(char=? c...) is true if all characters are equal"),
+    builtin("string-length", Arity::Exactly(1), "(string-length s) returns the number of characters of string s"),
+    builtin("substring", Arity::Range(2, 3), "(substring s start end) returns the characters of s from start up to end or the end of s"),
+    builtin("string-append", Arity::AtLeast(0), "(string-append s...) concatenates its string arguments"),
+    builtin("string-upcase", Arity::Exactly(1), "(string-upcase s) returns s in upper case"),
+    builtin("string-downcase", Arity::Exactly(1), "(string-downcase s) returns s in lower case"),
+    builtin("number->string", Arity::Range(1, 3), "(number->string n radix digits) writes n in radix 2 to 36, default 10, or with digits digits after the point"),
+    builtin("string->number", Arity::Range(1, 2), "(string->number s radix) reads the number written in s in radix 2 to 36, default 10, or returns #f"),
+    builtin("string-split", Arity::Range(1, 2), "(string-split s sep) returns a list of the parts of s between each separator string or character sep, or between whitespace; an empty sep splits s into its characters"),
+    builtin("string-index", Arity::Exactly(2), "(string-index s c) returns the index of the first character c in s, or #f"),
+    builtin("string=?", Arity::AtLeast(1), "(string=? s...) is true if all strings are equal"),
+    builtin("string->list", Arity::Exactly(1), "(string->list s) returns the characters of s as a list"),
+    builtin("list->string", Arity::Exactly(1), "(list->string l) returns the string of the characters in list l"),
+    builtin("char->string", Arity::Exactly(1), "(char->string c) returns the string of character c"),
+    builtin("symbol->string", Arity::Exactly(1), "(symbol->string s) returns the name of symbol s"),
+    builtin("string->symbol", Arity::Exactly(1), "(string->symbol s) returns the symbol named s"),
+    builtin("apply", Arity::AtLeast(2), "(apply f x... list) calls f with the xs followed by the elements of list"),
+    builtin("curry", Arity::AtLeast(1), "(curry f x...) returns a procedure calling f with the xs followed by its own arguments"),
+    builtin("map", Arity::AtLeast(2), "(map f list...) returns the results of calling f on the elements of the lists"),
+    builtin("for-each", Arity::AtLeast(2), "(for-each f list...) calls f on the elements of the lists in order"),
+    builtin("filter", Arity::Exactly(2), "(filter pred list) returns the elements of list for which pred is true"),
+    builtin("sort", Arity::Range(1, 2), "(sort l less?) returns the elements of list or vector l sorted stably by (less? a b), or numbers, strings, characters and symbols in ascending order without less?"),
+    builtin("fold-left", Arity::Exactly(3), "(fold-left f init list) combines the elements from the left as (f acc x)"),
+    builtin("fold-right", Arity::Exactly(3), "(fold-right f init list) combines the elements from the right as (f x acc)"),
+    builtin("eval", Arity::Range(1, 2), "(eval exp env) evaluates exp in env, or in the current environment"),
+    builtin("load", Arity::Exactly(1), "(load path) evaluates the forms of the file at path in the current environment"),
+    builtin("load-extension", Arity::Exactly(1), "(load-extension path) loads the shared library at path and defines the procedures it registers in the current environment"),
+    builtin("collect-garbage", Arity::Exactly(0), "(collect-garbage) frees the values only kept alive by reference cycles and returns how many containers were emptied"),
+    builtin("interaction-environment", Arity::Exactly(0), "(interaction-environment) returns the current environment for use with eval"),
+    builtin("error", Arity::AtLeast(1), "(error message irritant...) raises an error object"),
+    builtin("raise", Arity::Exactly(1), "(raise obj) raises obj as an exception"),
+    builtin("error-object?", Arity::Exactly(1), "(error-object? x) is true if x is an error object"),
+    builtin("error-object-message", Arity::Exactly(1), "(error-object-message e) returns the message of error object e"),
+    builtin("error-object-irritants", Arity::Exactly(1), "(error-object-irritants e) returns the irritants of error object e"),
+    builtin("call/cc", Arity::Exactly(1), "(call/cc f) calls f with an escape continuation, calling it returns its argument from call/cc"),
+    builtin("call-with-current-continuation", Arity::Exactly(1), "(call-with-current-continuation f) is the same as call/cc"),
+    builtin("values", Arity::AtLeast(0), "(values x...) returns the arguments as several values"),
+    builtin("call-with-values", Arity::Exactly(2), "(call-with-values producer consumer) calls consumer with the values returned by calling producer"),
+    builtin("force", Arity::Exactly(1), "(force promise) evaluates the expression of promise on the first call and returns its value"),
+    builtin("promise?", Arity::Exactly(1), "(promise? x) tests whether x is a promise"),
+    builtin("number?", Arity::Exactly(1), "(number? x) tests whether x is a number"),
+    builtin("integer?", Arity::Exactly(1), "(integer? x) tests whether x is an integer, exact or a float without a fraction"),
+    builtin("symbol?", Arity::Exactly(1), "(symbol? x) tests whether x is a symbol"),
+    builtin("string?", Arity::Exactly(1), "(string? x) tests whether x is a string"),
+    builtin("boolean?", Arity::Exactly(1), "(boolean? x) tests whether x is #t or #f"),
+    builtin("char?", Arity::Exactly(1), "(char? x) tests whether x is a character"),
+    builtin("keyword?", Arity::Exactly(1), "(keyword? x) tests whether x is a keyword like #:width"),
+    builtin("vector?", Arity::Exactly(1), "(vector? x) tests whether x is a vector"),
+    builtin("list?", Arity::Exactly(1), "(list? x) tests whether x is a list, including the empty list"),
+    builtin("pair?", Arity::Exactly(1), "(pair? x) tests whether x is a pair or a non-empty list"),
+    builtin("null?", Arity::Exactly(1), "(null? x) tests whether x is the empty list"),
+    builtin("procedure?", Arity::Exactly(1), "(procedure? x) tests whether x can be called, including the names of builtins"),
+    builtin("not", Arity::Exactly(1), "(not x) returns #t if x is #f, #f otherwise"),
+    builtin("eq?", Arity::Exactly(2), "(eq? x y) is true if x and y are the same object, or the same symbol, boolean, character or small number"),
+    builtin("eqv?", Arity::Exactly(2), "(eqv? x y) is true if x and y are the same object or equal atoms, numbers only if both are exact or both inexact"),
+    builtin("equal?", Arity::Exactly(2), "(equal? x y) is true if x and y are eqv? or lists, pairs, vectors, hash tables or strings with equal? contents"),
+    builtin("assert-equal", Arity::Exactly(2), "(assert-equal expected actual) returns #t if expected and actual are equal? and fails otherwise"),
+    builtin("run-tests", Arity::Exactly(0), "(run-tests) runs the tests defined with define-test, prints a line for each and a summary, and returns whether all passed"),
+    builtin("display", Arity::Exactly(1), "(display x) writes x to stdout for humans, strings and characters without quotes"),
+    builtin("write", Arity::Exactly(1), "(write x) writes x to stdout in the syntax it can be read back from"),
+    builtin("newline", Arity::Exactly(0), "(newline) writes a line break to stdout"),
+    builtin("format", Arity::AtLeast(1), "(format dest template arg...) fills in the ~a (display), ~s (write), ~d ~b ~o ~x (integer in base 10, 2, 8, 16), ~n (newline) and ~~ directives of template; prints the result if dest is #t, returns it if dest is #f or left out"),
+    builtin("read", Arity::Exactly(0), "(read) reads the next expression from stdin and returns it unevaluated"),
+    builtin("open-input-file", Arity::Exactly(1), "(open-input-file path) opens the file at path for reading and returns its port"),
+    builtin("open-output-file", Arity::Exactly(1), "(open-output-file path) creates or truncates the file at path for writing and returns its port"),
+    builtin("read-line", Arity::Range(0, 1), "(read-line port) returns the next line of port, the current input or stdin, without its line break, or the eof object at the end"),
+    builtin("read-all", Arity::Range(0, 1), "(read-all port) returns the rest of port, the current input or stdin, as a string"),
+    builtin("write-string", Arity::Range(1, 2), "(write-string s port) writes string s to output port port, or to stdout"),
+    builtin("close-port", Arity::Exactly(1), "(close-port port) writes out the buffered output of port and closes it"),
+    builtin("with-input-from-file", Arity::Exactly(2), "(with-input-from-file path thunk) calls thunk with the file at path as the current input of read-line and read-all"),
+    builtin("input-port?", Arity::Exactly(1), "(input-port? x) tests whether x is an input port"),
+    builtin("output-port?", Arity::Exactly(1), "(output-port? x) tests whether x is an output port"),
+    builtin("command-line-arguments", Arity::Exactly(0), "(command-line-arguments) returns the arguments after the script on the command line as a list of strings"),
+    builtin("exit", Arity::Range(0, 1), "(exit code) ends the program with exit status code, 0 without it or for #t and 1 for #f"),
+    builtin("system", Arity::Exactly(1), "(system command) runs command with the shell and returns its exit code; needs --allow-exec"),
+    builtin("process-output", Arity::Exactly(1), "(process-output command) runs command with the shell and returns what it wrote to stdout; needs --allow-exec"),
+    builtin("getenv", Arity::Exactly(1), "(getenv name) returns the value of environment variable name, or #f if it is not set"),
+    builtin("setenv", Arity::Exactly(2), "(setenv name value) sets environment variable name to string value for this process and its commands, or removes it if value is #f"),
+    builtin("eof-object", Arity::Exactly(0), "(eof-object) returns the eof object, which reading returns at the end of a file"),
+    builtin("eof-object?", Arity::Exactly(1), "(eof-object? x) tests whether x is the eof object"),
+    builtin("read-string", Arity::Exactly(1), "(read-string s) returns the first expression of string s unevaluated"),
+];
+
+thread_local! {
+    /// The builtins by name, since each call of a builtin looks up its arity.
+    static BY_NAME: HashMap<&'static str, &'static Builtin> = BUILTINS.iter().map(|b| (b.name, b)).collect();
+}
+
+pub fn lookup_builtin(name: &str) -> Option<&'static Builtin> {
+    BY_NAME.with(|by_name| by_name.get(name).copied())
+}
+
+/// The number of arguments the procedure or macro `value` takes, or None
+/// if it cannot be told.
+fn arity(value: &Exp) -> Option<Arity> {
+    match value {
+        Exp::Lambda(lambda) | Exp::Macro(lambda) => Some(lambda.params.arity()),
+        Exp::Closure(closure) => Some(closure.arity()),
+        _ => None,
+    }
+}
+
+/// The list of builtins, or the description of the definition or builtin
+/// `name`, definitions in `env` first.
+pub fn help_text(name: Option<&str>, env: &Env) -> Result<String> {
+    match name {
+        None => {
+            let names = |special_form: bool| BUILTINS.iter()
+                .filter(|b| b.special_form == special_form)
+                .map(|b| b.name)
+                .collect::<Vec<_>>()
+                .join(" ");
+            Ok(format!("Special forms: {}\nBuiltins: {}", names(true), names(false)))
+        },
+        Some(name) => {
+            let sym = Symbol::from(name);
+            if let Some(value) = env.get(&sym) {
+                let doc = env.doc(&sym);
+                let doc = doc.as_deref().unwrap_or("no documentation");
+                return Ok(match arity(&value) {
+                    Some(arity) => format!("{}: {} (takes {})", name, doc, arity),
+                    None => format!("{}: {}", name, doc),
+                });
+            }
+            let b = lookup_builtin(name).ok_or(eval_error!("No help available for '{}'", name))?;
+            Ok(format!("{}: {} (takes {})", b.name, b.doc, b.arity))
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn help_lists_builtins() {
+        let text = help_text(None, &Env::new()).unwrap();
+        assert!(text.split_whitespace().any(|name| name == "car"));
+    }
+
+    #[test]
+    fn help_on_unknown_name_fails() {
+        let err = help_text(Some("no-such-builtin"), &Env::new()).unwrap_err();
+        assert_eq!(err.to_string(), "No help available for 'no-such-builtin'");
+    }
+}
@@ -0,0 +1,80 @@
+//! The C interface of the shared library, for embedding brewlis in C, C++
+//! or, through `ctypes`, Python programs. `include/brewlis.h` declares it.
+//!
+//! A context owns an interpreter whose definitions persist between calls to
+//! `brewlis_eval`. Contexts are not thread safe, each must be used by the
+//! thread that created it.
+
+use std::{ffi::{CStr, c_char, c_int}, ptr};
+
+use crate::Interpreter;
+
+/// The program ran, the buffer holds the value of its last form.
+pub const BREWLIS_OK: c_int = 0;
+/// The program failed, the buffer holds the error.
+pub const BREWLIS_ERROR: c_int = 1;
+/// A pointer was null or the program not valid UTF-8.
+pub const BREWLIS_INVALID_ARGUMENT: c_int = 2;
+
+/// An interpreter used through the C interface.
+pub struct Context {
+    interpreter: Interpreter,
+}
+
+/// Creates a context with the standard environment. It must be freed with
+/// `brewlis_free`.
+#[no_mangle]
+pub extern "C" fn brewlis_new() -> *mut Context {
+    Box::into_raw(Box::new(Context { interpreter: Interpreter::new() }))
+}
+
+/// Evaluates the NUL-terminated program `source` in `ctx` and writes the
+/// displayed value of the last form, or the error, to `out`. At most
+/// `out_len` bytes are written, including the terminating NUL, longer
+/// texts are cut off at a character boundary. Returns one of the
+/// `BREWLIS_*` codes.
+///
+/// # Safety
+///
+/// `ctx` must come from `brewlis_new` and not be freed, `source` must be
+/// NUL-terminated and `out` point to `out_len` writable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn brewlis_eval(ctx: *mut Context, source: *const c_char, out: *mut c_char, out_len: usize) -> c_int {
+    if ctx.is_null() || source.is_null() || (out.is_null() && out_len > 0) {
+        return BREWLIS_INVALID_ARGUMENT;
+    }
+    // SAFETY: The caller passes a live context and a NUL-terminated string.
+    let (context, source) = unsafe { (&mut *ctx, CStr::from_ptr(source)) };
+    let Ok(source) = source.to_str() else {
+        return BREWLIS_INVALID_ARGUMENT;
+    };
+    let (text, code) = match context.interpreter.eval_str(source) {
+        Ok(value) => (value.to_string(), BREWLIS_OK),
+        Err(err) => (err.report(), BREWLIS_ERROR),
+    };
+    if out_len > 0 {
+        let mut len = text.len().min(out_len - 1);
+        while !text.is_char_boundary(len) {
+            len -= 1;
+        }
+        // SAFETY: `out` has room for `out_len` bytes, `len` is less.
+        unsafe {
+            ptr::copy_nonoverlapping(text.as_ptr(), out.cast(), len);
+            *out.add(len) = 0;
+        }
+    }
+    code
+}
+
+/// Frees a context created by `brewlis_new`. Null is ignored.
+///
+/// # Safety
+///
+/// `ctx` must be null or come from `brewlis_new`, and not be used again.
+#[no_mangle]
+pub unsafe extern "C" fn brewlis_free(ctx: *mut Context) {
+    if !ctx.is_null() {
+        // SAFETY: The context was allocated by `brewlis_new`.
+        drop(unsafe { Box::from_raw(ctx) });
+    }
+}
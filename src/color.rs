@@ -0,0 +1,78 @@
+use std::io::{IsTerminal, stdout};
+
+use logos::Logos;
+
+use brewlis::lexer::Token;
+
+const RESET: &str = "\x1b[0m";
+const RED: &str = "\x1b[31m";
+const GREEN: &str = "\x1b[32m";
+const YELLOW: &str = "\x1b[33m";
+const MAGENTA: &str = "\x1b[35m";
+const CYAN: &str = "\x1b[36m";
+const BOLD_BLUE: &str = "\x1b[1;34m";
+
+/// Colors output with ANSI escape codes, or leaves it as it is if colors
+/// are disabled.
+#[derive(Clone, Copy)]
+pub struct Palette {
+    enabled: bool,
+}
+
+impl Palette {
+    /// Colors are used on terminals, unless disabled by `--no-color` or by
+    /// a non-empty `NO_COLOR` variable.
+    pub fn new(no_color: bool) -> Palette {
+        let no_color_env = std::env::var_os("NO_COLOR").is_some_and(|value| !value.is_empty());
+        Palette { enabled: !no_color && !no_color_env && stdout().is_terminal() }
+    }
+
+    fn paint(&self, color: &str, text: &str) -> String {
+        if self.enabled {
+            format!("{}{}{}", color, text, RESET)
+        } else {
+            text.to_string()
+        }
+    }
+
+    /// Colors the tokens of `text` by their kind. Everything between tokens
+    /// is kept as it is.
+    pub fn highlight(&self, text: &str) -> String {
+        if !self.enabled {
+            return text.to_string();
+        }
+        let mut result = String::with_capacity(text.len());
+        let mut lex = Token::lexer(text);
+        let mut end = 0;
+        while let Some(token) = lex.next() {
+            let span = lex.span();
+            result.push_str(&text[end..span.start]);
+            let color = match token {
+                Ok(Token::StrFloat | Token::StrRational | Token::StrRadix | Token::StrComplex) => Some(CYAN),
+                Ok(Token::True | Token::False | Token::Keyword) => Some(MAGENTA),
+                Ok(Token::StringLiteral | Token::CharLiteral) => Some(GREEN),
+                Ok(Token::Str | Token::StrQualified | Token::StrOperation) => Some(YELLOW),
+                _ => None,
+            };
+            match color {
+                Some(color) => result.push_str(&self.paint(color, lex.slice())),
+                None => result.push_str(lex.slice()),
+            }
+            end = span.end;
+        }
+        result.push_str(&text[end..]);
+        result
+    }
+
+    pub fn error(&self, text: &str) -> String {
+        self.paint(RED, text)
+    }
+
+    pub fn success(&self, text: &str) -> String {
+        self.paint(GREEN, text)
+    }
+
+    pub fn prompt(&self, text: &str) -> String {
+        self.paint(BOLD_BLUE, text)
+    }
+}
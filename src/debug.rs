@@ -0,0 +1,122 @@
+//! The interface of debuggers to the tree-walking evaluator.
+//!
+//! A program embedding brewlis installs a debugger with [`set_debugger`].
+//! Evaluation then stops and calls it at `(break)`, before each form while
+//! stepping and, with [`set_break_on_error`], where an error is raised,
+//! before a `guard` catches it. The debugger inspects the [`Stop`] and
+//! tells evaluation how to go on with a [`Resume`]. Code compiled by the
+//! VM does not stop.
+
+use std::cell::{Cell, RefCell};
+
+use crate::{env::Env, error::BrewlisError, exp::Exp};
+
+/// Why evaluation stopped.
+pub enum Reason<'a> {
+    Break,
+    Step,
+    Error(&'a BrewlisError),
+}
+
+pub struct Stop<'a> {
+    pub reason: Reason<'a>,
+    /// The `(break)` form, the form about to be evaluated or the form
+    /// which failed.
+    pub form: &'a Exp,
+    /// The environment `form` is evaluated in.
+    pub env: &'a Env,
+    /// The calls being evaluated, the innermost last.
+    pub calls: &'a [Exp],
+}
+
+pub enum Resume {
+    Continue,
+    /// Stops before the next form, also inside procedures called.
+    Step,
+    /// Stops before the next form after the current one returned. After
+    /// an error or at `(break)` it is the same as `Step`.
+    Next,
+}
+
+pub type Debugger = Box<dyn FnMut(&Stop) -> Resume>;
+
+thread_local! {
+    /// The debugger, taken out while it runs so that the expressions it
+    /// evaluates do not stop.
+    static DEBUGGER: RefCell<Option<Debugger>> = const { RefCell::new(None) };
+    /// Whether a debugger is installed, checked before each form.
+    static ACTIVE: Cell<bool> = const { Cell::new(false) };
+    static BREAK_ON_ERROR: Cell<bool> = const { Cell::new(false) };
+    static STEPPING: Cell<bool> = const { Cell::new(false) };
+    /// Whether the error being unwound was shown to the debugger, so that
+    /// the evaluations it propagates to do not stop for it again.
+    static STOPPED_AT_ERROR: Cell<bool> = const { Cell::new(false) };
+}
+
+/// Installs `debugger` for all interpreters of the thread, or removes the
+/// debugger with `None`.
+pub fn set_debugger(debugger: Option<Debugger>) {
+    ACTIVE.with(|active| active.set(debugger.is_some()));
+    DEBUGGER.with(|cell| *cell.borrow_mut() = debugger);
+}
+
+/// Makes evaluation stop where errors are raised, for all interpreters of
+/// the thread.
+pub fn set_break_on_error(enabled: bool) {
+    BREAK_ON_ERROR.with(|cell| cell.set(enabled));
+}
+
+pub(crate) fn is_active() -> bool {
+    ACTIVE.with(Cell::get)
+}
+
+/// Notes that evaluation goes on, after an error was caught if one was
+/// being unwound.
+pub(crate) fn evaluating() {
+    STOPPED_AT_ERROR.with(|stopped| stopped.set(false));
+}
+
+pub(crate) fn is_stepping() -> bool {
+    STEPPING.with(Cell::get)
+}
+
+/// Stops before the next form, once the form stepped over returned.
+pub(crate) fn step() {
+    STEPPING.with(|stepping| stepping.set(true));
+}
+
+/// Whether evaluation stops for `err`. Continuations and `exit` unwind
+/// without stopping.
+pub(crate) fn breaks_on(err: &BrewlisError) -> bool {
+    is_active()
+        && BREAK_ON_ERROR.with(Cell::get)
+        && !STOPPED_AT_ERROR.with(Cell::get)
+        && !matches!(err.root(), BrewlisError::Escape { .. } | BrewlisError::Exit { .. })
+}
+
+/// Calls the debugger at `stop`. Returns `Continue` if the debugger is
+/// running already.
+pub(crate) fn stop(stop: &Stop) -> Resume {
+    let Some(mut debugger) = DEBUGGER.with(|cell| cell.borrow_mut().take()) else {
+        return Resume::Continue;
+    };
+    STEPPING.with(|stepping| stepping.set(false));
+    let resume = debugger(stop);
+    // Unless it removed or replaced itself
+    if is_active() {
+        DEBUGGER.with(|cell| {
+            cell.borrow_mut().get_or_insert(debugger);
+        });
+    }
+    // Stepping over a form resumes once it returned
+    let stepping = match resume {
+        Resume::Continue => false,
+        Resume::Step => true,
+        Resume::Next => !matches!(stop.reason, Reason::Step),
+    };
+    STEPPING.with(|cell| cell.set(stepping));
+    if matches!(stop.reason, Reason::Error(_)) {
+        STOPPED_AT_ERROR.with(|stopped| stopped.set(true));
+    }
+    resume
+}
@@ -0,0 +1,126 @@
+//! The debugger of `--debug`: a REPL that runs where evaluation stopped,
+//! in the environment of the stopped form.
+
+use rustyline::{DefaultEditor, error::ReadlineError};
+
+use brewlis::{Exp, debug::{Debugger, Reason, Resume, Stop}, eval::eval, parser::{open_lists, parse}};
+
+use crate::color::Palette;
+
+/// The debugger commands with their descriptions, listed by :help.
+const COMMANDS: &[(&str, &str)] = &[
+    (":locals", "list the local variables"),
+    (":backtrace", "list the calls being evaluated"),
+    (":step, :s", "evaluate up to the next form, also inside procedures"),
+    (":next, :n", "evaluate up to the next form after this one"),
+    (":continue, :c", "go on without stopping"),
+    (":help", "show this list"),
+];
+
+/// Prints `form` with its location if it has one.
+fn show_form(form: &Exp, palette: Palette) {
+    match form {
+        Exp::List(l) if l.span.is_some() => {
+            let location = l.span.as_ref().expect("a span").location();
+            println!("{}:\n{}", location, location.snippet());
+        },
+        form => println!("{}", palette.highlight(&form.to_string())),
+    }
+}
+
+fn show_stop(stop: &Stop, palette: Palette) {
+    match stop.reason {
+        Reason::Break => println!("Break"),
+        Reason::Step => println!("Step"),
+        Reason::Error(err) => println!("{}", palette.error(&format!("Error: {}", err))),
+    }
+    show_form(stop.form, palette);
+}
+
+/// Runs the debugger command `command`, given without its colon. Returns
+/// how to resume evaluation if the command ends the debugger.
+fn run_command(command: &str, stop: &Stop, palette: Palette) -> Option<Resume> {
+    match command.trim() {
+        "locals" => {
+            for (sym, value) in stop.env.locals() {
+                println!("{} = {}", sym, palette.highlight(&value.to_string()));
+            }
+        },
+        "backtrace" => {
+            for call in stop.calls.iter().rev() {
+                println!("in {}", palette.highlight(&call.to_string()));
+            }
+        },
+        "step" | "s" => return Some(Resume::Step),
+        "next" | "n" => return Some(Resume::Next),
+        "continue" | "c" => return Some(Resume::Continue),
+        "help" => {
+            for (command, description) in COMMANDS {
+                println!("{:<16} {}", command, description);
+            }
+        },
+        _ => println!("{}", palette.error(&format!("Unknown command :{}, see :help", command))),
+    }
+    None
+}
+
+/// Reads commands and expressions until a command resumes evaluation.
+/// Expressions are evaluated in the environment of the stopped form.
+fn debug(stop: &Stop, palette: Palette) -> rustyline::Result<Resume> {
+    show_stop(stop, palette);
+    let mut editor = DefaultEditor::new()?;
+    let mut s = String::new();
+    loop {
+        let prompt = if s.is_empty() { "debug> " } else { "... " };
+        match editor.readline(prompt) {
+            Ok(line) => {
+                if !s.is_empty() {
+                    s.push('\n');
+                }
+                s.push_str(&line);
+            },
+            Err(ReadlineError::Interrupted) => {
+                s.clear();
+                continue;
+            },
+            // Ctrl-D continues
+            Err(ReadlineError::Eof) => return Ok(Resume::Continue),
+            Err(err) => return Err(err),
+        }
+        if open_lists(&s) > 0 {
+            continue;
+        }
+        let s = std::mem::take(&mut s);
+        if s.trim().is_empty() {
+            continue;
+        }
+        editor.add_history_entry(s.as_str())?;
+        if let Some(command) = s.trim().strip_prefix(':') {
+            if let Some(resume) = run_command(command, stop, palette) {
+                return Ok(resume);
+            }
+            continue;
+        }
+        match parse(&s) {
+            Ok(forms) => {
+                for exp in &forms {
+                    match eval(exp, stop.env) {
+                        Ok(result) => println!("{}", palette.highlight(&result.to_string())),
+                        Err(err) => {
+                            println!("{}", palette.error(&format!("Evaluation error: {}", err.report())));
+                            break;
+                        },
+                    }
+                }
+            },
+            Err(err) => println!("{}", palette.error(&format!("Parsing error: {}", err.report()))),
+        }
+    }
+}
+
+pub fn debugger(palette: Palette) -> Debugger {
+    Box::new(move |stop| debug(stop, palette).unwrap_or_else(|err| {
+        eprintln!("{}", palette.error(&format!("Cannot run the debugger: {}", err)));
+        Resume::Continue
+    }))
+}
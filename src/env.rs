@@ -0,0 +1,158 @@
+use std::{cell::RefCell, collections::HashMap, f64::consts::{PI, E}, fmt, rc::Rc};
+
+use crate::{eval::eval, gc, exp::{Atom, Exp}, parser::parse, symbol::Symbol};
+
+/// The bindings of one scope and the scope it is nested in.
+pub struct Frame {
+    pub(crate) vars: HashMap<Symbol, Exp>,
+    /// The documentation strings of bindings of this frame.
+    pub(crate) docs: HashMap<Symbol, Rc<str>>,
+    pub(crate) parent: Option<Env>,
+}
+
+/// A handle to a chain of frames. Clones share the frames, so definitions
+/// made through one handle are visible through all others.
+#[derive(Clone)]
+pub struct Env(pub(crate) Rc<RefCell<Frame>>);
+
+impl Env {
+    /// Creates an empty top level environment.
+    pub fn new() -> Env {
+        Env::with_parent(None)
+    }
+
+    /// Creates an empty frame nested in this environment.
+    pub fn extend(&self) -> Env {
+        Env::with_parent(Some(self.clone()))
+    }
+
+    fn with_parent(parent: Option<Env>) -> Env {
+        let frame = Rc::new(RefCell::new(Frame { vars: HashMap::new(), docs: HashMap::new(), parent }));
+        gc::track(gc::Tracked::Frame(Rc::downgrade(&frame)));
+        Env(frame)
+    }
+
+    /// Looks `sym` up in this frame and then in the enclosing ones.
+    pub fn get(&self, sym: &Symbol) -> Option<Exp> {
+        let frame = self.0.borrow();
+        match frame.vars.get(sym) {
+            Some(value) => Some(value.clone()),
+            None => frame.parent.as_ref()?.get(sym),
+        }
+    }
+
+    pub fn contains(&self, sym: &Symbol) -> bool {
+        let frame = self.0.borrow();
+        frame.vars.contains_key(sym) || frame.parent.as_ref().is_some_and(|parent| parent.contains(sym))
+    }
+
+    /// Binds `sym` in this frame, shadowing bindings of enclosing frames.
+    pub fn define(&self, sym: Symbol, value: Exp) {
+        let mut frame = self.0.borrow_mut();
+        if !frame.docs.is_empty() {
+            frame.docs.remove(&sym);
+        }
+        frame.vars.insert(sym, value);
+    }
+
+    /// Binds `sym` like `define`, with the documentation string `doc`.
+    pub fn define_documented(&self, sym: Symbol, value: Exp, doc: Option<Rc<str>>) {
+        self.define(sym, value);
+        if let Some(doc) = doc {
+            self.0.borrow_mut().docs.insert(sym, doc);
+        }
+    }
+
+    /// The documentation string of the innermost binding of `sym`.
+    pub fn doc(&self, sym: &Symbol) -> Option<Rc<str>> {
+        let frame = self.0.borrow();
+        match frame.vars.contains_key(sym) {
+            true => frame.docs.get(sym).cloned(),
+            false => frame.parent.as_ref()?.doc(sym),
+        }
+    }
+
+    /// Changes the innermost existing binding of `sym`. Returns false if
+    /// `sym` is unbound.
+    pub fn set(&self, sym: &Symbol, value: Exp) -> bool {
+        let mut frame = self.0.borrow_mut();
+        if let Some(var) = frame.vars.get_mut(sym) {
+            *var = value;
+            return true;
+        }
+        match &frame.parent {
+            Some(parent) => parent.set(sym, value),
+            None => false,
+        }
+    }
+
+    /// The bindings of this frame and the enclosing ones, without the top
+    /// level, the innermost first.
+    pub fn locals(&self) -> Vec<(Symbol, Exp)> {
+        let mut locals: Vec<(Symbol, Exp)> = Vec::new();
+        let mut env = self.clone();
+        loop {
+            let parent = env.0.borrow().parent.clone();
+            let Some(parent) = parent else { break };
+            let mut vars: Vec<(Symbol, Exp)> = env.0.borrow().vars.iter()
+                .filter(|(sym, _)| !locals.iter().any(|(local, _)| local == *sym))
+                .map(|(sym, value)| (*sym, value.clone()))
+                .collect();
+            vars.sort_by(|a, b| a.0.as_str().cmp(b.0.as_str()));
+            locals.extend(vars);
+            env = parent;
+        }
+        locals
+    }
+
+    /// The symbols bound in this frame and the enclosing ones.
+    pub fn symbols(&self) -> Vec<Symbol> {
+        let frame = self.0.borrow();
+        let mut symbols: Vec<Symbol> = frame.vars.keys().cloned().collect();
+        if let Some(parent) = &frame.parent {
+            symbols.extend(parent.symbols().into_iter().filter(|sym| !frame.vars.contains_key(sym)));
+        }
+        symbols
+    }
+}
+
+impl Default for Env {
+    fn default() -> Self {
+        Env::new()
+    }
+}
+
+impl PartialEq for Env {
+    fn eq(&self, other: &Self) -> bool {
+        Rc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+impl fmt::Debug for Env {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // The bindings are left out, they usually contain the procedures
+        // which contain this environment again
+        write!(f, "Env")
+    }
+}
+
+/// Procedures written in brewlis, evaluated into the standard environment.
+const PRELUDE: &str = include_str!("prelude.brl");
+
+/// The top level environment with the builtin constants, but without the
+/// prelude.
+pub fn bare_env() -> Env {
+    let env = Env::new();
+    env.define(Symbol::new("pi"), Exp::Atom(Atom::Number(PI)));
+    env.define(Symbol::new("e"), Exp::Atom(Atom::Number(E)));
+
+    env
+}
+
+pub fn standard_env() -> Env {
+    let env = bare_env();
+    for exp in parse(PRELUDE).expect("the prelude parses") {
+        eval(&exp, &env).expect("the prelude evaluates");
+    }
+    env
+}
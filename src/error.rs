@@ -0,0 +1,212 @@
+use std::rc::Rc;
+
+use thiserror::Error;
+
+use crate::{builtins::Arity, exp::{ErrorObject, Exp, List}, span::Span, symbol::Symbol};
+
+pub type Result<T, E = BrewlisError> = std::result::Result<T, E>;
+
+/// An error of parsing or evaluating brewlis code. Errors attributable to
+/// a part of the source carry its span.
+#[derive(Debug, Error)]
+pub enum BrewlisError {
+    /// The source text is not a valid expression.
+    #[error("{message}")]
+    Parse { message: String, span: Option<Span> },
+    /// A symbol without a binding was evaluated.
+    #[error("{symbol}, not in env")]
+    Unbound { symbol: Symbol, span: Option<Span> },
+    /// A value of the wrong type, `expected` names the type with its
+    /// article, like "a number".
+    #[error("Not {expected}: {value}")]
+    Type { expected: &'static str, value: Exp, span: Option<Span> },
+    /// A procedure called with the wrong number of arguments.
+    #[error("'{procedure}' expects {expected}, got {given}")]
+    Arity { procedure: String, expected: Arity, given: usize, span: Option<Span> },
+    /// Any other error of evaluation, like a malformed special form or an
+    /// index out of range.
+    #[error("{message}")]
+    Eval { message: String, span: Option<Span> },
+    /// An object raised by `raise` or `error`.
+    #[error("{}", raised_message(value))]
+    Raised { value: Exp, span: Option<Span> },
+    /// The invocation of an escape continuation. It unwinds evaluation up to
+    /// the `call/cc` the continuation belongs to, which returns `value`.
+    #[error("Continuation called after its call/cc returned")]
+    Escape { continuation: u64, value: Exp },
+    /// A call of `exit`, which unwinds all evaluation so that the program
+    /// running brewlis can exit with `code`.
+    #[error("Exited with status {code}")]
+    Exit { code: i32 },
+    /// An error with a note on what was evaluated when it happened.
+    #[error("{context}: {source}")]
+    Context { context: String, source: Box<BrewlisError> },
+    /// An error with the calls it happened in, the innermost first, each
+    /// with how many times it was made in a row, and the number of outer
+    /// calls left out of them.
+    #[error("{source}")]
+    Backtrace { calls: Vec<(Exp, usize)>, omitted: usize, source: Box<BrewlisError> },
+}
+
+/// The most calls a backtrace shows, the innermost.
+const MAX_BACKTRACE: usize = 20;
+
+fn raised_message(value: &Exp) -> String {
+    match value {
+        Exp::Error(error) => error.to_string(),
+        exp => format!("Uncaught exception: {}", exp),
+    }
+}
+
+/// Creates a `BrewlisError::Eval` with a formatted message.
+macro_rules! eval_error {
+    ($($arg:tt)*) => {
+        $crate::error::BrewlisError::Eval { message: format!($($arg)*), span: None }
+    };
+}
+
+/// Creates a `BrewlisError::Parse` with a formatted message.
+macro_rules! parse_error {
+    ($($arg:tt)*) => {
+        $crate::error::BrewlisError::Parse { message: format!($($arg)*), span: None }
+    };
+}
+
+pub(crate) use {eval_error, parse_error};
+
+impl BrewlisError {
+    pub fn type_error(expected: &'static str, value: &Exp) -> BrewlisError {
+        BrewlisError::Type { expected, value: value.clone(), span: None }
+    }
+
+    pub fn arity(procedure: &str, expected: Arity, given: usize) -> BrewlisError {
+        BrewlisError::Arity { procedure: procedure.to_string(), expected, given, span: None }
+    }
+
+    /// The error without the context added to it.
+    pub fn root(&self) -> &BrewlisError {
+        match self {
+            BrewlisError::Context { source, .. } | BrewlisError::Backtrace { source, .. } => source.root(),
+            err => err,
+        }
+    }
+
+    pub fn span(&self) -> Option<&Span> {
+        match self.root() {
+            BrewlisError::Parse { span, .. }
+            | BrewlisError::Unbound { span, .. }
+            | BrewlisError::Type { span, .. }
+            | BrewlisError::Arity { span, .. }
+            | BrewlisError::Eval { span, .. }
+            | BrewlisError::Raised { span, .. } => span.as_ref(),
+            BrewlisError::Escape { .. } | BrewlisError::Exit { .. } | BrewlisError::Context { .. } | BrewlisError::Backtrace { .. } => None,
+        }
+    }
+
+    /// Adds the location of `span` to the error, unless an inner expression
+    /// already gave it a more precise one.
+    pub fn with_span(mut self, new_span: &Span) -> BrewlisError {
+        let mut err = &mut self;
+        while let BrewlisError::Context { source, .. } | BrewlisError::Backtrace { source, .. } = err {
+            err = source;
+        }
+        match err {
+            BrewlisError::Parse { span, .. }
+            | BrewlisError::Unbound { span, .. }
+            | BrewlisError::Type { span, .. }
+            | BrewlisError::Arity { span, .. }
+            | BrewlisError::Eval { span, .. }
+            | BrewlisError::Raised { span, .. } if span.is_none() => *span = Some(new_span.clone()),
+            _ => {},
+        }
+        self
+    }
+
+    pub fn with_context(self, context: impl Into<String>) -> BrewlisError {
+        BrewlisError::Context { context: context.into(), source: Box::new(self) }
+    }
+
+    /// Adds `call` to the backtrace, as the call the ones in it were made
+    /// from.
+    pub fn with_caller(mut self, call: &Exp) -> BrewlisError {
+        let mut err = &mut self;
+        while let BrewlisError::Context { source, .. } = err {
+            err = source;
+        }
+        match err {
+            // Recursion repeats the same call
+            BrewlisError::Backtrace { calls, omitted, .. } => {
+                let (repeated, full) = (calls.last().is_some_and(|(last, _)| last == call), calls.len() >= MAX_BACKTRACE);
+                match calls.last_mut() {
+                    Some((_, times)) if repeated && *omitted == 0 => *times += 1,
+                    _ if !full => calls.push((call.clone(), 1)),
+                    _ => *omitted += 1,
+                }
+            },
+            _ => return BrewlisError::Backtrace { calls: vec![(call.clone(), 1)], omitted: 0, source: Box::new(self) },
+        }
+        self
+    }
+
+    /// The calls the error happened in, the innermost first, with how many
+    /// times in a row each was made.
+    pub fn backtrace(&self) -> &[(Exp, usize)] {
+        match self {
+            BrewlisError::Context { source, .. } => source.backtrace(),
+            BrewlisError::Backtrace { calls, .. } => calls,
+            _ => &[],
+        }
+    }
+
+    fn omitted_calls(&self) -> usize {
+        match self {
+            BrewlisError::Context { source, .. } => source.omitted_calls(),
+            BrewlisError::Backtrace { omitted, .. } => *omitted,
+            _ => 0,
+        }
+    }
+
+    /// The exit status if the error is a call of `exit`.
+    pub fn exit_code(&self) -> Option<i32> {
+        match self.root() {
+            BrewlisError::Exit { code } => Some(*code),
+            _ => None,
+        }
+    }
+
+    /// The object the error is caught as by `guard`. Errors of builtins are
+    /// turned into error objects carrying their message, without the added
+    /// context.
+    pub fn condition(&self) -> Exp {
+        match self.root() {
+            BrewlisError::Raised { value, .. } => value.clone(),
+            err => Exp::Error(Rc::new(ErrorObject { message: err.to_string(), irritants: List::new() })),
+        }
+    }
+
+    /// Formats the error, followed by the annotated source line if it has a
+    /// location and by the calls it happened in.
+    pub fn report(&self) -> String {
+        let mut report = match self.span() {
+            Some(span) => {
+                let location = span.location();
+                format!("{}\n{}:\n{}", self, location, location.snippet())
+            },
+            None => self.to_string(),
+        };
+        let calls = self.backtrace();
+        if !calls.is_empty() {
+            for (call, times) in calls {
+                report.push_str(&format!("\nin {}", call));
+                if *times > 1 {
+                    report.push_str(&format!(" ({} times)", times));
+                }
+            }
+            if self.omitted_calls() > 0 {
+                report.push_str(&format!("\n... {} more", self.omitted_calls()));
+            }
+            report.push_str("\nat top level");
+        }
+        report
+    }
+}
@@ -0,0 +1,2284 @@
+use std::{cell::{Cell, RefCell}, cmp::Ordering, collections::HashMap, fs, io::{Read, Write, stdin, stdout}, rc::Rc};
+
+use num_bigint::BigInt;
+use num_complex::Complex64;
+use num_integer::Integer;
+
+use crate::{builtins::{Arity, help_text, lookup_builtin}, debug::{self, Reason, Resume, Stop}, env::Env, error::{BrewlisError, Result, eval_error}, exp::{Atom, Displayed, ErrorObject, Exp, HashKey, Lambda, List, Params, Promise}, gc, json, module, numeric::Num, parser::{open_lists, parse, parse_first}, plugin, port::{self, Port}, process, random, span::Span, symbol::Symbol, syntax_rules::SyntaxRules, testing, time, trace, vm};
+
+/// The most bits `arithmetic-shift` shifts an integer to the left by.
+const MAX_SHIFT: usize = 1 << 24;
+
+fn numbers(l: &[Exp]) -> Result<Vec<Num>> {
+    l.iter().map(Num::from_exp).collect()
+}
+
+/// Splits the numeric arguments of `procname` into the first and the rest.
+fn numbers_split(procname: &str, l: &[Exp]) -> Result<(Num, Vec<Num>)> {
+    let mut numbers = numbers(l)?;
+    if numbers.is_empty() {
+        return Err(BrewlisError::arity(procname, Arity::AtLeast(1), 0))
+    }
+    let first = numbers.remove(0);
+    Ok((first, numbers))
+}
+
+/// The value of an exact integer argument.
+fn exact_integer(exp: &Exp) -> Result<BigInt> {
+    match exp {
+        Exp::Atom(Atom::Int(i)) => Ok(BigInt::from(*i)),
+        Exp::Atom(Atom::BigInt(i)) => Ok(BigInt::clone(i)),
+        _ => Err(BrewlisError::type_error("an exact integer", exp)),
+    }
+}
+
+/// The radix argument of `number->string` and `string->number`, 10 if it
+/// is missing.
+fn radix(exp: Option<&Exp>) -> Result<u32> {
+    match exp {
+        None => Ok(10),
+        Some(Exp::Atom(Atom::Int(radix))) if (2..=36).contains(radix) => Ok(*radix as u32),
+        Some(exp) => Err(BrewlisError::type_error("a radix from 2 to 36", exp)),
+    }
+}
+
+/// Checks that `cmp` holds for every adjacent pair of arguments, which
+/// must be real.
+fn compare(l: &[Exp], cmp: impl Fn(Ordering) -> bool) -> Result<Exp> {
+    let numbers = l.iter().map(Num::from_real).collect::<Result<Vec<_>>>()?;
+    Ok(Exp::Atom(Atom::Bool(numbers.windows(2).all(|w| w[0].partial_cmp(&w[1]).is_some_and(&cmp)))))
+}
+
+fn car(exp: &Exp) -> Result<Exp> {
+    match exp {
+        Exp::List(list) => Ok(list.first().ok_or(eval_error!("called 'car' with empty list"))?.clone()),
+        Exp::Pair(car, _) => Ok(car.as_ref().clone()),
+        exp => Err(BrewlisError::type_error("a pair", exp)),
+    }
+}
+
+fn cdr(exp: &Exp) -> Result<Exp> {
+    match exp {
+        Exp::List(list) if !list.is_empty() => Ok(Exp::List(list.tail())),
+        Exp::List(_) => Err(eval_error!("called 'cdr' with empty list")),
+        Exp::Pair(_, cdr) => Ok(cdr.as_ref().clone()),
+        exp => Err(BrewlisError::type_error("a pair", exp)),
+    }
+}
+
+/// The order `sort` uses without a predicate: numbers by value, strings,
+/// characters and symbols alphabetically.
+fn default_less(a: &Exp, b: &Exp) -> Result<bool> {
+    match (a, b) {
+        (Exp::Atom(Atom::String(a)), Exp::Atom(Atom::String(b))) => Ok(a < b),
+        (Exp::Atom(Atom::Char(a)), Exp::Atom(Atom::Char(b))) => Ok(a < b),
+        (Exp::Atom(Atom::Symbol(a)), Exp::Atom(Atom::Symbol(b))) => Ok(a.as_str() < b.as_str()),
+        _ => Ok(Num::from_real(a)? < Num::from_real(b)?),
+    }
+}
+
+/// Sorts `items` stably by `less`, which need not be a consistent order
+/// and may fail. Merges runs of doubling length, so `less` is called
+/// O(n log n) times.
+fn merge_sort(items: Vec<Exp>, less: &mut impl FnMut(&Exp, &Exp) -> Result<bool>) -> Result<Vec<Exp>> {
+    let mut items = items;
+    let mut merged = Vec::with_capacity(items.len());
+    let mut width = 1;
+    while width < items.len() {
+        for start in (0..items.len()).step_by(2 * width) {
+            let middle = (start + width).min(items.len());
+            let end = (start + 2 * width).min(items.len());
+            let (mut i, mut j) = (start, middle);
+            while i < middle && j < end {
+                // Taking from the right only if it is less keeps equal elements in order
+                if less(&items[j], &items[i])? {
+                    merged.push(items[j].clone());
+                    j += 1;
+                } else {
+                    merged.push(items[i].clone());
+                    i += 1;
+                }
+            }
+            merged.extend_from_slice(&items[i..middle]);
+            merged.extend_from_slice(&items[j..end]);
+        }
+        std::mem::swap(&mut items, &mut merged);
+        merged.clear();
+        width *= 2;
+    }
+    Ok(items)
+}
+
+/// The pair of `car` and `cdr`, a list if `cdr` is one.
+fn cons(car: Exp, cdr: &Exp) -> Exp {
+    match cdr {
+        Exp::List(list) => {
+            let mut cons = List::with_capacity(list.len() + 1);
+            cons.push(car);
+            cons.extend(list.iter().cloned());
+            Exp::List(cons)
+        },
+        cdr => Exp::Pair(Box::new(car), Box::new(cdr.clone())),
+    }
+}
+
+fn extract_list(exp: &Exp) -> Result<&List> {
+    match exp {
+        Exp::List(list) => Ok(list),
+        _ => Err(BrewlisError::type_error("a list", exp)),
+    }
+}
+
+fn extract_vector(exp: &Exp) -> Result<&Rc<RefCell<Vec<Exp>>>> {
+    match exp {
+        Exp::Vector(vector) => Ok(vector),
+        _ => Err(BrewlisError::type_error("a vector", exp)),
+    }
+}
+
+fn extract_port(exp: &Exp) -> Result<&Rc<Port>> {
+    match exp {
+        Exp::Port(port) => Ok(port),
+        _ => Err(BrewlisError::type_error("a port", exp)),
+    }
+}
+
+/// The port argument of `read-line` and `read-all`, or the current input
+/// port without one. `None` stands for stdin.
+fn input_port(exp: Option<&Exp>) -> Result<Option<Rc<Port>>> {
+    match exp {
+        Some(exp) => Ok(Some(extract_port(exp)?.clone())),
+        None => Ok(port::current_input()),
+    }
+}
+
+fn extract_hash_table(exp: &Exp) -> Result<&Rc<RefCell<HashMap<HashKey, Exp>>>> {
+    match exp {
+        Exp::HashTable(table) => Ok(table),
+        _ => Err(BrewlisError::type_error("a hash table", exp)),
+    }
+}
+
+/// Whether `exp` can be called.
+fn is_procedure(exp: &Exp) -> bool {
+    match exp {
+        Exp::Builtin(name) => lookup_builtin(name.as_str()).is_some_and(|b| !b.special_form),
+        Exp::Lambda(_) | Exp::Closure(_) | Exp::Native(_) | Exp::Continuation(_) => true,
+        _ => false,
+    }
+}
+
+pub(crate) fn proc(proc: &Exp, l: &[Exp], env: &Env) -> Result<Exp>{
+    match proc {
+        Exp::Builtin(procname) => {
+            // The arms below rely on the number of arguments being checked
+            if let Some(builtin) = lookup_builtin(procname).filter(|builtin| !builtin.special_form) {
+                if !builtin.arity.accepts(l.len()) {
+                    return Err(BrewlisError::arity(builtin.name, builtin.arity, l.len()));
+                }
+            }
+            match procname.as_str() {
+                "^" | "expt" => {
+                    let l0 = Num::from_exp(&l[0])?;
+                    let l1 = Num::from_exp(&l[1])?;
+                    Ok(l0.checked_pow(&l1)?.into_exp())
+                },
+                "*" => {
+                    let product = numbers(l)?.into_iter().fold(Num::Int(1), |a, b| a * b);
+                    Ok(product.into_exp())
+                },
+                "/" => {
+                    let (first, rest) = numbers_split(procname, l)?;
+                    // (/ x) is the reciprocal of x
+                    let (mut quotient, divisors) = if rest.is_empty() { (Num::Int(1), vec![first]) } else { (first, rest) };
+                    for divisor in divisors {
+                        quotient = quotient.checked_div(&divisor)?;
+                    }
+                    Ok(quotient.into_exp())
+                },
+                "+" => {
+                    let sum = numbers(l)?.into_iter().fold(Num::Int(0), |a, b| a + b);
+                    Ok(sum.into_exp())
+                }
+                "-" => {
+                    let (first, rest) = numbers_split(procname, l)?;
+                    // (- x) negates x
+                    if rest.is_empty() {
+                        return Ok((-first).into_exp())
+                    }
+                    Ok(rest.into_iter().fold(first, |a, b| a - b).into_exp())
+                }
+                ">" => compare(l, Ordering::is_gt),
+                "<" => compare(l, Ordering::is_lt),
+                ">=" => compare(l, Ordering::is_ge),
+                "<=" => compare(l, Ordering::is_le),
+                "=" => {
+                    // Complex numbers can be equal, but not ordered
+                    let numbers = numbers(l)?;
+                    Ok(Exp::Atom(Atom::Bool(numbers.windows(2).all(|w| w[0] == w[1]))))
+                }
+                "abs" => {
+                    let l0 = Num::from_exp(&l[0])?;
+                    Ok(l0.abs().into_exp())
+                }
+                "exact->inexact" => {
+                    let l0 = Num::from_exp(&l[0])?;
+                    Ok(l0.to_inexact().into_exp())
+                }
+                "sqrt" => Ok(Num::from_exp(&l[0])?.sqrt().into_exp()),
+                "floor" => Ok(Num::from_real(&l[0])?.floor().into_exp()),
+                "ceil" | "ceiling" => Ok(Num::from_real(&l[0])?.ceil().into_exp()),
+                "round" => Ok(Num::from_real(&l[0])?.round().into_exp()),
+                "truncate" => Ok(Num::from_real(&l[0])?.truncate().into_exp()),
+                "min" | "max" => {
+                    let mut result = Num::from_real(&l[0])?;
+                    for exp in &l[1..] {
+                        let n = Num::from_real(exp)?;
+                        let inexact = matches!(result, Num::Float(_)) || matches!(n, Num::Float(_));
+                        let better = if procname == "min" { n < result } else { n > result };
+                        // NaN compares false to everything, but wins
+                        if better || n.partial_cmp(&n).is_none() {
+                            result = n;
+                        }
+                        if inexact {
+                            result = result.to_inexact();
+                        }
+                    }
+                    Ok(result.into_exp())
+                }
+                "quotient" => Ok(Num::from_real(&l[0])?.quotient(&Num::from_real(&l[1])?)?.into_exp()),
+                "gcd" | "lcm" => {
+                    let mut result = BigInt::from(if procname == "gcd" { 0 } else { 1 });
+                    for exp in l {
+                        let n = Num::from_exp(exp)?.to_integer().ok_or(BrewlisError::type_error("an integer", exp))?;
+                        result = if procname == "gcd" { result.gcd(&n) } else { result.lcm(&n) };
+                    }
+                    Ok(Num::from_bigint(result).into_exp())
+                }
+                "even?" | "odd?" => {
+                    let n = Num::from_exp(&l[0])?.to_integer().ok_or(BrewlisError::type_error("an integer", &l[0]))?;
+                    Ok(Exp::Atom(Atom::Bool(n.is_even() == (procname == "even?"))))
+                }
+                "bitwise-and" | "bitwise-or" | "bitwise-xor" => {
+                    // Negative integers act as infinitely sign-extended
+                    // two's complement
+                    let mut result = BigInt::from(if procname == "bitwise-and" { -1 } else { 0 });
+                    for exp in l {
+                        let n = exact_integer(exp)?;
+                        result = match procname.as_str() {
+                            "bitwise-and" => result & n,
+                            "bitwise-or" => result | n,
+                            _ => result ^ n,
+                        };
+                    }
+                    Ok(Num::from_bigint(result).into_exp())
+                }
+                "bitwise-not" => Ok(Num::from_bigint(!exact_integer(&l[0])?).into_exp()),
+                "arithmetic-shift" => {
+                    let n = exact_integer(&l[0])?;
+                    let Exp::Atom(Atom::Int(shift)) = l[1] else {
+                        return Err(BrewlisError::type_error("an integer", &l[1]));
+                    };
+                    let amount = usize::try_from(shift.unsigned_abs()).map_err(|_| eval_error!("Shift amount too large: {}", shift))?;
+                    if shift > 0 && amount > MAX_SHIFT {
+                        return Err(eval_error!("Shift amount too large: {}", shift));
+                    }
+                    // Shifting right rounds down, also for negative integers
+                    let shifted = if shift >= 0 { n << amount } else { n >> amount };
+                    Ok(Num::from_bigint(shifted).into_exp())
+                }
+                "modulo" => Ok(Num::from_real(&l[0])?.modulo(&Num::from_real(&l[1])?)?.into_exp()),
+                "remainder" => Ok(Num::from_real(&l[0])?.remainder(&Num::from_real(&l[1])?)?.into_exp()),
+                "log" => {
+                    let l0 = Num::from_exp(&l[0])?;
+                    match l.get(1) {
+                        Some(base) => Ok(l0.log(&Num::from_exp(base)?).into_exp()),
+                        None => Ok(l0.ln().into_exp()),
+                    }
+                }
+                "log10" => {
+                    let l0 = l[0].clone().extract_number()?;
+                    Ok(Exp::Atom(Atom::Number(l0.log10())))
+                }
+                "atan2" => {
+                    let y = l[0].clone().extract_number()?;
+                    let x = l[1].clone().extract_number()?;
+                    Ok(Exp::Atom(Atom::Number(y.atan2(x))))
+                }
+                "sin" => {
+                    let l0 = l[0].clone().extract_number()?;
+                    Ok(Exp::Atom(Atom::Number(l0.sin())))
+                }
+                "cos" => {
+                    let l0 = l[0].clone().extract_number()?;
+                    Ok(Exp::Atom(Atom::Number(l0.cos())))
+                }
+                "tan" => {
+                    let l0 = l[0].clone().extract_number()?;
+                    Ok(Exp::Atom(Atom::Number(l0.tan())))
+                }
+                "sinh" => {
+                    let l0 = l[0].clone().extract_number()?;
+                    Ok(Exp::Atom(Atom::Number(l0.sinh())))
+                }
+                "cosh" => {
+                    let l0 = l[0].clone().extract_number()?;
+                    Ok(Exp::Atom(Atom::Number(l0.cosh())))
+                }
+                "tanh" => {
+                    let l0 = l[0].clone().extract_number()?;
+                    Ok(Exp::Atom(Atom::Number(l0.tanh())))
+                }
+                "asin" => {
+                    let l0 = l[0].clone().extract_number()?;
+                    Ok(Exp::Atom(Atom::Number(l0.asin())))
+                }
+                "acos" => {
+                    let l0 = l[0].clone().extract_number()?;
+                    Ok(Exp::Atom(Atom::Number(l0.acos())))
+                }
+                "atan" => {
+                    let l0 = l[0].clone().extract_number()?;
+                    match l.get(1) {
+                        Some(x) => Ok(Exp::Atom(Atom::Number(l0.atan2(x.clone().extract_number()?)))),
+                        None => Ok(Exp::Atom(Atom::Number(l0.atan()))),
+                    }
+                }
+                "asinh" => {
+                    let l0 = l[0].clone().extract_number()?;
+                    Ok(Exp::Atom(Atom::Number(l0.asinh())))
+                }
+                "acosh" => {
+                    let l0 = l[0].clone().extract_number()?;
+                    Ok(Exp::Atom(Atom::Number(l0.acosh())))
+                }
+                "atanh" => {
+                    let l0 = l[0].clone().extract_number()?;
+                    Ok(Exp::Atom(Atom::Number(l0.atanh())))
+                }
+                "exp" => {
+                    let l0 = l[0].clone().extract_number()?;
+                    Ok(Exp::Atom(Atom::Number(l0.exp())))
+                }
+                "ln" => Ok(Num::from_exp(&l[0])?.ln().into_exp()),
+                "magnitude" => Ok(Num::from_exp(&l[0])?.magnitude().into_exp()),
+                "angle" => Ok(Num::from_exp(&l[0])?.angle().into_exp()),
+                "real-part" => Ok(Num::from_exp(&l[0])?.real_part().into_exp()),
+                "imag-part" => Ok(Num::from_exp(&l[0])?.imag_part().into_exp()),
+                "make-rectangular" => {
+                    let re = l[0].clone().extract_number()?;
+                    let im = l[1].clone().extract_number()?;
+                    Ok(Num::from_complex(Complex64::new(re, im)).into_exp())
+                }
+                "make-polar" => {
+                    let magnitude = l[0].clone().extract_number()?;
+                    let angle = l[1].clone().extract_number()?;
+                    Ok(Num::from_complex(Complex64::from_polar(magnitude, angle)).into_exp())
+                }
+                "car" => car(&l[0]),
+                "cdr" => cdr(&l[0]),
+                "cons" => Ok(cons(l[0].clone(), &l[1])),
+                "list" => Ok(Exp::List(l.into())),
+                "caar" | "cadr" | "cdar" | "cddr" | "caddr" | "cdddr" | "cadddr" => {
+                    // (cadr x) is (car (cdr x)), the innermost operation comes last
+                    let mut exp = l[0].clone();
+                    for op in procname.as_str()[1..procname.as_str().len() - 1].chars().rev() {
+                        exp = if op == 'a' { car(&exp)? } else { cdr(&exp)? };
+                    }
+                    Ok(exp)
+                }
+                "length" => Ok(Exp::Atom(Atom::Int(extract_list(&l[0])?.len() as i64))),
+                "append" => {
+                    // The last argument is shared and need not be a list
+                    let Some((last, init)) = l.split_last() else {
+                        return Ok(Exp::List(List::new()));
+                    };
+                    let mut items = Vec::new();
+                    for list in init {
+                        items.extend(extract_list(list)?.iter().cloned());
+                    }
+                    if items.is_empty() {
+                        return Ok(last.clone());
+                    }
+                    Ok(match last {
+                        Exp::List(last) => {
+                            items.extend(last.iter().cloned());
+                            Exp::List(items.into())
+                        }
+                        last => items.into_iter().rev().fold(last.clone(), |cdr, car| cons(car, &cdr)),
+                    })
+                }
+                "reverse" => Ok(Exp::List(extract_list(&l[0])?.iter().rev().cloned().collect())),
+                "list-tail" | "list-ref" => {
+                    let list = extract_list(&l[0])?;
+                    let index = l[1].clone().extract_index()?;
+                    if procname == "list-tail" && index <= list.len() {
+                        return Ok(Exp::List(list.tail_from(index)));
+                    }
+                    list.get(index).cloned().ok_or(eval_error!("Index {} out of range for list of length {}", index, list.len()))
+                }
+                "last" => {
+                    let list = extract_list(&l[0])?;
+                    Ok(list.last().ok_or(eval_error!("called 'last' with empty list"))?.clone())
+                }
+                "member" | "memv" | "memq" => {
+                    let same = match procname.as_str() { "member" => equal, "memv" => eqv, _ => eq };
+                    let list = extract_list(&l[1])?;
+                    match list.iter().position(|x| same(&l[0], x)) {
+                        Some(index) => Ok(Exp::List(list.tail_from(index))),
+                        None => Ok(Exp::Atom(Atom::Bool(false))),
+                    }
+                }
+                "assoc" | "assv" | "assq" => {
+                    let same = match procname.as_str() { "assoc" => equal, "assv" => eqv, _ => eq };
+                    for entry in extract_list(&l[1])? {
+                        let key = match entry {
+                            Exp::List(pair) if !pair.is_empty() => &pair[0],
+                            Exp::Pair(car, _) => car.as_ref(),
+                            entry => return Err(BrewlisError::type_error("an association list entry", entry)),
+                        };
+                        if same(&l[0], key) {
+                            return Ok(entry.clone());
+                        }
+                    }
+                    Ok(Exp::Atom(Atom::Bool(false)))
+                }
+                "make-vector" => {
+                    let size = l[0].clone().extract_index()?;
+                    let fill = l.get(1).cloned().unwrap_or(Exp::Atom(Atom::Int(0)));
+                    let mut items = Vec::new();
+                    items.try_reserve_exact(size).map_err(|_| eval_error!("Cannot allocate a vector of length {}", size))?;
+                    items.resize(size, fill);
+                    Ok(Exp::vector(items))
+                }
+                "vector-ref" => {
+                    let vector = extract_vector(&l[0])?;
+                    let index = l[1].clone().extract_index()?;
+                    let vector = vector.borrow();
+                    Ok(vector.get(index).ok_or(eval_error!("Index {} out of range for vector of length {}", index, vector.len()))?.clone())
+                }
+                "vector-set!" => {
+                    let vector = extract_vector(&l[0])?;
+                    let index = l[1].clone().extract_index()?;
+                    let mut vector = vector.borrow_mut();
+                    let len = vector.len();
+                    *vector.get_mut(index).ok_or(eval_error!("Index {} out of range for vector of length {}", index, len))? = l[2].clone();
+                    Ok(Exp::Atom(Atom::Bool(true)))
+                }
+                "vector-length" => {
+                    let len = extract_vector(&l[0])?.borrow().len();
+                    Ok(Exp::Atom(Atom::Int(len as i64)))
+                }
+                "vector->list" => Ok(Exp::List(extract_vector(&l[0])?.borrow().clone().into())),
+                "list->vector" => Ok(Exp::vector(extract_list(&l[0])?.to_vec())),
+                "make-hash" => Ok(Exp::hash_table()),
+                "hash-set!" => {
+                    let key = HashKey::from_exp(&l[1])?;
+                    extract_hash_table(&l[0])?.borrow_mut().insert(key, l[2].clone());
+                    Ok(Exp::Atom(Atom::Bool(true)))
+                }
+                "hash-ref" => {
+                    let key = HashKey::from_exp(&l[1])?;
+                    match extract_hash_table(&l[0])?.borrow().get(&key) {
+                        Some(value) => Ok(value.clone()),
+                        None => l.get(2).cloned().ok_or(eval_error!("No value for key {} in hash table", l[1])),
+                    }
+                }
+                "hash-remove!" => {
+                    let key = HashKey::from_exp(&l[1])?;
+                    extract_hash_table(&l[0])?.borrow_mut().remove(&key);
+                    Ok(Exp::Atom(Atom::Bool(true)))
+                }
+                "hash-keys" => {
+                    let keys = extract_hash_table(&l[0])?.borrow().keys().map(HashKey::to_exp).collect();
+                    Ok(Exp::List(keys))
+                }
+                "json-parse" => json::parse(&l[0].clone().extract_string()?),
+                "json-stringify" => Ok(Exp::Atom(Atom::String(json::stringify(&l[0])?.into()))),
+                "current-seconds" => Ok(time::current_seconds()),
+                "current-time" => Ok(time::current_time()),
+                "time-format" => {
+                    let t = l[0].clone().extract_number()?;
+                    let template = l[1].clone().extract_string()?;
+                    Ok(Exp::Atom(Atom::String(time::format(t, &template)?.into())))
+                }
+                "random" => random::random(l.first()),
+                "random-seed!" => {
+                    let Exp::Atom(Atom::Int(seed)) = l[0] else {
+                        return Err(BrewlisError::type_error("an integer", &l[0]));
+                    };
+                    random::seed(seed as u64);
+                    Ok(Exp::Atom(Atom::Bool(true)))
+                }
+                "char->integer" => {
+                    let c = l[0].clone().extract_char()?;
+                    Ok(Exp::Atom(Atom::Int(c as i64)))
+                }
+                "integer->char" => {
+                    let Exp::Atom(Atom::Int(i)) = l[0] else {
+                        return Err(BrewlisError::type_error("an integer", &l[0]));
+                    };
+                    let c = u32::try_from(i).ok().and_then(char::from_u32).ok_or(eval_error!("Not a valid character code: {}", i))?;
+                    Ok(Exp::Atom(Atom::Char(c)))
+                }
+                "char=?" => {
+                    let chars = l.iter().map(|e| e.clone().extract_char()).collect::<Result<Vec<_>>>()?;
+                    Ok(Exp::Atom(Atom::Bool(chars.windows(2).all(|w| w[0] == w[1]))))
+                }
+                "string-length" => Ok(Exp::Atom(Atom::Int(l[0].clone().extract_string()?.chars().count() as i64))),
+                "substring" => {
+                    // Indices count characters, end defaults to the length
+                    let chars: Vec<char> = l[0].clone().extract_string()?.chars().collect();
+                    let start = l[1].clone().extract_index()?;
+                    let end = l.get(2).map(|e| e.clone().extract_index()).transpose()?.unwrap_or(chars.len());
+                    if start > end || end > chars.len() {
+                        return Err(eval_error!("Substring {} to {} out of range for length {}", start, end, chars.len()));
+                    }
+                    Ok(Exp::Atom(Atom::String(chars[start..end].iter().collect::<String>().into())))
+                }
+                "string-append" => {
+                    let strings = l.iter().map(|e| e.clone().extract_string()).collect::<Result<Vec<_>>>()?;
+                    Ok(Exp::Atom(Atom::String(strings.concat().into())))
+                }
+                "string-upcase" => Ok(Exp::Atom(Atom::String(l[0].clone().extract_string()?.to_uppercase().into()))),
+                "string-downcase" => Ok(Exp::Atom(Atom::String(l[0].clone().extract_string()?.to_lowercase().into()))),
+                "number->string" => {
+                    let number = Num::from_exp(&l[0])?;
+                    let radix = radix(l.get(1))?;
+                    let string = match l.get(2) {
+                        // A precision gives a fixed number of digits after the point
+                        Some(digits) if radix == 10 => {
+                            let digits = digits.clone().extract_index()?;
+                            format!("{:.*}", digits, Num::from_real(&l[0])?.to_f64())
+                        }
+                        Some(_) => return Err(eval_error!("Precision is only supported in radix 10")),
+                        None => number.to_string_radix(radix)?,
+                    };
+                    Ok(Exp::Atom(Atom::String(string.into())))
+                }
+                "string->number" => {
+                    let string = l[0].clone().extract_string()?;
+                    match Num::parse(string.trim(), radix(l.get(1))?) {
+                        Some(number) => Ok(number.into_exp()),
+                        None => Ok(Exp::Atom(Atom::Bool(false))),
+                    }
+                }
+                "string-split" => {
+                    // Without a separator the string is split at whitespace,
+                    // with an empty one into its characters
+                    let string = l[0].clone().extract_string()?;
+                    let parts: Vec<&str> = match l.get(1) {
+                        None => string.split_whitespace().collect(),
+                        Some(Exp::Atom(Atom::Char(c))) => string.split(*c).collect(),
+                        Some(separator) => match &*separator.clone().extract_string()? {
+                            "" => string.char_indices().map(|(i, c)| &string[i..i + c.len_utf8()]).collect(),
+                            separator => string.split(separator).collect(),
+                        },
+                    };
+                    Ok(Exp::List(parts.into_iter().map(|part| Exp::Atom(Atom::String(part.into()))).collect()))
+                }
+                "string-index" => {
+                    let string = l[0].clone().extract_string()?;
+                    let c = l[1].clone().extract_char()?;
+                    match string.chars().position(|x| x == c) {
+                        Some(i) => Ok(Exp::Atom(Atom::Int(i as i64))),
+                        None => Ok(Exp::Atom(Atom::Bool(false))),
+                    }
+                }
+                "string=?" => {
+                    let strings = l.iter().map(|e| e.clone().extract_string()).collect::<Result<Vec<_>>>()?;
+                    Ok(Exp::Atom(Atom::Bool(strings.windows(2).all(|w| w[0] == w[1]))))
+                }
+                "string->list" => {
+                    let string = l[0].clone().extract_string()?;
+                    Ok(Exp::List(string.chars().map(|c| Exp::Atom(Atom::Char(c))).collect()))
+                }
+                "list->string" => {
+                    let chars = extract_list(&l[0])?.iter().map(|e| e.clone().extract_char()).collect::<Result<String>>()?;
+                    Ok(Exp::Atom(Atom::String(chars.into())))
+                }
+                "char->string" => Ok(Exp::Atom(Atom::String(l[0].clone().extract_char()?.to_string().into()))),
+                "symbol->string" => Ok(Exp::Atom(Atom::String(l[0].clone().extract_symbol()?.as_str().into()))),
+                "string->symbol" => Ok(Exp::Atom(Atom::Symbol(Symbol::new(&l[0].clone().extract_string()?)))),
+                "apply" => {
+                    // (apply f a b '(c d)) calls f with a, b, c and d
+                    let (f, rest) = l.split_first().ok_or(eval_error!("called 'apply' without a procedure"))?;
+                    let (list, init) = rest.split_last().ok_or(eval_error!("called 'apply' without an argument list"))?;
+                    let list = extract_list(list)?;
+                    let mut args = init.to_vec();
+                    args.extend(list.iter().cloned());
+                    self::proc(f, &args, env)
+                }
+                "curry" => {
+                    // ((curry f a b) c d) calls f with a, b, c and d
+                    let (f, args) = (l[0].clone(), l[1..].to_vec());
+                    let env = env.clone();
+                    Ok(Exp::native("curry", move |rest| {
+                        let mut all = args.clone();
+                        all.extend(rest.iter().cloned());
+                        self::proc(&f, &all, &env)
+                    }))
+                }
+                "map" | "for-each" => {
+                    // With several lists f is called with one element of each,
+                    // up to the length of the shortest list
+                    let (f, lists) = l.split_first().ok_or(eval_error!("called '{}' without a procedure", procname))?;
+                    let lists = lists.iter().map(extract_list).collect::<Result<Vec<_>>>()?;
+                    let len = lists.iter().map(|list| list.len()).min().ok_or(eval_error!("called '{}' without a list", procname))?;
+                    let results = (0..len).map(|i| {
+                        let args: List = lists.iter().map(|list| list[i].clone()).collect();
+                        self::proc(f, &args, env)
+                    }).collect::<Result<List>>()?;
+                    if procname == "for-each" {
+                        return Ok(Exp::Atom(Atom::Bool(true)))
+                    }
+                    Ok(Exp::List(results))
+                }
+                "filter" => {
+                    let mut results = List::new();
+                    for x in extract_list(&l[1])? {
+                        if is_true(&self::proc(&l[0], std::slice::from_ref(x), env)?) {
+                            results.push(x.clone());
+                        }
+                    }
+                    Ok(Exp::List(results))
+                }
+                "sort" => {
+                    let mut less = |a: &Exp, b: &Exp| match l.get(1) {
+                        Some(less) => Ok(is_true(&self::proc(less, &[a.clone(), b.clone()], env)?)),
+                        None => default_less(a, b),
+                    };
+                    match &l[0] {
+                        Exp::Vector(vector) => {
+                            let items = vector.borrow().clone();
+                            Ok(Exp::vector(merge_sort(items, &mut less)?))
+                        }
+                        list => Ok(Exp::List(merge_sort(extract_list(list)?.to_vec(), &mut less)?.into())),
+                    }
+                }
+                "fold-left" => {
+                    // (f acc x) from the first element to the last
+                    let mut acc = l[1].clone();
+                    for x in extract_list(&l[2])? {
+                        acc = self::proc(&l[0], &[acc, x.clone()], env)?;
+                    }
+                    Ok(acc)
+                }
+                "fold-right" => {
+                    // (f x acc) from the last element to the first
+                    let mut acc = l[1].clone();
+                    for x in extract_list(&l[2])?.iter().rev() {
+                        acc = self::proc(&l[0], &[x.clone(), acc], env)?;
+                    }
+                    Ok(acc)
+                }
+                "eval" => {
+                    match l.get(1) {
+                        None => eval(&l[0], env),
+                        Some(Exp::Environment(target)) => eval(&l[0], target),
+                        Some(exp) => Err(BrewlisError::type_error("an environment", exp)),
+                    }
+                }
+                "interaction-environment" => Ok(Exp::Environment(env.clone())),
+                "load" => load(&l[0].clone().extract_string()?, env),
+                "load-extension" => plugin::load_extension(&l[0].clone().extract_string()?, env),
+                "collect-garbage" => Ok(Exp::Atom(Atom::Int(gc::collect() as i64))),
+                "error" => {
+                    let (message, irritants) = l.split_first().ok_or(eval_error!("called 'error' without a message"))?;
+                    let message = message.clone().extract_string()?;
+                    let error = ErrorObject { message: message.to_string(), irritants: irritants.into() };
+                    Err(BrewlisError::Raised { value: Exp::Error(Rc::new(error)), span: None })
+                }
+                "raise" => Err(BrewlisError::Raised { value: l[0].clone(), span: None }),
+                "error-object?" => Ok(Exp::Atom(Atom::Bool(matches!(l[0], Exp::Error(_))))),
+                "error-object-message" => {
+                    let Exp::Error(error) = &l[0] else {
+                        return Err(BrewlisError::type_error("an error object", &l[0]));
+                    };
+                    Ok(Exp::Atom(Atom::String(error.message.as_str().into())))
+                }
+                "error-object-irritants" => {
+                    let Exp::Error(error) = &l[0] else {
+                        return Err(BrewlisError::type_error("an error object", &l[0]));
+                    };
+                    Ok(Exp::List(error.irritants.clone()))
+                }
+                "call/cc" | "call-with-current-continuation" => {
+                    let continuation = next_continuation();
+                    match self::proc(&l[0], &[Exp::Continuation(continuation)], env) {
+                        Err(err) => match err.root() {
+                            BrewlisError::Escape { continuation: escaped, value } if *escaped == continuation => Ok(value.clone()),
+                            _ => Err(err),
+                        },
+                        result => result,
+                    }
+                }
+                "values" => match l {
+                    [value] => Ok(value.clone()),
+                    _ => Ok(Exp::Values(l.into())),
+                }
+                "call-with-values" => {
+                    let args = values_list(self::proc(&l[0], &[], env)?);
+                    self::proc(&l[1], &args, env)
+                }
+                "force" => {
+                    let Exp::Promise(promise) = &l[0] else {
+                        return Ok(l[0].clone())
+                    };
+                    let (exp, delayed_env) = match &*promise.borrow() {
+                        Promise::Forced(value) => return Ok(value.clone()),
+                        Promise::Delayed(exp, env) => (exp.clone(), env.clone()),
+                    };
+                    let value = eval(&exp, &delayed_env)?;
+                    // Forcing the promise from within its own expression may
+                    // have forced it already, that first value is kept
+                    if let Promise::Forced(value) = &*promise.borrow() {
+                        return Ok(value.clone())
+                    }
+                    *promise.borrow_mut() = Promise::Forced(value.clone());
+                    Ok(value)
+                }
+                "promise?" => Ok(Exp::Atom(Atom::Bool(matches!(l[0], Exp::Promise(_))))),
+                "number?" => Ok(Exp::Atom(Atom::Bool(Num::from_exp(&l[0]).is_ok()))),
+                "integer?" => Ok(Exp::Atom(Atom::Bool(Num::from_exp(&l[0]).is_ok_and(|n| n.to_integer().is_some())))),
+                "symbol?" => Ok(Exp::Atom(Atom::Bool(matches!(l[0], Exp::Atom(Atom::Symbol(_)))))),
+                "string?" => Ok(Exp::Atom(Atom::Bool(matches!(l[0], Exp::Atom(Atom::String(_)))))),
+                "boolean?" => Ok(Exp::Atom(Atom::Bool(matches!(l[0], Exp::Atom(Atom::Bool(_)))))),
+                "char?" => Ok(Exp::Atom(Atom::Bool(matches!(l[0], Exp::Atom(Atom::Char(_)))))),
+                "keyword?" => Ok(Exp::Atom(Atom::Bool(matches!(l[0], Exp::Atom(Atom::Keyword(_)))))),
+                "vector?" => Ok(Exp::Atom(Atom::Bool(matches!(l[0], Exp::Vector(_))))),
+                "list?" => Ok(Exp::Atom(Atom::Bool(matches!(l[0], Exp::List(_))))),
+                "pair?" => Ok(Exp::Atom(Atom::Bool(matches!(&l[0], Exp::Pair(..)) || matches!(&l[0], Exp::List(list) if !list.is_empty())))),
+                "null?" => Ok(Exp::Atom(Atom::Bool(matches!(&l[0], Exp::List(list) if list.is_empty())))),
+                "procedure?" => Ok(Exp::Atom(Atom::Bool(is_procedure(&l[0])))),
+                "not" => Ok(Exp::Atom(Atom::Bool(!is_true(&l[0])))),
+                "eq?" => Ok(Exp::Atom(Atom::Bool(eq(&l[0], &l[1])))),
+                "eqv?" => Ok(Exp::Atom(Atom::Bool(eqv(&l[0], &l[1])))),
+                "equal?" => Ok(Exp::Atom(Atom::Bool(equal(&l[0], &l[1])))),
+                "assert-equal" => {
+                    if !equal(&l[0], &l[1]) {
+                        return Err(eval_error!("assert-equal failed: expected {} but got {}", l[0], l[1]));
+                    }
+                    Ok(Exp::Atom(Atom::Bool(true)))
+                }
+                // The VM compiles (time exp) to a call with (lambda () exp)
+                "time" => match l {
+                    [thunk] if is_procedure(thunk) => time::timed(|| self::proc(thunk, &[], env), write_output),
+                    [thunk] => Err(BrewlisError::type_error("a procedure", thunk)),
+                    _ => Err(BrewlisError::arity("time", Arity::Exactly(1), l.len())),
+                },
+                "run-tests" => Ok(Exp::Atom(Atom::Bool(testing::run_all(env, write_output)?))),
+                "display" | "write" => {
+                    if procname == "display" {
+                        write_output(&Displayed(&l[0]).to_string())?;
+                    } else {
+                        write_output(&l[0].to_string())?;
+                    }
+                    Ok(Exp::Atom(Atom::Bool(true)))
+                }
+                "format" => {
+                    // (format #t ...) prints, (format #f ...) and
+                    // (format "..." ...) return the string
+                    let (destination, args) = match &l[0] {
+                        Exp::Atom(Atom::Bool(print)) => (Some(*print), &l[1..]),
+                        _ => (None, l),
+                    };
+                    let (template, args) = args.split_first().ok_or(eval_error!("called 'format' without a format string"))?;
+                    let formatted = format_string(&template.clone().extract_string()?, args)?;
+                    if destination == Some(true) {
+                        write_output(&formatted)?;
+                        return Ok(Exp::Atom(Atom::Bool(true)));
+                    }
+                    Ok(Exp::Atom(Atom::String(formatted.into())))
+                }
+                "read" => read_stdin(),
+                "open-input-file" => Ok(Exp::Port(Port::open_input(&l[0].clone().extract_string()?)?)),
+                "open-output-file" => Ok(Exp::Port(Port::open_output(&l[0].clone().extract_string()?)?)),
+                "read-line" => {
+                    let line = match input_port(l.first())? {
+                        Some(port) => port.read_line()?,
+                        None => read_line_stdin()?,
+                    };
+                    Ok(line.map_or(Exp::Eof, |line| Exp::Atom(Atom::String(line.into()))))
+                }
+                "read-all" => {
+                    let text = match input_port(l.first())? {
+                        Some(port) => port.read_all()?,
+                        None => read_all_stdin()?,
+                    };
+                    Ok(Exp::Atom(Atom::String(text.into())))
+                }
+                "write-string" => {
+                    let text = l[0].clone().extract_string()?;
+                    match l.get(1) {
+                        Some(port) => extract_port(port)?.write_str(&text)?,
+                        None => write_output(&text)?,
+                    }
+                    Ok(Exp::Atom(Atom::Bool(true)))
+                }
+                "close-port" => {
+                    extract_port(&l[0])?.close()?;
+                    Ok(Exp::Atom(Atom::Bool(true)))
+                }
+                "with-input-from-file" => {
+                    let port = Port::open_input(&l[0].clone().extract_string()?)?;
+                    port::with_input(port, || self::proc(&l[1], &[], env))
+                }
+                "input-port?" => Ok(Exp::Atom(Atom::Bool(matches!(&l[0], Exp::Port(port) if port.is_input())))),
+                "output-port?" => Ok(Exp::Atom(Atom::Bool(matches!(&l[0], Exp::Port(port) if !port.is_input())))),
+                "command-line-arguments" => Ok(Exp::List(process::arguments().into_iter().map(|arg| Exp::Atom(Atom::String(arg.into()))).collect())),
+                "exit" => {
+                    // (exit #t) succeeds and (exit #f) fails like in R7RS
+                    let code = match l.first() {
+                        None | Some(Exp::Atom(Atom::Bool(true))) => 0,
+                        Some(Exp::Atom(Atom::Bool(false))) => 1,
+                        Some(Exp::Atom(Atom::Int(code))) => i32::try_from(*code).map_err(|_| eval_error!("Exit status out of range: {}", code))?,
+                        Some(exp) => return Err(BrewlisError::type_error("an exit status", exp)),
+                    };
+                    Err(BrewlisError::Exit { code })
+                }
+                "system" => Ok(Exp::Atom(Atom::Int(process::system(&l[0].clone().extract_string()?)?))),
+                "process-output" => Ok(Exp::Atom(Atom::String(process::output(&l[0].clone().extract_string()?)?.into()))),
+                "getenv" => match std::env::var(&*l[0].clone().extract_string()?) {
+                    Ok(value) => Ok(Exp::Atom(Atom::String(value.into()))),
+                    Err(_) => Ok(Exp::Atom(Atom::Bool(false))),
+                },
+                "setenv" => {
+                    // set_var panics on names and values it cannot pass on
+                    let name = l[0].clone().extract_string()?;
+                    if name.is_empty() || name.contains(['=', '\0']) {
+                        return Err(eval_error!("Invalid environment variable name: {:?}", name));
+                    }
+                    match &l[1] {
+                        Exp::Atom(Atom::Bool(false)) => std::env::remove_var(&*name),
+                        value => {
+                            let value = value.clone().extract_string()?;
+                            if value.contains('\0') {
+                                return Err(eval_error!("Invalid environment variable value: {:?}", value));
+                            }
+                            std::env::set_var(&*name, &*value)
+                        }
+                    }
+                    Ok(Exp::Atom(Atom::Bool(true)))
+                }
+                "eof-object" => Ok(Exp::Eof),
+                "eof-object?" => Ok(Exp::Atom(Atom::Bool(matches!(l[0], Exp::Eof)))),
+                "read-string" => {
+                    let text = l[0].clone().extract_string()?;
+                    let (exp, _) = parse_first(&text)?.ok_or(eval_error!("called 'read-string' without an expression: {:?}", text))?;
+                    Ok(exp)
+                }
+                "newline" => {
+                    write_output("\n")?;
+                    Ok(Exp::Atom(Atom::Bool(true)))
+                }
+                _ => Err(eval_error!("Cannot call the special form '{}'", procname)),
+            }
+        },
+        Exp::Lambda(lambda) => traced_call(proc, None, l, || call_lambda(lambda, l)),
+        Exp::Closure(closure) => traced_call(proc, closure.name(), l, || vm::call(closure, l)),
+        Exp::Native(native) => (native.function)(l),
+        Exp::Continuation(continuation) => {
+            let value = l.first().cloned().unwrap_or(Exp::Atom(Atom::Bool(true)));
+            Err(BrewlisError::Escape { continuation: *continuation, value })
+        }
+        _ => Err(BrewlisError::type_error("a procedure", proc)),
+    }
+}
+
+thread_local! {
+    static CONTINUATIONS: Cell<u64> = const { Cell::new(0) };
+    /// Text read from stdin by `read` after the form it returned.
+    static INPUT: RefCell<String> = const { RefCell::new(String::new()) };
+    /// What `display` and the like printed while output is captured.
+    static OUTPUT: RefCell<Option<String>> = const { RefCell::new(None) };
+}
+
+/// Writes `text` to stdout, or to the captured output.
+fn write_output(text: &str) -> Result<()> {
+    let captured = OUTPUT.with(|output| output.borrow_mut().as_mut().map(|output| output.push_str(text)).is_some());
+    if !captured {
+        print!("{}", text);
+        // The REPL prompt would otherwise come first
+        stdout().flush().map_err(|err| eval_error!("Cannot write to stdout: {}", err))?;
+    }
+    Ok(())
+}
+
+/// Runs `f` and returns its result with what it printed, which is not
+/// written to stdout.
+pub fn capture_output<T>(f: impl FnOnce() -> T) -> (T, String) {
+    let outer = OUTPUT.with(|output| output.replace(Some(String::new())));
+    let result = f();
+    let captured = OUTPUT.with(|output| output.replace(outer)).unwrap_or_default();
+    (result, captured)
+}
+
+/// The next line of stdin without its line break, starting with what
+/// `read` left over, `None` at the end of the input.
+fn read_line_stdin() -> Result<Option<String>> {
+    let mut line = INPUT.with(|input| std::mem::take(&mut *input.borrow_mut()));
+    if !line.contains('\n') && stdin().read_line(&mut line).map_err(|err| eval_error!("Cannot read from stdin: {}", err))? == 0 && line.is_empty() {
+        return Ok(None);
+    }
+    if let Some(end) = line.find('\n') {
+        INPUT.with(|input| input.borrow_mut().push_str(&line[end + 1..]));
+        line.truncate(end);
+    }
+    if line.ends_with('\r') {
+        line.pop();
+    }
+    Ok(Some(line))
+}
+
+/// The rest of stdin, starting with what `read` left over.
+fn read_all_stdin() -> Result<String> {
+    let mut text = INPUT.with(|input| std::mem::take(&mut *input.borrow_mut()));
+    stdin().read_to_string(&mut text).map_err(|err| eval_error!("Cannot read from stdin: {}", err))?;
+    Ok(text)
+}
+
+/// Reads the next form from stdin, reading more lines until it is complete.
+fn read_stdin() -> Result<Exp> {
+    INPUT.with(|input| {
+        let mut input = input.borrow_mut();
+        loop {
+            if open_lists(&input) == 0 {
+                match parse_first(&input) {
+                    Ok(Some((exp, end))) => {
+                        input.drain(..end);
+                        return Ok(exp);
+                    },
+                    Ok(None) => {},
+                    Err(err) => {
+                        // The rest of the erroneous input is skipped
+                        input.clear();
+                        return Err(err);
+                    },
+                }
+            }
+            let mut line = String::new();
+            if stdin().read_line(&mut line).map_err(|err| eval_error!("Cannot read from stdin: {}", err))? == 0 {
+                input.clear();
+                return Err(eval_error!("called 'read' at the end of the input"));
+            }
+            input.push_str(&line);
+        }
+    })
+}
+
+fn next_continuation() -> u64 {
+    CONTINUATIONS.with(|count| {
+        count.set(count.get() + 1);
+        count.get()
+    })
+}
+
+/// Formats `args` by the directives of `template`: ~a displays the next
+/// argument, ~s writes it, ~d, ~b, ~o and ~x write an integer in base 10,
+/// 2, 8 and 16, ~n is a line break and ~~ a tilde.
+fn format_string(template: &str, args: &[Exp]) -> Result<String> {
+    let mut result = String::with_capacity(template.len());
+    let mut args = args.iter();
+    let mut chars = template.chars();
+    while let Some(c) = chars.next() {
+        if c != '~' {
+            result.push(c);
+            continue;
+        }
+        let directive = chars.next().ok_or(eval_error!("Incomplete directive at the end of format string {:?}", template))?;
+        let mut next_arg = || args.next().ok_or(eval_error!("Too few arguments for format string {:?}", template));
+        match directive.to_ascii_lowercase() {
+            'a' => result.push_str(&Displayed(next_arg()?).to_string()),
+            's' => result.push_str(&next_arg()?.to_string()),
+            'd' => match next_arg()? {
+                exp @ Exp::Atom(Atom::Int(_) | Atom::BigInt(_) | Atom::Rational(_) | Atom::Number(_)) => result.push_str(&exp.to_string()),
+                exp => return Err(BrewlisError::type_error("a number", exp)),
+            },
+            radix @ ('b' | 'o' | 'x') => {
+                let radix = match radix { 'b' => 2, 'o' => 8, _ => 16 };
+                match next_arg()? {
+                    Exp::Atom(Atom::Int(i)) => result.push_str(&BigInt::from(*i).to_str_radix(radix)),
+                    Exp::Atom(Atom::BigInt(i)) => result.push_str(&i.to_str_radix(radix)),
+                    exp => return Err(BrewlisError::type_error("an integer", exp)),
+                }
+            },
+            'n' => result.push('\n'),
+            '~' => result.push('~'),
+            c => return Err(eval_error!("Unknown format directive ~{}", c)),
+        }
+    }
+    if args.next().is_some() {
+        return Err(eval_error!("Too many arguments for format string {:?}", template));
+    }
+    Ok(result)
+}
+
+/// The name of `lambda` in errors.
+fn lambda_name(lambda: &Lambda) -> String {
+    lambda.name.map_or("lambda".to_string(), |name| name.to_string())
+}
+
+/// Splits `args` into the positional arguments and the values of the
+/// keyword arguments of `lambda`, in the order of its keyword parameters.
+fn keyword_arguments(lambda: &Lambda, args: &[Exp]) -> Result<(Vec<Exp>, Vec<Option<Exp>>)> {
+    let keywords = &lambda.params.keywords;
+    let mut positional = Vec::with_capacity(args.len());
+    let mut values = vec![None; keywords.len()];
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let Exp::Atom(Atom::Keyword(keyword)) = arg else {
+            positional.push(arg.clone());
+            continue;
+        };
+        let i = keywords.iter().position(|(k, _, _)| k == keyword)
+            .ok_or(eval_error!("'{}' does not take the keyword #:{}", lambda_name(lambda), keyword))?;
+        let value = args.next().ok_or(eval_error!("called '{}' without a value for #:{}", lambda_name(lambda), keyword))?;
+        if values[i].replace(value.clone()).is_some() {
+            return Err(eval_error!("called '{}' with #:{} twice", lambda_name(lambda), keyword));
+        }
+    }
+    Ok((positional, values))
+}
+
+/// Builds the environment a call to `lambda` with `args` is evaluated in.
+fn bind_lambda(lambda: &Lambda, args: &[Exp]) -> Result<Env> {
+    let params = &lambda.params;
+    let positional;
+    let mut keyword_values = Vec::new();
+    let args = match params.keywords.is_empty() {
+        true => args,
+        false => {
+            (positional, keyword_values) = keyword_arguments(lambda, args)?;
+            &positional
+        },
+    };
+    let arity = params.arity();
+    if !arity.accepts(args.len()) {
+        return Err(BrewlisError::arity(&lambda_name(lambda), arity, args.len()));
+    }
+    let env = lambda.env.extend();
+    let (required, rest) = args.split_at(params.required.len());
+    params.required.iter().zip(required).for_each(|(param, value)| {
+        env.define(*param, value.clone());
+    });
+    // Defaults see the parameters before them
+    for (i, (param, default)) in params.optional.iter().enumerate() {
+        let value = match rest.get(i) {
+            Some(value) => value.clone(),
+            None => eval(default, &env)?,
+        };
+        env.define(*param, value);
+    }
+    if let Some(param) = params.rest {
+        let rest = rest.get(params.optional.len()..).unwrap_or_default();
+        env.define(param, Exp::List(rest.into()));
+    }
+    for (i, (keyword, param, default)) in params.keywords.iter().enumerate() {
+        let value = keyword_values[i].take();
+        let value = match (value, default) {
+            (Some(value), _) => value,
+            (None, Some(default)) => eval(default, &env)?,
+            (None, None) => return Err(eval_error!("called '{}' without #:{}", lambda_name(lambda), keyword)),
+        };
+        env.define(*param, value);
+    }
+    Ok(env)
+}
+
+fn call_lambda(lambda: &Lambda, args: &[Exp]) -> Result<Exp> {
+    let env = bind_lambda(lambda, args)?;
+    let mut result = Exp::Atom(Atom::Bool(true));
+    for exp in &lambda.body {
+        result = eval(exp, &env)?;
+    }
+    Ok(result)
+}
+
+/// Calls `f`, the call of `procedure` with `args`, and writes the call and
+/// its value if the procedure is traced. `operator` is the name it was
+/// called by.
+fn traced_call(procedure: &Exp, operator: Option<Symbol>, args: &[Exp], f: impl FnOnce() -> Result<Exp>) -> Result<Exp> {
+    let Some(name) = trace::traced_name(procedure, operator) else {
+        return f();
+    };
+    write_output(&trace::enter(&name, args))?;
+    match f() {
+        Ok(value) => {
+            write_output(&trace::leave(&value))?;
+            Ok(value)
+        },
+        Err(err) => {
+            trace::unwind();
+            Err(err)
+        },
+    }
+}
+
+/// Whether `exp` counts as true in conditionals: everything except `#f` does.
+pub(crate) fn is_true(exp: &Exp) -> bool {
+    *exp != Exp::Atom(Atom::Bool(false))
+}
+
+fn unquoted<'a>(exp: &'a Exp, name: &str) -> Option<&'a Exp> {
+    match exp {
+        Exp::List(l) if l.len() == 2 && matches!(&l[0], Exp::Atom(Atom::Symbol(s)) if s == name) => Some(&l[1]),
+        _ => None,
+    }
+}
+
+/// The separate values of the result of `values`.
+fn values_list(exp: Exp) -> List {
+    match exp {
+        Exp::Values(values) => values,
+        exp => vec![exp].into(),
+    }
+}
+
+/// Builds the value of `template`, evaluating the parts unquoted at `depth`.
+/// Nested quasiquotes increase the depth, their unquotes are kept as they are.
+fn quasiquote(template: &Exp, depth: usize, env: &Env) -> Result<Exp> {
+    let nested = |name: &str, exp: &Exp, depth: usize, env: &Env| -> Result<Exp> {
+        Ok(Exp::List(vec![Exp::Atom(Atom::Symbol(Symbol::new(name))), quasiquote(exp, depth, env)?].into()))
+    };
+    if let Some(exp) = unquoted(template, "unquote") {
+        return if depth == 1 { eval(exp, env) } else { nested("unquote", exp, depth - 1, env) };
+    }
+    if let Some(exp) = unquoted(template, "quasiquote") {
+        return nested("quasiquote", exp, depth + 1, env);
+    }
+    let elements = |l: &[Exp], env: &Env| -> Result<Vec<Exp>> {
+        let mut result = Vec::new();
+        for element in l {
+            match unquoted(element, "unquote-splicing") {
+                Some(exp) if depth == 1 => result.extend(extract_list(&eval(exp, env)?)?.iter().cloned()),
+                Some(exp) => result.push(nested("unquote-splicing", exp, depth - 1, env)?),
+                None => result.push(quasiquote(element, depth, env)?),
+            }
+        }
+        Ok(result)
+    };
+    // The elements followed by the tail after a dot
+    let improper = |mut elements: Vec<Exp>, tail: Exp| match tail {
+        Exp::List(tail) => {
+            elements.extend(tail.iter().cloned());
+            Exp::List(elements.into())
+        },
+        tail => elements.into_iter().rev().fold(tail, |cdr, car| Exp::Pair(Box::new(car), Box::new(cdr))),
+    };
+    match template {
+        // `(x . ,y) reads as (x unquote y)
+        Exp::List(l) if l.len() > 2 && matches!(&l[l.len() - 2], Exp::Atom(Atom::Symbol(s)) if s == "unquote") => {
+            let (l, tail) = l.split_at(l.len() - 2);
+            Ok(improper(elements(l, env)?, quasiquote(&Exp::List(tail.into()), depth, env)?))
+        },
+        Exp::List(l) => Ok(Exp::List(elements(l, env)?.into())),
+        Exp::Pair(..) => {
+            let (l, tail) = template.improper_list().expect("a pair");
+            Ok(improper(elements(&l, env)?, quasiquote(tail.expect("the tail of a pair"), depth, env)?))
+        },
+        Exp::Vector(v) => {
+            let v = v.borrow().clone();
+            Ok(Exp::vector(elements(&v, env)?))
+        },
+        _ => Ok(template.clone()),
+    }
+}
+
+/// Compares by identity: symbols, booleans, characters and numbers stored
+/// inline by value, strings, big numbers and all other objects by whether
+/// they are the same object. Lists are never modified, so a list is the
+/// same as another sharing its elements.
+fn eq(a: &Exp, b: &Exp) -> bool {
+    match (a, b) {
+        (Exp::Atom(Atom::String(a)), Exp::Atom(Atom::String(b))) => Rc::ptr_eq(a, b),
+        (Exp::Atom(Atom::BigInt(a)), Exp::Atom(Atom::BigInt(b))) => Rc::ptr_eq(a, b),
+        (Exp::Atom(Atom::Rational(a)), Exp::Atom(Atom::Rational(b))) => Rc::ptr_eq(a, b),
+        (Exp::List(a), Exp::List(b)) => a.len() == b.len() && (a.is_empty() || std::ptr::eq(a.as_ptr(), b.as_ptr())),
+        _ => eqv(a, b),
+    }
+}
+
+/// Compares atoms by value, other objects by identity. Numbers are only the
+/// same if both are exact or both inexact, and floats if they have the same
+/// bits, so `0.0` and `-0.0` differ but NaN is the same as itself.
+fn eqv(a: &Exp, b: &Exp) -> bool {
+    match (a, b) {
+        (Exp::Atom(Atom::Number(a)), Exp::Atom(Atom::Number(b))) => a.to_bits() == b.to_bits(),
+        (Exp::Atom(Atom::Complex(a)), Exp::Atom(Atom::Complex(b))) => a.re.to_bits() == b.re.to_bits() && a.im.to_bits() == b.im.to_bits(),
+        (Exp::Atom(a), Exp::Atom(b)) => a == b,
+        (Exp::List(a), Exp::List(b)) => a.is_empty() && b.is_empty(),
+        (Exp::Vector(a), Exp::Vector(b)) => Rc::ptr_eq(a, b),
+        (Exp::HashTable(a), Exp::HashTable(b)) => Rc::ptr_eq(a, b),
+        (Exp::Lambda(a), Exp::Lambda(b)) => Rc::ptr_eq(a, b),
+        (Exp::Closure(a), Exp::Closure(b)) => Rc::ptr_eq(a, b),
+        (Exp::Native(a), Exp::Native(b)) => Rc::ptr_eq(a, b),
+        (Exp::Builtin(a), Exp::Builtin(b)) => a == b,
+        (Exp::Promise(a), Exp::Promise(b)) => Rc::ptr_eq(a, b),
+        (Exp::Port(a), Exp::Port(b)) => Rc::ptr_eq(a, b),
+        (Exp::Eof, Exp::Eof) => true,
+        _ => false,
+    }
+}
+
+/// Compares lists, pairs, vectors, hash tables and strings by their
+/// contents, everything else like `eqv`. Large structures are compared
+/// without recursion, but comparing two structures containing themselves
+/// does not terminate.
+fn equal(a: &Exp, b: &Exp) -> bool {
+    let mut pending = vec![(a.clone(), b.clone())];
+    while let Some((a, b)) = pending.pop() {
+        match (&a, &b) {
+            (Exp::List(x), Exp::List(y)) => {
+                if x.len() != y.len() {
+                    return false;
+                }
+                pending.extend(x.iter().cloned().zip(y.iter().cloned()));
+            }
+            (Exp::Pair(car_a, cdr_a), Exp::Pair(car_b, cdr_b)) => {
+                pending.push((Exp::clone(car_a), Exp::clone(car_b)));
+                pending.push((Exp::clone(cdr_a), Exp::clone(cdr_b)));
+            }
+            (Exp::Vector(x), Exp::Vector(y)) if !Rc::ptr_eq(x, y) => {
+                let (x, y) = (x.borrow(), y.borrow());
+                if x.len() != y.len() {
+                    return false;
+                }
+                pending.extend(x.iter().cloned().zip(y.iter().cloned()));
+            }
+            (Exp::HashTable(x), Exp::HashTable(y)) if !Rc::ptr_eq(x, y) => {
+                let (x, y) = (x.borrow(), y.borrow());
+                if x.len() != y.len() {
+                    return false;
+                }
+                for (key, value) in x.iter() {
+                    let Some(other) = y.get(key) else {
+                        return false;
+                    };
+                    pending.push((value.clone(), other.clone()));
+                }
+            }
+            (Exp::Atom(Atom::String(x)), Exp::Atom(Atom::String(y))) => {
+                if x != y {
+                    return false;
+                }
+            }
+            _ => {
+                if !eqv(&a, &b) {
+                    return false;
+                }
+            }
+        }
+    }
+    true
+}
+
+/// Splits the `((sym init) ...)` bindings of a `let` form.
+fn parse_bindings(bindings: &Exp) -> Result<Vec<(Symbol, Exp)>> {
+    let Exp::List(bindings) = bindings else {
+        return Err(eval_error!("Not a binding list: {}", bindings));
+    };
+    bindings.iter().map(|binding| {
+        match binding {
+            Exp::List(binding) if binding.len() == 2 => {
+                Ok((binding[0].clone().extract_symbol()?, binding[1].clone()))
+            },
+            _ => Err(eval_error!("Not a binding: {}", binding)),
+        }
+    }).collect()
+}
+
+/// Reads the parameter list `exps` of a procedure.
+fn parse_params(exps: &[Exp], rest: Option<&Exp>) -> Result<Params> {
+    let mut params = Params { rest: rest.map(|rest| rest.clone().extract_symbol()).transpose()?, ..Params::default() };
+    let mut exps = exps.iter();
+    while let Some(exp) = exps.next() {
+        match exp {
+            // #:keyword param or #:keyword (param default)
+            Exp::Atom(Atom::Keyword(keyword)) => {
+                let param = exps.next().ok_or(eval_error!("Error in parameter list: No parameter after #:{}", keyword))?;
+                let (param, default) = match param {
+                    Exp::List(l) if l.len() == 2 => (l[0].clone().extract_symbol()?, Some(l[1].clone())),
+                    param => (param.clone().extract_symbol()?, None),
+                };
+                params.keywords.push((*keyword, param, default));
+            },
+            Exp::Atom(Atom::Symbol(param)) if params.optional.is_empty() => params.required.push(*param),
+            Exp::Atom(Atom::Symbol(param)) => return Err(eval_error!("Error in parameter list: Required parameter {} after optional ones", param)),
+            // (param default)
+            Exp::List(l) if l.len() == 2 => params.optional.push((l[0].clone().extract_symbol()?, l[1].clone())),
+            exp => return Err(BrewlisError::type_error("a parameter", exp)),
+        }
+    }
+    Ok(params)
+}
+
+/// The documentation string of `value` if it is a procedure written in
+/// brewlis.
+fn lambda_doc(value: &Exp) -> Option<Rc<str>> {
+    match value {
+        Exp::Lambda(lambda) | Exp::Macro(lambda) => lambda.doc.clone(),
+        _ => None,
+    }
+}
+
+/// The documentation string starting `body`, a string followed by more
+/// forms.
+pub(crate) fn docstring(body: &[Exp]) -> Option<Rc<str>> {
+    match body {
+        [Exp::Atom(Atom::String(doc)), _, ..] => Some(doc.clone()),
+        _ => None,
+    }
+}
+
+fn make_lambda(name: Option<Symbol>, l: &[Exp], env: &Env) -> Result<Exp> {
+    // (lambda (x y) "doc" body...), (lambda (x . rest) body...) or
+    // (lambda args body...)
+    let params = l.get(1).ok_or(eval_error!("Error in lambda expression: No parameters"))?;
+    make_procedure(name, params, &l[2..], env)
+}
+
+/// Creates the procedure `name` with the parameter list `params`, a list,
+/// an improper list ending in the rest parameter, or only the rest
+/// parameter.
+fn make_procedure(name: Option<Symbol>, params: &Exp, body: &[Exp], env: &Env) -> Result<Exp> {
+    let doc = docstring(body);
+    let body = List::from(&body[doc.is_some() as usize..]);
+    if body.is_empty() {
+        return Err(eval_error!("Error in lambda expression: No body"));
+    }
+    let params = match params {
+        Exp::Atom(Atom::Symbol(_)) => parse_params(&[], Some(params))?,
+        params => {
+            let (params, rest) = params.improper_list().ok_or(BrewlisError::type_error("a parameter list", params))?;
+            parse_params(&params, rest)?
+        },
+    };
+    Ok(Exp::Lambda(Rc::new(Lambda { name, doc, params, body, env: env.clone() })))
+}
+
+/// Splits the signature `(name params...)` of `form`, which may be an
+/// improper list like (name x . rest), into the name and parameter list.
+fn signature(form: &str, signature: &Exp) -> Result<(Symbol, Exp)> {
+    let (name, params) = match signature {
+        Exp::List(l) => (l.first().ok_or(eval_error!("called '{}' with empty signature", form))?, Exp::List(l.tail_from(1))),
+        Exp::Pair(car, cdr) => (car.as_ref(), cdr.as_ref().clone()),
+        exp => return Err(BrewlisError::type_error("a signature", exp)),
+    };
+    Ok((name.clone().extract_symbol()?, params))
+}
+
+/// Reads the file at `path` and evaluates its forms in `env`. Returns the
+/// value of the last form, or #t for an empty file.
+pub fn load(path: &str, env: &Env) -> Result<Exp> {
+    load_with(path, env, eval)
+}
+
+/// Like `load`, but evaluates the forms with `evaluate`.
+pub fn load_with(path: &str, env: &Env, evaluate: fn(&Exp, &Env) -> Result<Exp>) -> Result<Exp> {
+    let program = fs::read_to_string(path).map_err(|err| eval_error!("Cannot read '{}': {}", path, err))?;
+    let context = |err: BrewlisError| err.with_context(format!("while loading '{}'", path));
+    let mut result = Exp::Atom(Atom::Bool(true));
+    for exp in parse(&program).map_err(context)? {
+        result = evaluate(&exp, env).map_err(context)?;
+    }
+    Ok(result)
+}
+
+/// The frames evaluation may nest by default, see `set_max_depth`.
+pub const DEFAULT_MAX_DEPTH: usize = 100_000;
+
+/// Space left on the Rust stack below which a nested evaluation, like a
+/// procedure called by `map`, continues on a newly allocated stack.
+const RED_ZONE: usize = 256 * 1024;
+const STACK_SEGMENT: usize = 4 * 1024 * 1024;
+
+/// The most notes on the arguments an error happened in, the innermost.
+const MAX_CONTEXTS: usize = 10;
+
+thread_local! {
+    static MAX_DEPTH: Cell<usize> = const { Cell::new(DEFAULT_MAX_DEPTH) };
+    /// The frames of all evaluations running, nested ones included.
+    static DEPTH: Cell<usize> = const { Cell::new(0) };
+    /// The expressions evaluated by the tree-walker since the thread
+    /// started, reported by `time`.
+    static EVALUATIONS: Cell<u64> = const { Cell::new(0) };
+}
+
+/// Sets how many frames evaluation may nest before it fails with "maximum
+/// recursion depth exceeded". Calls in tail position do not nest.
+pub fn set_max_depth(depth: usize) {
+    MAX_DEPTH.with(|max_depth| max_depth.set(depth));
+}
+
+pub fn max_depth() -> usize {
+    MAX_DEPTH.with(Cell::get)
+}
+
+pub fn evaluations() -> u64 {
+    EVALUATIONS.with(Cell::get)
+}
+
+pub(crate) fn depth_exceeded() -> BrewlisError {
+    eval_error!("maximum recursion depth exceeded")
+}
+
+/// Runs `f` for a nested evaluation or walk of a form, on a new stack
+/// segment if the current one is almost used up, so nesting is limited by
+/// the heap only.
+pub(crate) fn nested<T>(f: impl FnOnce() -> T) -> T {
+    stacker::maybe_grow(RED_ZONE, STACK_SEGMENT, f)
+}
+
+/// Evaluates `exp` in `env`.
+///
+/// Evaluation keeps the forms waiting for the value of a subexpression on
+/// an explicit stack of frames instead of the Rust stack. Expressions in
+/// tail position (the branches of `if`, the last expression of `begin` and
+/// of a procedure body) push no frame, so tail-recursive procedures run in
+/// constant space.
+///
+/// Errors carry the location of the innermost form with a source span.
+pub fn eval(exp: &Exp, env: &Env) -> Result<Exp> {
+    nested(|| Machine::new().run(exp.clone(), env.clone()))
+}
+
+/// What to do next: evaluate an expression or pass a value to the frame
+/// on top of the stack.
+enum State {
+    Eval(Exp, Env),
+    Return(Exp),
+}
+
+fn true_value() -> State {
+    State::Return(Exp::Atom(Atom::Bool(true)))
+}
+
+fn false_value() -> State {
+    State::Return(Exp::Atom(Atom::Bool(false)))
+}
+
+/// A form waiting for the value of one of its subexpressions.
+enum Frame {
+    /// The test of `if`.
+    If { consequent: Exp, alternative: Option<Exp>, env: Env },
+    /// The test of `when` or `unless`, named by `form`.
+    When { form: Symbol, body: List, env: Env },
+    /// An expression of a body, with the expressions after it.
+    Sequence { rest: List, env: Env },
+    Define { symbol: Symbol, doc: Option<Rc<str>>, env: Env },
+    DefineValues { symbols: List, env: Env },
+    Set { symbol: Symbol, env: Env },
+    /// An expression of `and` or `or`, with the expressions after it.
+    AndOr { stop_at: bool, rest: List, env: Env },
+    /// The test of a cond clause, with the clauses after it.
+    Cond { clause: List, rest: List, env: Env },
+    /// The key of `case`.
+    Case { clauses: List, env: Env },
+    /// The init of a `let`, `let*` or `letrec` binding. The inits of `let`
+    /// are evaluated in `env`, the others in `let_env`.
+    Let { list: List, bindings: Rc<Vec<(Symbol, Exp)>>, index: usize, let_env: Env, env: Env },
+    /// An init of a named `let`, with the values of the ones before it.
+    NamedLet { list: List, bindings: Rc<Vec<(Symbol, Exp)>>, args: Vec<Exp>, env: Env },
+    /// The operator of a call.
+    Operator { list: List, env: Env },
+    /// An argument of a call, with the procedure and the arguments before it.
+    Argument { procedure: Exp, args: Vec<Exp>, list: List, env: Env },
+    /// An expression of the body of `guard`, with the expressions after it.
+    Guard { var: Symbol, clauses: List, rest: List, env: Env },
+    /// The test of `while`.
+    WhileTest { list: List, env: Env },
+    /// An expression of the body of `while`, followed by the one at `next`.
+    WhileBody { list: List, next: usize, env: Env },
+    /// The init of the `index`th variable of `do`.
+    DoInit { do_loop: Rc<DoLoop>, index: usize, env: Env },
+    DoTest { do_loop: Rc<DoLoop> },
+    DoBody { do_loop: Rc<DoLoop>, rest: List },
+    /// A step of `do`, with the values of the steps before it.
+    DoStep { do_loop: Rc<DoLoop>, values: Vec<Exp> },
+    /// A form the debugger steps over, stepping resumes once it returned.
+    StepOver,
+}
+
+impl Frame {
+    /// The call evaluated for the frame, an argument or the operator of a
+    /// call. Procedure bodies have no frames, but the calls that entered
+    /// them do.
+    fn call(&self) -> Option<&Exp> {
+        match self {
+            Frame::Operator { list, .. } => list.first(),
+            Frame::Argument { args, list, .. } => list.get(args.len() + 1),
+            _ => None,
+        }
+    }
+}
+
+/// The parts of a `do` form: `(do ((var init step)...) (test result...) body...)`.
+struct DoLoop {
+    vars: Vec<(Symbol, Exp)>,
+    steps: Vec<(Symbol, Exp)>,
+    test: Exp,
+    results: List,
+    body: List,
+    do_env: Env,
+}
+
+struct Machine {
+    frames: Vec<(Frame, Option<Span>)>,
+    /// The span of the form in tail position that is evaluated, restored
+    /// from the frame when a subexpression returns.
+    span: Option<Span>,
+    /// The depth of the evaluations this one is nested in.
+    base: usize,
+    /// The form evaluated last with its environment, where the debugger
+    /// stops for an error. Only kept while a debugger is installed.
+    current: Option<(Exp, Env)>,
+    /// The form evaluated at top level, not nested in another evaluation.
+    entry: Option<Exp>,
+}
+
+/// The value of `exp` if it takes no evaluation step: a symbol or a
+/// constant.
+fn immediate(exp: &Exp, env: &Env) -> Result<Option<Exp>> {
+    match exp {
+        Exp::List(_) => Ok(None),
+        Exp::Atom(Atom::Symbol(sym)) => variable(sym, env).map(Some),
+        exp => Ok(Some(exp.clone())),
+    }
+}
+
+/// The value of the symbol `sym` in `env`. Builtins are found by their
+/// names unless they are shadowed.
+pub(crate) fn variable(sym: &Symbol, env: &Env) -> Result<Exp> {
+    match env.get(sym) {
+        Some(value) => Ok(value),
+        None if lookup_builtin(sym).is_some() => Ok(Exp::Builtin(*sym)),
+        None => Err(BrewlisError::Unbound { symbol: *sym, span: None }),
+    }
+}
+
+impl Machine {
+    fn new() -> Machine {
+        Machine { frames: Vec::new(), span: None, base: DEPTH.with(Cell::get), current: None, entry: None }
+    }
+
+    fn run(&mut self, exp: Exp, env: Env) -> Result<Exp> {
+        if self.base == 0 {
+            self.entry = Some(exp.clone());
+        }
+        let mut state = self.eval(exp, env);
+        let result = loop {
+            state = match state {
+                Ok(State::Eval(exp, env)) => self.eval(exp, env),
+                Ok(State::Return(value)) => match self.pop() {
+                    Some(frame) => self.resume(frame, value),
+                    None => break Ok(value),
+                },
+                Err(err) => {
+                    if debug::breaks_on(&err) {
+                        if let Some((form, env)) = self.current.clone() {
+                            self.stop(Reason::Error(&err), &form, &env);
+                        }
+                    }
+                    match self.unwind(err) {
+                        Ok(state) => Ok(state),
+                        Err(err) => break Err(err),
+                    }
+                },
+            };
+        };
+        DEPTH.with(|depth| depth.set(self.base));
+        result
+    }
+
+    /// The call evaluated at top level, which pushed no frame if it entered
+    /// a procedure body in tail position.
+    fn entry_call(&self) -> Option<&Exp> {
+        let entry = self.entry.as_ref()?;
+        let Exp::List(l) = entry else { return None };
+        let special_form = matches!(l.first(), Some(Exp::Atom(Atom::Symbol(sym))) if lookup_builtin(sym).is_some_and(|b| b.special_form));
+        (!special_form).then_some(entry)
+    }
+
+    /// The calls of this evaluation, the innermost last, as the backtrace
+    /// of an error lists them.
+    fn calls(&self) -> Vec<Exp> {
+        self.entry_call().into_iter().chain(self.frames.iter().filter_map(|(frame, _)| frame.call())).cloned().collect()
+    }
+
+    /// Calls the debugger, with the calls of this evaluation waiting for an
+    /// argument.
+    fn stop(&self, reason: Reason, form: &Exp, env: &Env) -> Resume {
+        debug::stop(&Stop { reason, form, env, calls: &self.calls() })
+    }
+
+    /// Pushes `frame` to evaluate a subexpression of the current form.
+    fn push(&mut self, frame: Frame) -> Result<()> {
+        let depth = self.base + self.frames.len() + 1;
+        if depth > max_depth() {
+            return Err(depth_exceeded());
+        }
+        DEPTH.with(|d| d.set(depth));
+        self.frames.push((frame, self.span.take()));
+        Ok(())
+    }
+
+    fn pop(&mut self) -> Option<Frame> {
+        let (frame, span) = self.frames.pop()?;
+        self.span = span;
+        DEPTH.with(|d| d.set(self.base + self.frames.len()));
+        Some(frame)
+    }
+
+    fn located(&self, err: BrewlisError) -> BrewlisError {
+        match &self.span {
+            Some(span) => err.with_span(span),
+            None => err,
+        }
+    }
+
+    /// Pops frames up to a `guard` that catches `err`.
+    fn unwind(&mut self, err: BrewlisError) -> Result<State> {
+        let mut err = self.located(err);
+        // Continuations and exit unwind through guard
+        let unwinding = matches!(err.root(), BrewlisError::Escape { .. } | BrewlisError::Exit { .. });
+        let mut contexts = 0;
+        let mut last_context = String::new();
+        while let Some(frame) = self.pop() {
+            match &frame {
+                Frame::Guard { var, clauses, env, .. } if !unwinding => {
+                    return Ok(guard_clauses(*var, clauses, &err, env));
+                },
+                // Deep recursion would add a context for each level, the
+                // same one for each level of a recursive procedure
+                Frame::Argument { args, list, .. } if !unwinding && contexts < MAX_CONTEXTS => {
+                    let context = match &list[0] {
+                        Exp::Atom(Atom::Symbol(sym)) => format!("while evaluating argument {} of '{}'", args.len() + 1, sym),
+                        _ => format!("while evaluating argument {}", args.len() + 1),
+                    };
+                    if context != last_context {
+                        contexts += 1;
+                        last_context.clone_from(&context);
+                        err = err.with_context(context);
+                    }
+                },
+                _ => {},
+            }
+            if let Some(call) = frame.call().filter(|_| !unwinding) {
+                err = err.with_caller(call);
+            }
+            err = self.located(err);
+        }
+        if let Some(call) = self.entry_call().filter(|_| !unwinding) {
+            err = err.with_caller(call);
+        }
+        Err(err)
+    }
+
+    /// Evaluates the subexpression `exp` of the current form, then passes its
+    /// value to `frame`.
+    fn subexpression(&mut self, frame: Frame, exp: &Exp, env: &Env) -> Result<State> {
+        self.push(frame)?;
+        Ok(State::Eval(exp.clone(), env.clone()))
+    }
+
+    /// Evaluates `body` like `begin`, the last expression in tail position.
+    fn body(&mut self, body: List, env: Env, empty: impl FnOnce() -> BrewlisError) -> Result<State> {
+        match body.len() {
+            0 => Err(empty()),
+            1 => Ok(State::Eval(body[0].clone(), env)),
+            _ => {
+                let first = body[0].clone();
+                self.push(Frame::Sequence { rest: body.tail(), env: env.clone() })?;
+                Ok(State::Eval(first, env))
+            },
+        }
+    }
+
+    fn eval(&mut self, exp: Exp, env: Env) -> Result<State> {
+        EVALUATIONS.with(|evaluations| evaluations.set(evaluations.get() + 1));
+        if debug::is_active() && matches!(exp, Exp::List(_)) {
+            debug::evaluating();
+            self.current = Some((exp.clone(), env.clone()));
+            if debug::is_stepping() {
+                if let Resume::Next = self.stop(Reason::Step, &exp, &env) {
+                    self.push(Frame::StepOver)?;
+                }
+            }
+        }
+        let l = match exp {
+            Exp::List(l) => l,
+            exp => return Ok(State::Return(immediate(&exp, &env)?.expect("not a list"))),
+        };
+        if let Some(span) = &l.span {
+            self.span = Some(Span::clone(span));
+        }
+        let head = l.first().ok_or(eval_error!("Cannot evaluate an empty list"))?;
+        if let Exp::Atom(Atom::Symbol(sym)) = head {
+            if let Some(state) = self.special_form(*sym, &l, &env)? {
+                return Ok(state);
+            }
+        }
+        match immediate(head, &env)? {
+            Some(procedure) => self.call(procedure, l, env),
+            None => {
+                let head = head.clone();
+                self.subexpression(Frame::Operator { list: l, env: env.clone() }, &head, &env)
+            },
+        }
+    }
+
+    /// Evaluates the special form `l` named `sym`, or returns None if `sym`
+    /// names no special form.
+    fn special_form(&mut self, sym: Symbol, l: &List, env: &Env) -> Result<Option<State>> {
+        let state = match sym.as_str() {
+            "if" => {
+                let test = l.get(1).ok_or(eval_error!("called 'if' without a test"))?;
+                let consequent = l.get(2).ok_or(eval_error!("called 'if' without a consequent"))?.clone();
+                let frame = Frame::If { consequent, alternative: l.get(3).cloned(), env: env.clone() };
+                self.subexpression(frame, test, env)?
+            },
+            "when" | "unless" => {
+                // (when test body...) evaluates body if test is true, unless
+                // if it is false, and both return #f otherwise
+                let test = l.get(1).ok_or(eval_error!("called '{}' without a test", sym))?;
+                self.subexpression(Frame::When { form: sym, body: l.tail_from(2), env: env.clone() }, test, env)?
+            },
+            "quote" => State::Return(l.get(1).cloned().ok_or(eval_error!("called 'quote' without an expression"))?),
+            "quasiquote" => {
+                let template = l.get(1).ok_or(eval_error!("called 'quasiquote' without an expression"))?;
+                State::Return(quasiquote(template, 1, env)?)
+            },
+            "begin" => self.body(l.tail_from(1), env.clone(), || eval_error!("called 'begin' with empty list"))?,
+            "define" => {
+                if let Some(sig @ (Exp::List(_) | Exp::Pair(..))) = l.get(1) {
+                    // (define (f x y) body...) is (define f (lambda (x y) body...))
+                    let (symbol, params) = signature("define", sig)?;
+                    let result = make_procedure(Some(symbol), &params, &l[2..], env)?;
+                    let doc = lambda_doc(&result);
+                    env.define_documented(symbol, result, doc);
+                    return Ok(Some(true_value()));
+                }
+                let symbol = l.get(1).ok_or(eval_error!("called 'define' without a symbol"))?.clone().extract_symbol()?;
+                // (define x "doc" value)
+                let doc = docstring(&l[2..]);
+                let exp = l.get(2 + doc.is_some() as usize).ok_or(eval_error!("called 'define' without a value"))?;
+                self.subexpression(Frame::Define { symbol, doc, env: env.clone() }, exp, env)?
+            },
+            "define-values" => {
+                // (define-values (a b) exp) binds each value of exp
+                let Some(Exp::List(symbols)) = l.get(1) else {
+                    return Err(eval_error!("called 'define-values' without a list of symbols"));
+                };
+                let exp = l.get(2).ok_or(eval_error!("called 'define-values' without an expression"))?;
+                self.subexpression(Frame::DefineValues { symbols: symbols.clone(), env: env.clone() }, exp, env)?
+            },
+            "set!" => {
+                let symbol = l.get(1).ok_or(eval_error!("called 'set!' without a symbol"))?.clone().extract_symbol()?;
+                let exp = l.get(2).ok_or(eval_error!("called 'set!' without a value"))?;
+                self.subexpression(Frame::Set { symbol, env: env.clone() }, exp, env)?
+            },
+            "define-macro" => {
+                // (define-macro (name params...) body...)
+                let Some(sig @ (Exp::List(_) | Exp::Pair(..))) = l.get(1) else {
+                    return Err(eval_error!("called 'define-macro' without (name params...)"));
+                };
+                let (symbol, params) = signature("define-macro", sig)?;
+                let Exp::Lambda(lambda) = make_procedure(Some(symbol), &params, &l[2..], env)? else {
+                    unreachable!("make_procedure returns a lambda");
+                };
+                let doc = lambda.doc.clone();
+                env.define_documented(symbol, Exp::Macro(lambda), doc);
+                true_value()
+            },
+            "define-syntax" => {
+                // (define-syntax name (syntax-rules (literal...) (pattern template)...))
+                let symbol = l.get(1).ok_or(eval_error!("called 'define-syntax' without a name"))?.clone().extract_symbol()?;
+                let rules = SyntaxRules::new(l.get(2).ok_or(eval_error!("called 'define-syntax' without syntax-rules"))?)?;
+                env.define(symbol, Exp::Syntax(Rc::new(rules)));
+                true_value()
+            },
+            "delay" => {
+                let delayed = l.get(1).ok_or(eval_error!("called 'delay' without an expression"))?.clone();
+                State::Return(Exp::Promise(Rc::new(RefCell::new(Promise::Delayed(delayed, env.clone())))))
+            },
+            "lambda" => State::Return(make_lambda(None, l, env)?),
+            "trace" | "untrace" => {
+                // (trace f...) and (untrace f...) take the procedures by name
+                let names = l[1..].iter().map(|name| name.clone().extract_symbol()).collect::<Result<Vec<_>>>()?;
+                if sym == "untrace" && names.is_empty() {
+                    trace::untrace(None);
+                }
+                for name in names {
+                    let procedure = match env.get(&name) {
+                        Some(procedure) => procedure,
+                        None if lookup_builtin(&name).is_some() => return Err(eval_error!("Cannot {} the builtin {}", sym, name)),
+                        None => return Err(BrewlisError::Unbound { symbol: name, span: None }),
+                    };
+                    match sym.as_str() {
+                        "trace" => trace::trace(&procedure, name)?,
+                        _ => trace::untrace(Some(&procedure)),
+                    }
+                }
+                true_value()
+            },
+            "break" => {
+                // (break) does nothing without a debugger
+                self.stop(Reason::Break, &Exp::List(l.clone()), env);
+                true_value()
+            },
+            "time" => {
+                let exp = l.get(1).ok_or(eval_error!("called 'time' without an expression"))?;
+                State::Return(time::timed(|| eval(exp, env), write_output)?)
+            },
+            "define-module" => {
+                module::define(l.get(1).ok_or(eval_error!("called 'define-module' without a name"))?)?;
+                true_value()
+            },
+            "export" => {
+                let names = l[1..].iter().map(|exp| exp.clone().extract_symbol()).collect::<Result<Vec<_>>>()?;
+                module::export(names);
+                true_value()
+            },
+            "import" => {
+                for name in &l[1..] {
+                    module::import(name, env)?;
+                }
+                true_value()
+            },
+            "help" => {
+                // (help name) takes the name unevaluated, other expressions
+                // like 'name are evaluated to it
+                let name = match l.get(1) {
+                    Some(Exp::Atom(Atom::Symbol(name))) => Some(*name),
+                    Some(exp) => Some(eval(exp, env)?.extract_symbol()?),
+                    None => None,
+                };
+                write_output(&format!("{}\n", help_text(name.as_deref(), env)?))?;
+                true_value()
+            },
+            "define-test" => {
+                // (define-test name body...) registers (lambda () body...)
+                let name = match l.get(1) {
+                    Some(Exp::Atom(Atom::Symbol(name))) => name.to_string(),
+                    Some(Exp::Atom(Atom::String(name))) => name.to_string(),
+                    Some(exp) => return Err(BrewlisError::type_error("a test name", exp)),
+                    None => return Err(eval_error!("called 'define-test' without a name")),
+                };
+                let mut lambda = vec![Exp::Atom(Atom::Symbol(Symbol::new("lambda"))), Exp::List(List::new())];
+                lambda.extend(l[2..].iter().cloned());
+                testing::register(name, make_lambda(None, &lambda, env)?);
+                true_value()
+            },
+            "assert-error" => {
+                // (assert-error exp) succeeds if evaluating exp raises an error
+                let exp = l.get(1).ok_or(eval_error!("called 'assert-error' without an expression"))?;
+                match eval(exp, env) {
+                    Ok(value) => return Err(eval_error!("assert-error failed: {} returned {}", exp, value)),
+                    Err(err) if matches!(err.root(), BrewlisError::Escape { .. } | BrewlisError::Exit { .. }) => return Err(err),
+                    Err(_) => true_value(),
+                }
+            },
+            "and" | "or" => {
+                // and stops at the first false value, or at the first true one
+                let stop_at = sym == "or";
+                self.and_or(stop_at, l.tail_from(1), env.clone())?
+            },
+            "cond" => self.cond(l.tail_from(1), env.clone())?,
+            "case" => {
+                let key = l.get(1).ok_or(eval_error!("called 'case' without a key"))?;
+                self.subexpression(Frame::Case { clauses: l.tail_from(2), env: env.clone() }, key, env)?
+            },
+            "guard" => {
+                // (guard (var clause...) body...)
+                let Some(Exp::List(spec)) = l.get(1) else {
+                    return Err(eval_error!("called 'guard' without (var clause...)"));
+                };
+                let var = spec.first().ok_or(eval_error!("called 'guard' without a variable"))?.clone().extract_symbol()?;
+                let body = l.tail_from(2);
+                let Some(first) = body.first() else {
+                    return Ok(Some(true_value()));
+                };
+                let frame = Frame::Guard { var, clauses: spec.tail(), rest: body.tail(), env: env.clone() };
+                self.subexpression(frame, first, env)?
+            },
+            "while" => {
+                // (while test body...) returns #t once test is false
+                let test = l.get(1).ok_or(eval_error!("called 'while' without a test"))?;
+                self.subexpression(Frame::WhileTest { list: l.clone(), env: env.clone() }, test, env)?
+            },
+            "do" => {
+                let do_loop = Rc::new(do_loop(l, env)?);
+                self.do_init(do_loop, 0, env.clone())?
+            },
+            "let" if matches!(l.get(1), Some(Exp::Atom(Atom::Symbol(_)))) => {
+                // (let name ((sym init)...) body...) calls a procedure name
+                // with the bindings as parameters, which the body can call
+                // again to loop
+                let bindings = parse_bindings(l.get(2).ok_or(eval_error!("called 'let' without bindings"))?)?;
+                self.named_let(l.clone(), Rc::new(bindings), Vec::new(), env.clone())?
+            },
+            "let" | "let*" | "letrec" => {
+                let bindings = l.get(1).ok_or(eval_error!("called '{}' without bindings", sym))?;
+                let bindings = Rc::new(parse_bindings(bindings)?);
+                self.let_binding(l.clone(), bindings, 0, env.extend(), env.clone())?
+            },
+            _ => return Ok(None),
+        };
+        Ok(Some(state))
+    }
+
+    fn and_or(&mut self, stop_at: bool, exps: List, env: Env) -> Result<State> {
+        match exps.len() {
+            0 => Ok(State::Return(Exp::Atom(Atom::Bool(!stop_at)))),
+            1 => Ok(State::Eval(exps[0].clone(), env)),
+            _ => {
+                let first = exps[0].clone();
+                self.subexpression(Frame::AndOr { stop_at, rest: exps.tail(), env: env.clone() }, &first, &env)
+            },
+        }
+    }
+
+    fn cond(&mut self, clauses: List, env: Env) -> Result<State> {
+        let Some(clause) = clauses.first() else {
+            return Ok(false_value());
+        };
+        let Exp::List(clause) = clause else {
+            return Err(eval_error!("Not a cond clause: {}", clause));
+        };
+        let test = clause.first().ok_or(eval_error!("Empty cond clause"))?;
+        if *test == Exp::Atom(Atom::Symbol(Symbol::new("else"))) {
+            return self.cond_body(clause.clone(), Exp::Atom(Atom::Bool(true)), env);
+        }
+        let test = test.clone();
+        self.subexpression(Frame::Cond { clause: clause.clone(), rest: clauses.tail(), env: env.clone() }, &test, &env)
+    }
+
+    fn cond_body(&mut self, clause: List, value: Exp, env: Env) -> Result<State> {
+        // A clause without expressions returns the value of its test
+        if clause.len() == 1 {
+            return Ok(State::Return(value));
+        }
+        self.body(clause.tail(), env, || unreachable!("the clause has expressions"))
+    }
+
+    fn let_binding(&mut self, list: List, bindings: Rc<Vec<(Symbol, Exp)>>, index: usize, let_env: Env, env: Env) -> Result<State> {
+        let Some((_, init)) = bindings.get(index) else {
+            let sym = list[0].clone();
+            return self.body(list.tail_from(2), let_env, || eval_error!("called '{}' without body", sym));
+        };
+        // let* and letrec evaluate each init with the previous bindings in
+        // scope. Procedures see all bindings of a letrec since they are
+        // called after it was set up.
+        let init_env = if list[0] == Exp::Atom(Atom::Symbol(Symbol::new("let"))) { env.clone() } else { let_env.clone() };
+        let init = init.clone();
+        self.subexpression(Frame::Let { list, bindings, index, let_env, env }, &init, &init_env)
+    }
+
+    fn named_let(&mut self, list: List, bindings: Rc<Vec<(Symbol, Exp)>>, args: Vec<Exp>, env: Env) -> Result<State> {
+        if let Some((_, init)) = bindings.get(args.len()) {
+            let init = init.clone();
+            return self.subexpression(Frame::NamedLet { list, bindings, args, env: env.clone() }, &init, &env);
+        }
+        let name = list[1].clone().extract_symbol()?;
+        let params = bindings.iter().map(|(symbol, _)| Exp::Atom(Atom::Symbol(*symbol))).collect();
+        let mut lambda = vec![Exp::Atom(Atom::Symbol(Symbol::new("lambda"))), Exp::List(params)];
+        lambda.extend(list[3..].iter().cloned());
+        let let_env = env.extend();
+        let Exp::Lambda(lambda) = make_lambda(Some(name), &lambda, &let_env)? else {
+            unreachable!("make_lambda returns a lambda")
+        };
+        let_env.define(name, Exp::Lambda(lambda.clone()));
+        let lambda_env = bind_lambda(&lambda, &args)?;
+        self.body(lambda.body.clone(), lambda_env, || eval_error!("called 'let' without body"))
+    }
+
+    fn do_init(&mut self, do_loop: Rc<DoLoop>, index: usize, env: Env) -> Result<State> {
+        match do_loop.vars.get(index) {
+            Some((_, init)) => {
+                let init = init.clone();
+                self.subexpression(Frame::DoInit { do_loop, index, env: env.clone() }, &init, &env)
+            },
+            None => self.do_test(do_loop),
+        }
+    }
+
+    fn do_test(&mut self, do_loop: Rc<DoLoop>) -> Result<State> {
+        let (test, do_env) = (do_loop.test.clone(), do_loop.do_env.clone());
+        self.subexpression(Frame::DoTest { do_loop }, &test, &do_env)
+    }
+
+    fn do_step(&mut self, do_loop: Rc<DoLoop>, values: Vec<Exp>) -> Result<State> {
+        if let Some((_, step)) = do_loop.steps.get(values.len()) {
+            let (step, do_env) = (step.clone(), do_loop.do_env.clone());
+            return self.subexpression(Frame::DoStep { do_loop, values }, &step, &do_env);
+        }
+        // All steps are evaluated before any variable is updated
+        for ((symbol, _), value) in do_loop.steps.iter().zip(values) {
+            do_loop.do_env.define(*symbol, value);
+        }
+        self.do_test(do_loop)
+    }
+
+    /// Calls `procedure` with the arguments of the call `list`, after
+    /// evaluating them unless it is a macro.
+    fn call(&mut self, procedure: Exp, list: List, env: Env) -> Result<State> {
+        match &procedure {
+            // The expansion is evaluated in place of the macro call
+            Exp::Macro(lambda) => Ok(State::Eval(proc(&Exp::Lambda(lambda.clone()), &list[1..], &env)?, env)),
+            Exp::Syntax(rules) => Ok(State::Eval(rules.expand(&list)?, env)),
+            _ => {
+                let args = Vec::with_capacity(list.len() - 1);
+                self.arguments(procedure, args, list, env)
+            },
+        }
+    }
+
+    /// Evaluates the arguments of `list` after `args` and applies `procedure`.
+    fn arguments(&mut self, procedure: Exp, mut args: Vec<Exp>, list: List, env: Env) -> Result<State> {
+        while let Some(exp) = list.get(args.len() + 1) {
+            match immediate(exp, &env)? {
+                Some(value) => args.push(value),
+                None => {
+                    let exp = exp.clone();
+                    return self.subexpression(Frame::Argument { procedure, args, list, env: env.clone() }, &exp, &env);
+                },
+            }
+        }
+        if let Exp::Lambda(lambda) = &procedure {
+            // Traced calls are not in tail position, their value is written
+            let operator = match &list[0] {
+                Exp::Atom(Atom::Symbol(sym)) => Some(*sym),
+                _ => None,
+            };
+            if trace::traced_name(&procedure, operator).is_some() {
+                return Ok(State::Return(traced_call(&procedure, operator, &args, || call_lambda(lambda, &args))?));
+            }
+            let lambda_env = bind_lambda(lambda, &args)?;
+            return self.body(lambda.body.clone(), lambda_env, || eval_error!("Error in lambda expression: No body"));
+        }
+        Ok(State::Return(proc(&procedure, &args, &env)?))
+    }
+
+    /// Passes the value of a subexpression to the form waiting for it.
+    fn resume(&mut self, frame: Frame, value: Exp) -> Result<State> {
+        match frame {
+            Frame::If { consequent, alternative, env } => {
+                if is_true(&value) {
+                    Ok(State::Eval(consequent, env))
+                } else if let Some(alternative) = alternative {
+                    Ok(State::Eval(alternative, env))
+                } else {
+                    // A one-armed if with a false test returns #f
+                    Ok(false_value())
+                }
+            },
+            Frame::When { form, body, env } => {
+                if is_true(&value) != (form == "when") {
+                    return Ok(false_value());
+                }
+                self.body(body, env, || eval_error!("called '{}' without body", form))
+            },
+            Frame::Sequence { rest, env } => self.body(rest, env, || unreachable!("sequences are not empty")),
+            Frame::Define { symbol, doc, env } => {
+                let doc = doc.or_else(|| lambda_doc(&value));
+                env.define_documented(symbol, value, doc);
+                Ok(true_value())
+            },
+            Frame::DefineValues { symbols, env } => {
+                let values = values_list(value);
+                if values.len() != symbols.len() {
+                    return Err(eval_error!("called 'define-values' with {} symbols for {} values", symbols.len(), values.len()));
+                }
+                for (symbol, value) in symbols.iter().zip(values) {
+                    env.define(symbol.clone().extract_symbol()?, value);
+                }
+                Ok(true_value())
+            },
+            Frame::Set { symbol, env } => {
+                if !env.set(&symbol, value) {
+                    return Err(eval_error!("called 'set!' on unbound symbol {}", symbol));
+                }
+                Ok(true_value())
+            },
+            Frame::AndOr { stop_at, rest, env } => {
+                if is_true(&value) == stop_at {
+                    return Ok(State::Return(value));
+                }
+                self.and_or(stop_at, rest, env)
+            },
+            Frame::Cond { clause, rest, env } => {
+                if is_true(&value) {
+                    self.cond_body(clause, value, env)
+                } else {
+                    self.cond(rest, env)
+                }
+            },
+            Frame::Case { clauses, env } => {
+                for clause in &clauses {
+                    let Exp::List(clause) = clause else {
+                        return Err(eval_error!("Not a case clause: {}", clause));
+                    };
+                    let matches = match clause.first() {
+                        Some(Exp::Atom(Atom::Symbol(s))) if s == "else" => true,
+                        Some(Exp::List(data)) => data.iter().any(|datum| eqv(datum, &value)),
+                        _ => return Err(eval_error!("Not a case clause: {}", Exp::List(clause.clone()))),
+                    };
+                    if matches {
+                        return self.body(clause.tail(), env, || eval_error!("Empty case clause"));
+                    }
+                }
+                Ok(false_value())
+            },
+            Frame::Let { list, bindings, index, let_env, env } => {
+                let_env.define(bindings[index].0, value);
+                self.let_binding(list, bindings, index + 1, let_env, env)
+            },
+            Frame::NamedLet { list, bindings, mut args, env } => {
+                args.push(value);
+                self.named_let(list, bindings, args, env)
+            },
+            Frame::Operator { list, env } => self.call(value, list, env),
+            Frame::Argument { procedure, mut args, list, env } => {
+                args.push(value);
+                self.arguments(procedure, args, list, env)
+            },
+            Frame::Guard { var, clauses, rest, env } => {
+                let Some(next) = rest.first() else {
+                    return Ok(State::Return(value));
+                };
+                let next = next.clone();
+                self.subexpression(Frame::Guard { var, clauses, rest: rest.tail(), env: env.clone() }, &next, &env)
+            },
+            Frame::WhileTest { list, env } => {
+                if !is_true(&value) {
+                    return Ok(true_value());
+                }
+                self.while_body(list, 2, env)
+            },
+            Frame::WhileBody { list, next, env } => self.while_body(list, next, env),
+            Frame::DoInit { do_loop, index, env } => {
+                do_loop.do_env.define(do_loop.vars[index].0, value);
+                self.do_init(do_loop, index + 1, env)
+            },
+            Frame::DoTest { do_loop } => {
+                if is_true(&value) {
+                    // Without result expressions do returns #t
+                    if do_loop.results.is_empty() {
+                        return Ok(true_value());
+                    }
+                    return self.body(do_loop.results.clone(), do_loop.do_env.clone(), || unreachable!("do has results"));
+                }
+                match do_loop.body.first() {
+                    Some(first) => {
+                        let (first, rest, do_env) = (first.clone(), do_loop.body.tail(), do_loop.do_env.clone());
+                        self.subexpression(Frame::DoBody { do_loop, rest }, &first, &do_env)
+                    },
+                    None => self.do_step(do_loop, Vec::new()),
+                }
+            },
+            Frame::DoBody { do_loop, rest } => match rest.first() {
+                Some(next) => {
+                    let (next, do_env) = (next.clone(), do_loop.do_env.clone());
+                    self.subexpression(Frame::DoBody { do_loop, rest: rest.tail() }, &next, &do_env)
+                },
+                None => self.do_step(do_loop, Vec::new()),
+            },
+            Frame::DoStep { do_loop, mut values } => {
+                values.push(value);
+                self.do_step(do_loop, values)
+            },
+            Frame::StepOver => {
+                debug::step();
+                Ok(State::Return(value))
+            },
+        }
+    }
+
+    /// Evaluates the body expressions of the `while` form `list` from
+    /// `index` on, then its test again.
+    fn while_body(&mut self, list: List, index: usize, env: Env) -> Result<State> {
+        match list.get(index) {
+            Some(exp) => {
+                let exp = exp.clone();
+                self.subexpression(Frame::WhileBody { list, next: index + 1, env: env.clone() }, &exp, &env)
+            },
+            None => {
+                let test = list[1].clone();
+                self.subexpression(Frame::WhileTest { list, env: env.clone() }, &test, &env)
+            },
+        }
+    }
+}
+
+/// Evaluates the clauses of a `guard` for the error `err` caught by it.
+/// The clauses are a cond on the condition, which is raised again if no
+/// clause matches.
+fn guard_clauses(var: Symbol, clauses: &List, err: &BrewlisError, env: &Env) -> State {
+    let guard_env = env.extend();
+    guard_env.define(var, err.condition());
+    let mut cond = vec![Exp::Atom(Atom::Symbol(Symbol::new("cond")))];
+    cond.extend(clauses.iter().cloned());
+    let has_else = clauses.last().is_some_and(|clause| {
+        matches!(clause, Exp::List(clause) if clause.first() == Some(&Exp::Atom(Atom::Symbol(Symbol::new("else")))))
+    });
+    if !has_else {
+        let reraise = vec![Exp::Atom(Atom::Symbol(Symbol::new("raise"))), Exp::Atom(Atom::Symbol(var))];
+        cond.push(Exp::List(vec![Exp::Atom(Atom::Symbol(Symbol::new("else"))), Exp::List(reraise.into())].into()));
+    }
+    State::Eval(Exp::List(cond.into()), guard_env)
+}
+
+/// Splits a `do` form into its parts.
+fn do_loop(l: &List, env: &Env) -> Result<DoLoop> {
+    let Some(Exp::List(specs)) = l.get(1) else {
+        return Err(eval_error!("called 'do' without variables"));
+    };
+    let mut vars = Vec::new();
+    let mut steps = Vec::new();
+    for spec in specs {
+        let Exp::List(spec) = spec else {
+            return Err(eval_error!("Not a do variable: {}", spec));
+        };
+        match &spec[..] {
+            [symbol, init] => vars.push((symbol.clone().extract_symbol()?, init.clone())),
+            [symbol, init, step] => {
+                let symbol = symbol.clone().extract_symbol()?;
+                vars.push((symbol, init.clone()));
+                steps.push((symbol, step.clone()));
+            },
+            _ => return Err(eval_error!("Not a do variable: {}", Exp::List(spec.clone()))),
+        }
+    }
+    let Some(Exp::List(exit)) = l.get(2) else {
+        return Err(eval_error!("called 'do' without a test"));
+    };
+    let test = exit.first().ok_or(eval_error!("called 'do' without a test"))?.clone();
+    Ok(DoLoop { vars, steps, test, results: exit.tail(), body: l.tail_from(3), do_env: env.extend() })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::Interpreter;
+
+    /// The value of `program` as printed, on the tree-walker and the VM.
+    fn values(program: &str) -> [String; 2] {
+        [false, true].map(|vm| {
+            let mut interpreter = Interpreter::new();
+            interpreter.use_vm(vm);
+            interpreter.eval_str(program).unwrap().to_string()
+        })
+    }
+
+    #[test]
+    fn symbol_parameters_bind_all_arguments() {
+        assert_eq!(values("((lambda args args) 1 2)"), ["(1 2)", "(1 2)"]);
+        assert_eq!(values("((lambda args (length args)))"), ["0", "0"]);
+    }
+
+    #[test]
+    fn quasiquote_unquotes_dotted_tails() {
+        assert_eq!(values("`(1 . ,(+ 1 1))"), ["(1 . 2)", "(1 . 2)"]);
+        assert_eq!(values("`(,(+ 1 1) . x)"), ["(2 . x)", "(2 . x)"]);
+    }
+}
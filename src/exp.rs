@@ -0,0 +1,633 @@
+use std::{cell::RefCell, collections::HashMap, fmt, ops::{Deref, DerefMut}, rc::Rc};
+
+use num_bigint::BigInt;
+use num_complex::Complex64;
+use num_rational::BigRational;
+use num_traits::ToPrimitive;
+
+use crate::{builtins::Arity, env::Env, eval, gc, error::{BrewlisError, Result}, port::Port, span::Span, symbol::Symbol, syntax_rules::SyntaxRules, vm::Closure};
+
+/// An atomic value. Numbers, booleans, characters and symbols are stored
+/// inline, strings and big numbers behind a shared handle, so cloning an
+/// atom never copies its contents.
+#[derive(PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Atom {
+    Symbol(Symbol),
+    /// A floating point number.
+    Number(f64),
+    /// An exact integer.
+    Int(i64),
+    /// An exact integer that does not fit into an `i64`.
+    BigInt(Rc<BigInt>),
+    /// An exact fraction, never with a denominator of one.
+    Rational(Rc<BigRational>),
+    /// A floating point complex number, never with an imaginary part of zero.
+    Complex(Complex64),
+    Bool(bool),
+    String(Rc<str>),
+    Char(char),
+    /// A keyword like `#:width`, naming the argument after it. It is
+    /// stored without the `#:`.
+    Keyword(Symbol),
+}
+
+impl fmt::Debug for Atom {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Atom::Symbol(s) => f.debug_tuple("Symbol").field(s).finish(),
+            Atom::Number(n) => f.debug_tuple("Number").field(n).finish(),
+            Atom::Int(i) => f.debug_tuple("Int").field(i).finish(),
+            Atom::BigInt(i) => f.debug_tuple("BigInt").field(i).finish(),
+            // Printed as 1/3 rather than as its numerator and denominator
+            Atom::Rational(r) => write!(f, "Rational({})", r),
+            Atom::Complex(c) => f.debug_tuple("Complex").field(&c.re).field(&c.im).finish(),
+            Atom::Bool(b) => f.debug_tuple("Bool").field(b).finish(),
+            Atom::String(s) => f.debug_tuple("String").field(s).finish(),
+            Atom::Char(c) => f.debug_tuple("Char").field(c).finish(),
+            Atom::Keyword(k) => f.debug_tuple("Keyword").field(k).finish(),
+        }
+    }
+}
+
+/// Atoms are displayed in the syntax they are read from.
+impl fmt::Display for Atom {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Atom::Symbol(s) => write!(f, "{}", s),
+            Atom::Number(n) if n.is_nan() => write!(f, "+nan.0"),
+            Atom::Number(n) if n.is_infinite() => write!(f, "{}inf.0", if *n > 0.0 { "+" } else { "-" }),
+            // Integral floats keep their point to stay inexact when read
+            Atom::Number(n) if n.fract() == 0.0 => write!(f, "{:.1}", n),
+            Atom::Number(n) => write!(f, "{}", n),
+            Atom::Int(i) => write!(f, "{}", i),
+            Atom::BigInt(i) => write!(f, "{}", i),
+            Atom::Rational(r) => write!(f, "{}", r),
+            Atom::Complex(c) => {
+                // A pure imaginary number is written like +2.0i
+                if c.re != 0.0 {
+                    write!(f, "{}", Atom::Number(c.re))?;
+                }
+                let im = Atom::Number(c.im).to_string();
+                let sign = if im.starts_with(['+', '-']) { "" } else { "+" };
+                write!(f, "{}{}i", sign, im)
+            }
+            Atom::Bool(b) => write!(f, "{}", if *b { "#t" } else { "#f" }),
+            Atom::String(s) => {
+                write!(f, "\"")?;
+                for c in s.chars() {
+                    match c {
+                        '\n' => write!(f, "\\n")?,
+                        '\t' => write!(f, "\\t")?,
+                        '"' => write!(f, "\\\"")?,
+                        '\\' => write!(f, "\\\\")?,
+                        c => write!(f, "{}", c)?,
+                    }
+                }
+                write!(f, "\"")
+            },
+            Atom::Char(c) => match char_name(*c) {
+                Some(name) => write!(f, "#\\{}", name),
+                None => write!(f, "#\\{}", c),
+            },
+            Atom::Keyword(k) => write!(f, "#:{}", k),
+        }
+    }
+}
+
+/// The name of a character that is written by name, the inverse of the
+/// names the parser accepts.
+fn char_name(c: char) -> Option<&'static str> {
+    match c {
+        ' ' => Some("space"),
+        '\n' => Some("newline"),
+        '\t' => Some("tab"),
+        '\r' => Some("return"),
+        '\0' => Some("nul"),
+        '\x07' => Some("alarm"),
+        '\x08' => Some("backspace"),
+        '\x1b' => Some("escape"),
+        '\x7f' => Some("delete"),
+        _ => None,
+    }
+}
+
+#[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Exp {
+    Atom(Atom),
+    List(List),
+    /// A pair whose cdr is not a list. Pairs whose cdr is a list are
+    /// represented as `List`s, with the empty list as the list terminator.
+    Pair(Box<Exp>, Box<Exp>),
+    /// A mutable, fixed-size vector shared by all references to it.
+    Vector(
+        #[cfg_attr(feature = "serde", serde(serialize_with = "crate::serialize::serialize_vector", deserialize_with = "crate::serialize::deserialize_vector"))]
+        Rc<RefCell<Vec<Exp>>>,
+    ),
+    /// A mutable hash table shared by all references to it.
+    HashTable(
+        #[cfg_attr(feature = "serde", serde(serialize_with = "crate::serialize::serialize_hash_table", deserialize_with = "crate::serialize::deserialize_hash_table"))]
+        Rc<RefCell<HashMap<HashKey, Exp>>>,
+    ),
+    #[cfg_attr(feature = "serde", serde(skip))]
+    Lambda(Rc<Lambda>),
+    /// A procedure compiled to bytecode by the VM.
+    #[cfg_attr(feature = "serde", serde(skip))]
+    Closure(Rc<Closure>),
+    /// A procedure implemented in Rust by the program embedding brewlis.
+    #[cfg_attr(feature = "serde", serde(skip))]
+    Native(Rc<NativeFn>),
+    /// A builtin, the value of its name.
+    #[cfg_attr(feature = "serde", serde(skip))]
+    Builtin(Symbol),
+    /// A macro defined by `define-macro`. The procedure is called with the
+    /// unevaluated arguments and returns the expression to evaluate instead.
+    #[cfg_attr(feature = "serde", serde(skip))]
+    Macro(Rc<Lambda>),
+    /// A macro defined by `define-syntax` and `syntax-rules`.
+    #[cfg_attr(feature = "serde", serde(skip))]
+    Syntax(Rc<SyntaxRules>),
+    /// An environment usable with `eval`, shared with the scope it was
+    /// created in.
+    #[cfg_attr(feature = "serde", serde(skip))]
+    Environment(Env),
+    /// An error object created by `error` or caught from a failed builtin.
+    Error(Rc<ErrorObject>),
+    /// An escape-only continuation captured by `call/cc`, identified by the
+    /// number of the `call/cc` invocation it belongs to.
+    #[cfg_attr(feature = "serde", serde(skip))]
+    Continuation(u64),
+    /// The results of `values` with other than one value, which
+    /// `call-with-values` and `define-values` pass on separately.
+    Values(List),
+    /// A promise created by `delay`, evaluated at most once by `force`.
+    #[cfg_attr(feature = "serde", serde(skip))]
+    Promise(Rc<RefCell<Promise>>),
+    /// A file opened for reading or writing.
+    #[cfg_attr(feature = "serde", serde(skip))]
+    Port(Rc<Port>),
+    /// What reading returns at the end of a file.
+    Eof,
+}
+
+/// Expressions are displayed as s-expressions in the syntax they are read
+/// from, values without a syntax as `#<...>`.
+impl fmt::Display for Exp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write_exp(f, self, true)
+    }
+}
+
+/// An expression displayed for humans, as by `display`: like its `Display`
+/// output, but with strings and characters written as they are.
+pub struct Displayed<'a>(pub &'a Exp);
+
+impl fmt::Display for Displayed<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write_exp(f, self.0, false)
+    }
+}
+
+/// Writes `exp` as an s-expression, re-readable if `readable` is set.
+fn write_exp(f: &mut fmt::Formatter<'_>, exp: &Exp, readable: bool) -> fmt::Result {
+    let elements = |f: &mut fmt::Formatter<'_>, elements: &[Exp]| -> fmt::Result {
+        for (i, element) in elements.iter().enumerate() {
+            if i > 0 {
+                write!(f, " ")?;
+            }
+            write_exp(f, element, readable)?;
+        }
+        Ok(())
+    };
+    match exp {
+        Exp::Atom(Atom::String(s)) if !readable => write!(f, "{}", s),
+        Exp::Atom(Atom::Char(c)) if !readable => write!(f, "{}", c),
+        Exp::Atom(atom) => write!(f, "{}", atom),
+        Exp::List(l) => {
+            write!(f, "(")?;
+            elements(f, l)?;
+            write!(f, ")")
+        },
+        Exp::Pair(car, cdr) => {
+            // Nested pairs are written as one improper list, (1 2 . 3)
+            write!(f, "(")?;
+            write_exp(f, car, readable)?;
+            let mut cdr = cdr.as_ref();
+            while let Exp::Pair(car, rest) = cdr {
+                write!(f, " ")?;
+                write_exp(f, car, readable)?;
+                cdr = rest;
+            }
+            write!(f, " . ")?;
+            write_exp(f, cdr, readable)?;
+            write!(f, ")")
+        },
+        Exp::Vector(v) => {
+            write!(f, "#(")?;
+            elements(f, &v.borrow())?;
+            write!(f, ")")
+        },
+        Exp::HashTable(_) => write!(f, "#<hash-table>"),
+        Exp::Lambda(_) | Exp::Closure(_) => write!(f, "#<procedure>"),
+        Exp::Native(native) => write!(f, "#<procedure {}>", native.name),
+        Exp::Builtin(name) => write!(f, "#<procedure {}>", name),
+        Exp::Macro(_) | Exp::Syntax(_) => write!(f, "#<macro>"),
+        Exp::Environment(_) => write!(f, "#<environment>"),
+        Exp::Error(error) => write!(f, "#<error {}>", error),
+        Exp::Continuation(_) => write!(f, "#<continuation>"),
+        Exp::Promise(_) => write!(f, "#<promise>"),
+        Exp::Port(port) => write!(f, "{}", port),
+        Exp::Eof => write!(f, "#<eof>"),
+        Exp::Values(values) => elements(f, values),
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum Promise {
+    /// The delayed expression with the environment it is evaluated in.
+    Delayed(Exp, Env),
+    Forced(Exp),
+}
+
+#[derive(Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ErrorObject {
+    pub message: String,
+    pub irritants: List,
+}
+
+impl fmt::Display for ErrorObject {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.message)?;
+        for irritant in &self.irritants {
+            write!(f, " {}", irritant)?;
+        }
+        Ok(())
+    }
+}
+
+/// The key of a hash table entry, a hashable copy of the key expression.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum HashKey {
+    Symbol(Symbol),
+    /// The bits of a float, so that `0.5` and `0.5` are the same key.
+    Number(u64),
+    Int(i64),
+    BigInt(Rc<BigInt>),
+    Rational(Rc<BigRational>),
+    /// The bits of the real and imaginary parts.
+    Complex(u64, u64),
+    Bool(bool),
+    String(Rc<str>),
+    Char(char),
+    Keyword(Symbol),
+    List(Vec<HashKey>),
+}
+
+impl HashKey {
+    pub fn from_exp(exp: &Exp) -> Result<HashKey> {
+        match exp {
+            Exp::Atom(Atom::Symbol(s)) => Ok(HashKey::Symbol(*s)),
+            Exp::Atom(Atom::Number(n)) => Ok(HashKey::Number(n.to_bits())),
+            Exp::Atom(Atom::Int(i)) => Ok(HashKey::Int(*i)),
+            Exp::Atom(Atom::BigInt(i)) => Ok(HashKey::BigInt(i.clone())),
+            Exp::Atom(Atom::Rational(r)) => Ok(HashKey::Rational(r.clone())),
+            Exp::Atom(Atom::Complex(c)) => Ok(HashKey::Complex(c.re.to_bits(), c.im.to_bits())),
+            Exp::Atom(Atom::Bool(b)) => Ok(HashKey::Bool(*b)),
+            Exp::Atom(Atom::String(s)) => Ok(HashKey::String(s.clone())),
+            Exp::Atom(Atom::Char(c)) => Ok(HashKey::Char(*c)),
+            Exp::Atom(Atom::Keyword(k)) => Ok(HashKey::Keyword(*k)),
+            Exp::List(l) => Ok(HashKey::List(l.iter().map(HashKey::from_exp).collect::<Result<_>>()?)),
+            _ => Err(BrewlisError::type_error("usable as a hash table key", exp)),
+        }
+    }
+
+    pub fn to_exp(&self) -> Exp {
+        match self {
+            HashKey::Symbol(s) => Exp::Atom(Atom::Symbol(*s)),
+            HashKey::Number(bits) => Exp::Atom(Atom::Number(f64::from_bits(*bits))),
+            HashKey::Int(i) => Exp::Atom(Atom::Int(*i)),
+            HashKey::BigInt(i) => Exp::Atom(Atom::BigInt(i.clone())),
+            HashKey::Rational(r) => Exp::Atom(Atom::Rational(r.clone())),
+            HashKey::Complex(re, im) => Exp::Atom(Atom::Complex(Complex64::new(f64::from_bits(*re), f64::from_bits(*im)))),
+            HashKey::Bool(b) => Exp::Atom(Atom::Bool(*b)),
+            HashKey::String(s) => Exp::Atom(Atom::String(s.clone())),
+            HashKey::Char(c) => Exp::Atom(Atom::Char(*c)),
+            HashKey::Keyword(k) => Exp::Atom(Atom::Keyword(*k)),
+            HashKey::List(l) => Exp::List(l.iter().map(HashKey::to_exp).collect()),
+        }
+    }
+}
+
+/// The parameters of a procedure, written `(a (b default) #:key c . rest)`.
+#[derive(Debug, Default)]
+pub struct Params {
+    pub required: Vec<Symbol>,
+    /// The parameters after the required ones with the expressions of
+    /// their defaults, which are evaluated when no argument is given.
+    pub optional: Vec<(Symbol, Exp)>,
+    /// The parameter bound to the list of the remaining arguments.
+    pub rest: Option<Symbol>,
+    /// The keyword parameters, each with its keyword and the expression of
+    /// its default if it may be left out. Keyword arguments are not
+    /// counted in the arity.
+    pub keywords: Vec<(Symbol, Symbol, Option<Exp>)>,
+}
+
+impl Params {
+    pub fn arity(&self) -> Arity {
+        match (self.optional.len(), self.rest) {
+            (_, Some(_)) => Arity::AtLeast(self.required.len()),
+            (0, None) => Arity::Exactly(self.required.len()),
+            (optional, None) => Arity::Range(self.required.len(), self.required.len() + optional),
+        }
+    }
+}
+
+/// A procedure created by `lambda`, closing over the environment it was
+/// defined in.
+pub struct Lambda {
+    /// The name the procedure was defined as, for errors.
+    pub name: Option<Symbol>,
+    /// The string before the body, if it is followed by more forms.
+    pub doc: Option<Rc<str>>,
+    pub params: Params,
+    pub body: List,
+    pub env: Env,
+}
+
+impl fmt::Debug for Lambda {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // The captured environment is left out, it usually contains the
+        // lambda itself.
+        f.debug_struct("Lambda")
+            .field("params", &self.params)
+            .field("body", &self.body)
+            .finish()
+    }
+}
+
+impl PartialEq for Lambda {
+    fn eq(&self, other: &Self) -> bool {
+        std::ptr::eq(self, other)
+    }
+}
+
+/// The Rust function of a native procedure.
+pub type NativeFunction = dyn Fn(&[Exp]) -> Result<Exp>;
+
+/// A procedure implemented in Rust, called with the evaluated arguments.
+pub struct NativeFn {
+    pub name: String,
+    pub function: Box<NativeFunction>,
+}
+
+impl fmt::Debug for NativeFn {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("NativeFn").field("name", &self.name).finish()
+    }
+}
+
+impl PartialEq for NativeFn {
+    fn eq(&self, other: &Self) -> bool {
+        std::ptr::eq(self, other)
+    }
+}
+
+impl Exp {
+    /// The elements of a list, or of an improper list like (a b . c) with
+    /// the tail after its dot.
+    pub fn improper_list(&self) -> Option<(Vec<Exp>, Option<&Exp>)> {
+        match self {
+            Exp::List(l) => Some((l.to_vec(), None)),
+            Exp::Pair(car, cdr) => {
+                let mut elements = vec![car.as_ref().clone()];
+                let mut tail = cdr.as_ref();
+                while let Exp::Pair(car, cdr) = tail {
+                    elements.push(car.as_ref().clone());
+                    tail = cdr;
+                }
+                Some((elements, Some(tail)))
+            },
+            _ => None,
+        }
+    }
+
+    /// Creates a procedure calling `function` with its arguments.
+    pub fn native(name: &str, function: impl Fn(&[Exp]) -> Result<Exp> + 'static) -> Exp {
+        Exp::Native(Rc::new(NativeFn { name: name.to_string(), function: Box::new(function) }))
+    }
+
+    /// Creates a vector, registered with the collector of cycles.
+    pub fn vector(items: Vec<Exp>) -> Exp {
+        let vector = Rc::new(RefCell::new(items));
+        gc::track(gc::Tracked::Vector(Rc::downgrade(&vector)));
+        Exp::Vector(vector)
+    }
+
+    /// Creates an empty hash table, registered with the collector of cycles.
+    pub fn hash_table() -> Exp {
+        let table = Rc::new(RefCell::new(HashMap::new()));
+        gc::track(gc::Tracked::HashTable(Rc::downgrade(&table)));
+        Exp::HashTable(table)
+    }
+
+    pub fn extract_symbol(self) -> Result<Symbol> {
+        if let Exp::Atom(Atom::Symbol(s)) = self {
+            Ok(s)
+        }
+        else {
+            Err(BrewlisError::type_error("a symbol", &self))
+        }
+    }
+    pub fn extract_string(self) -> Result<Rc<str>> {
+        if let Exp::Atom(Atom::String(s)) = self {
+            Ok(s)
+        }
+        else {
+            Err(BrewlisError::type_error("a string", &self))
+        }
+    }
+    /// Extracts a non-negative integer usable as an index or size.
+    pub fn extract_index(self) -> Result<usize> {
+        if let Exp::Atom(Atom::Int(i)) = self {
+            usize::try_from(i).map_err(|_| BrewlisError::type_error("a valid index", &self))
+        }
+        else {
+            Err(BrewlisError::type_error("an index", &self))
+        }
+    }
+    /// Extracts a character.
+    pub fn extract_char(self) -> Result<char> {
+        if let Exp::Atom(Atom::Char(c)) = self {
+            Ok(c)
+        }
+        else {
+            Err(BrewlisError::type_error("a character", &self))
+        }
+    }
+    /// Extracts any number, converting integers to floats.
+    pub fn extract_number(self) -> Result<f64> {
+        match self {
+            Exp::Atom(Atom::Number(n)) => Ok(n),
+            Exp::Atom(Atom::Int(i)) => Ok(i as f64),
+            Exp::Atom(Atom::BigInt(i)) => Ok(i.to_f64().unwrap_or(f64::NAN)),
+            Exp::Atom(Atom::Rational(r)) => Ok(r.to_f64().unwrap_or(f64::NAN)),
+            Exp::Atom(Atom::Complex(_)) => Err(BrewlisError::type_error("a real number", &self)),
+            _ => Err(BrewlisError::type_error("a number", &self)),
+        }
+    }
+}
+
+/// The elements of a list, with the span of the source text it was read
+/// from. The span is ignored when comparing lists.
+///
+/// Clones share the elements, which are copied only when a shared list is
+/// modified, so evaluation can pass around sub-trees of the program and
+/// procedure bodies without copying them. The tail of a list shares the
+/// elements too, it starts at a later index.
+#[derive(Clone, Default)]
+pub struct List {
+    items: Rc<Vec<Exp>>,
+    start: usize,
+    pub span: Option<Box<Span>>,
+}
+
+impl List {
+    pub fn new() -> List {
+        List::default()
+    }
+
+    pub fn with_capacity(capacity: usize) -> List {
+        Vec::with_capacity(capacity).into()
+    }
+
+    pub fn with_span(items: Vec<Exp>, span: Span) -> List {
+        List { items: Rc::new(items), start: 0, span: Some(Box::new(span)) }
+    }
+
+    /// The elements shared by the clones of the list, including any before
+    /// its start.
+    pub(crate) fn shared(&self) -> &Rc<Vec<Exp>> {
+        &self.items
+    }
+
+    /// The list without its first element, sharing the rest.
+    pub fn tail(&self) -> List {
+        self.tail_from(1)
+    }
+
+    /// The list without its first `n` elements, sharing the rest.
+    pub fn tail_from(&self, n: usize) -> List {
+        List { items: self.items.clone(), start: (self.start + n).min(self.items.len()), span: None }
+    }
+
+    /// The elements for modifying, copied first if they are shared.
+    fn items_mut(&mut self) -> &mut Vec<Exp> {
+        if self.start > 0 {
+            self.items = Rc::new(self.items[self.start..].to_vec());
+            self.start = 0;
+        }
+        Rc::make_mut(&mut self.items)
+    }
+
+    pub fn push(&mut self, exp: Exp) {
+        self.items_mut().push(exp)
+    }
+
+    pub fn insert(&mut self, index: usize, exp: Exp) {
+        self.items_mut().insert(index, exp)
+    }
+
+    pub fn to_vec(&self) -> Vec<Exp> {
+        self[..].to_vec()
+    }
+}
+
+/// Lists are dropped with a loop rather than recursively, so deeply nested
+/// lists do not overflow the stack.
+impl Drop for List {
+    fn drop(&mut self) {
+        let Some(items) = Rc::get_mut(&mut self.items) else {
+            return;
+        };
+        let mut pending = std::mem::take(items);
+        while let Some(exp) = pending.pop() {
+            if let Exp::List(mut list) = exp {
+                if let Some(items) = Rc::get_mut(&mut list.items) {
+                    pending.append(items);
+                }
+            }
+        }
+    }
+}
+
+impl Deref for List {
+    type Target = [Exp];
+
+    fn deref(&self) -> &[Exp] {
+        &self.items[self.start..]
+    }
+}
+
+impl DerefMut for List {
+    fn deref_mut(&mut self) -> &mut [Exp] {
+        self.items_mut()
+    }
+}
+
+impl From<Vec<Exp>> for List {
+    fn from(items: Vec<Exp>) -> List {
+        List { items: Rc::new(items), start: 0, span: None }
+    }
+}
+
+impl From<&[Exp]> for List {
+    fn from(items: &[Exp]) -> List {
+        items.to_vec().into()
+    }
+}
+
+impl FromIterator<Exp> for List {
+    fn from_iter<I: IntoIterator<Item = Exp>>(iter: I) -> List {
+        iter.into_iter().collect::<Vec<_>>().into()
+    }
+}
+
+impl Extend<Exp> for List {
+    fn extend<I: IntoIterator<Item = Exp>>(&mut self, iter: I) {
+        self.items_mut().extend(iter)
+    }
+}
+
+impl IntoIterator for List {
+    type Item = Exp;
+    type IntoIter = std::vec::IntoIter<Exp>;
+
+    fn into_iter(mut self) -> Self::IntoIter {
+        std::mem::take(self.items_mut()).into_iter()
+    }
+}
+
+impl<'a> IntoIterator for &'a List {
+    type Item = &'a Exp;
+    type IntoIter = std::slice::Iter<'a, Exp>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self[..].iter()
+    }
+}
+
+impl PartialEq for List {
+    fn eq(&self, other: &Self) -> bool {
+        self[..] == other[..]
+    }
+}
+
+impl fmt::Debug for List {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        eval::nested(|| self[..].fmt(f))
+    }
+}
@@ -0,0 +1,279 @@
+//! The formatter of `brewlis fmt`.
+//!
+//! Formatting works on the tokens rather than on the parsed forms, so that
+//! atoms keep their spelling (`#x1F` stays `#x1F`) and comments, which the
+//! lexer skips, are recovered from the text between tokens. A form is put
+//! on one line if it fits into the width and contains no comments.
+//! Otherwise the body of special forms like `define` and `let` is indented
+//! by two columns and the arguments of calls are aligned below the first.
+//! One blank line between forms is kept, more are squeezed into one.
+
+use logos::Logos;
+
+use crate::{error::Result, eval::nested, lexer::Token, parser::parse};
+
+/// The width `brewlis fmt` fills lines up to by default.
+pub const DEFAULT_WIDTH: usize = 80;
+
+enum Node {
+    Atom(String),
+    Comment(String),
+    /// A list or vector with its opening token, `(` or `#(`.
+    List(&'static str, Vec<Item>),
+    /// A form after a quote prefix like `'` or `,@`.
+    Prefix(String, Box<Node>),
+}
+
+struct Item {
+    node: Node,
+    /// Whether a blank line came before the item.
+    blank_before: bool,
+    /// Whether the item started on the line of the token before it, which
+    /// keeps a comment after the code it remarks on.
+    same_line: bool,
+}
+
+/// Splits the text between two tokens into its comments with whether
+/// each starts on the line of the token before and follows a blank line.
+fn comments(gap: &str, items: &mut Vec<Item>) -> (bool, bool) {
+    let mut rest = gap;
+    let mut newlines = 0;
+    loop {
+        let trimmed = rest.trim_start_matches(|c: char| c.is_whitespace() && c != '\n');
+        if let Some(after) = trimmed.strip_prefix('\n') {
+            newlines += 1;
+            rest = after;
+            continue;
+        }
+        let end = if trimmed.starts_with(';') || trimmed.starts_with("#!") {
+            trimmed.find('\n').unwrap_or(trimmed.len())
+        } else if trimmed.starts_with("#|") {
+            trimmed.find("|#").map_or(trimmed.len(), |end| end + 2)
+        } else {
+            return (newlines == 0, newlines > 1);
+        };
+        items.push(Item { node: Node::Comment(trimmed[..end].trim_end().to_string()), blank_before: newlines > 1, same_line: newlines == 0 });
+        newlines = 0;
+        rest = &trimmed[end..];
+    }
+}
+
+/// A list being read.
+struct Open {
+    /// Its opening token, `(` or `#(`, and none for the top level.
+    opening: &'static str,
+    items: Vec<Item>,
+    /// The prefixes read for the next item.
+    prefixes: Vec<String>,
+    /// Where the list or the first prefix of the next item started, as
+    /// `(same_line, blank_before)`.
+    start: (bool, bool),
+    next_start: Option<(bool, bool)>,
+}
+
+impl Open {
+    fn new(opening: &'static str, start: (bool, bool)) -> Open {
+        Open { opening, items: Vec::new(), prefixes: Vec::new(), start, next_start: None }
+    }
+}
+
+/// Reads the forms and comments of `source` into a tree of items.
+fn read(source: &str) -> Vec<Item> {
+    let mut open = vec![Open::new("", (false, false))];
+    let mut lex = Token::lexer(source);
+    let mut end = 0;
+    while let Some(token) = lex.next() {
+        let span = lex.span();
+        let current = open.last_mut().expect("the top level");
+        let gap = comments(&source[end..span.start], &mut current.items);
+        let start = *current.next_start.get_or_insert(gap);
+        end = span.end;
+        let node = match token {
+            Ok(Token::ParenOpen) => {
+                open.push(Open::new("(", start));
+                continue;
+            },
+            Ok(Token::VectorOpen) => {
+                open.push(Open::new("#(", start));
+                continue;
+            },
+            Ok(Token::Quote | Token::Quasiquote | Token::Unquote | Token::UnquoteSplicing) => {
+                current.prefixes.push(lex.slice().to_string());
+                continue;
+            },
+            Ok(Token::ParenClose) if open.len() > 1 => {
+                let list = open.pop().expect("an open list");
+                open.last_mut().expect("the top level").next_start = Some(list.start);
+                Node::List(list.opening, list.items)
+            },
+            _ => Node::Atom(lex.slice().to_string()),
+        };
+        let current = open.last_mut().expect("the top level");
+        let node = current.prefixes.drain(..).rev().fold(node, |node, prefix| Node::Prefix(prefix, Box::new(node)));
+        let (same_line, blank_before) = current.next_start.take().expect("a start");
+        current.items.push(Item { node, blank_before, same_line });
+    }
+    let mut top = open.swap_remove(0);
+    comments(&source[end..], &mut top.items);
+    top.items
+}
+
+/// How many arguments of the special form `head` go on its first line,
+/// before the body, or None if `head` is no such form.
+fn body_start(head: &str, items: &[Item]) -> Option<usize> {
+    match head {
+        "begin" => Some(0),
+        // (let name ((sym init)...) body...)
+        "let" if matches!(items.get(1), Some(Item { node: Node::Atom(_), .. })) => Some(2),
+        "do" => Some(2),
+        "define" | "define-macro" | "define-syntax" | "define-values" | "define-test" | "lambda" | "let" | "let*"
+            | "letrec" | "when" | "unless" | "while" | "case" | "guard" | "syntax-rules" => Some(1),
+        _ => None,
+    }
+}
+
+/// Whether the body of a special form or the clauses of a `cond` were
+/// written on lines of their own, which they are kept on.
+fn keeps_lines(items: &[Item]) -> bool {
+    let start = match items.first().map(|item| &item.node) {
+        Some(Node::Atom(head)) if head == "cond" => 1,
+        Some(Node::Atom(head)) => match body_start(head, items) {
+            Some(start) => start + 1,
+            None => return false,
+        },
+        _ => return false,
+    };
+    items.iter().skip(start).any(|item| !item.same_line)
+}
+
+/// The form on one line if it fits into `room` columns, or None if it
+/// does not or contains a comment or a line break or keeps its lines.
+/// Giving up once the room is used keeps deeply nested forms from being
+/// flattened at each level.
+fn flat(node: &Node, room: usize) -> Option<String> {
+    nested(|| {
+        let text = match node {
+            Node::Atom(text) if !text.contains('\n') => text.clone(),
+            Node::Atom(_) | Node::Comment(_) => return None,
+            Node::List(_, items) if keeps_lines(items) => return None,
+            Node::List(opening, items) => {
+                let mut room = room.checked_sub(opening.len() + 1)?;
+                let mut flats = Vec::with_capacity(items.len());
+                for (i, item) in items.iter().enumerate() {
+                    if i > 0 {
+                        room = room.checked_sub(1)?;
+                    }
+                    let flat = flat(&item.node, room)?;
+                    room -= flat.chars().count();
+                    flats.push(flat);
+                }
+                return Some(format!("{}{})", opening, flats.join(" ")));
+            },
+            Node::Prefix(prefix, node) => format!("{}{}", prefix, flat(node, room.checked_sub(prefix.chars().count())?)?),
+        };
+        (text.chars().count() <= room).then_some(text)
+    })
+}
+
+struct Printer {
+    out: String,
+    width: usize,
+}
+
+impl Printer {
+    fn column(&self) -> usize {
+        let start = self.out.rfind('\n').map_or(0, |i| i + 1);
+        self.out[start..].chars().count()
+    }
+
+    fn newline(&mut self, indent: usize) {
+        self.out.truncate(self.out.trim_end_matches(' ').len());
+        self.out.push('\n');
+        self.out.push_str(&" ".repeat(indent));
+    }
+
+    /// Prints `node`, on a new stack segment if the current one is almost
+    /// used up, as the forms can nest as deeply as the parser allows.
+    fn node(&mut self, node: &Node) {
+        nested(|| self.node_unguarded(node));
+    }
+
+    fn node_unguarded(&mut self, node: &Node) {
+        let column = self.column();
+        if let Some(flat) = flat(node, self.width.saturating_sub(column)) {
+            self.out.push_str(&flat);
+            return;
+        }
+        match node {
+            Node::Atom(text) | Node::Comment(text) => self.out.push_str(text),
+            Node::Prefix(prefix, node) => {
+                self.out.push_str(prefix);
+                self.node(node);
+            },
+            Node::List(opening, items) => {
+                self.out.push_str(opening);
+                self.list(items, column);
+                self.out.push(')');
+            },
+        }
+    }
+
+    /// Prints the items of a list whose opening token starts at `column`.
+    fn list(&mut self, items: &[Item], column: usize) {
+        let inner = self.column();
+        let (first_line, indent) = match items.first().map(|item| &item.node) {
+            Some(Node::Atom(head)) if !head.starts_with(|c: char| c.is_ascii_digit() || c == '"' || c == '#') => match body_start(head, items) {
+                Some(start) => (start + 1, column + 2),
+                // The arguments of a call are aligned below the first
+                None if inner + head.chars().count() < self.width / 2 => (2, inner + head.chars().count() + 1),
+                None => (1, column + 2),
+            },
+            // Data like '(1 2 3)
+            _ => (1, inner),
+        };
+        // Comments end the line
+        let first_line = items.iter().position(|item| matches!(item.node, Node::Comment(_))).map_or(first_line, |i| first_line.min(i.max(1)));
+        for (i, item) in items.iter().enumerate() {
+            if i > 0 {
+                match &item.node {
+                    Node::Comment(_) if item.same_line => self.out.push(' '),
+                    _ if i < first_line => self.out.push(' '),
+                    _ => {
+                        if item.blank_before {
+                            self.newline(0);
+                        }
+                        self.newline(indent);
+                    },
+                }
+            }
+            self.node(&item.node);
+        }
+        if let Some(Item { node: Node::Comment(_), .. }) = items.last() {
+            self.newline(indent);
+        }
+    }
+}
+
+/// Formats the program `source` with lines up to `width` columns, where
+/// the forms permit. Fails if `source` does not parse.
+pub fn format(source: &str, width: usize) -> Result<String> {
+    parse(source)?;
+    let mut printer = Printer { out: String::new(), width };
+    for (i, item) in read(source).iter().enumerate() {
+        if i > 0 {
+            if matches!(item.node, Node::Comment(_)) && item.same_line {
+                printer.out.push(' ');
+            } else {
+                printer.newline(0);
+                if item.blank_before {
+                    printer.newline(0);
+                }
+            }
+        }
+        printer.node(&item.node);
+    }
+    if !printer.out.is_empty() {
+        printer.out.push('\n');
+    }
+    Ok(printer.out)
+}
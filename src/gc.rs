@@ -0,0 +1,313 @@
+//! Collection of reference cycles.
+//!
+//! Values are reference counted and freed as soon as their last reference
+//! is dropped. That never happens for values referring to each other in a
+//! cycle: a procedure defined in a `let` keeps the frame it is bound in
+//! alive, a vector can contain itself. The collector finds such cycles by
+//! trial deletion. Starting from the frames, vectors, hash tables and
+//! compiled closures created since the last collection and those alive
+//! after it, it walks everything they refer to and counts how many of the
+//! references to each shared value come from the values walked. A value
+//! with more references than that is used from outside, by the host
+//! program or an evaluation in progress, and it is alive together with
+//! everything it refers to. The frames, vectors, hash tables, promises and
+//! captured variables of the rest are emptied, which breaks their cycles
+//! and lets reference counting free them.
+
+use std::{cell::{Cell, RefCell}, collections::HashMap, mem, rc::{Rc, Weak}};
+
+use crate::{env::Frame, exp::{Atom, ErrorObject, Exp, HashKey, Lambda, Promise}, vm::Closure};
+
+/// Collections are due once this many containers are tracked, or twice as
+/// many as survived the last collection.
+const MIN_THRESHOLD: usize = 10_000;
+
+thread_local! {
+    static TRACKED: RefCell<Vec<Tracked>> = const { RefCell::new(Vec::new()) };
+    static THRESHOLD: Cell<usize> = const { Cell::new(MIN_THRESHOLD) };
+    /// The containers tracked since the thread started.
+    static ALLOCATIONS: Cell<u64> = const { Cell::new(0) };
+}
+
+/// A container that can be part of a cycle without anything else
+/// referring to it.
+pub(crate) enum Tracked {
+    Frame(Weak<RefCell<Frame>>),
+    Vector(Weak<RefCell<Vec<Exp>>>),
+    HashTable(Weak<RefCell<HashMap<HashKey, Exp>>>),
+    Closure(Weak<Closure>),
+}
+
+impl Tracked {
+    fn is_alive(&self) -> bool {
+        match self {
+            Tracked::Frame(weak) => weak.strong_count() > 0,
+            Tracked::Vector(weak) => weak.strong_count() > 0,
+            Tracked::HashTable(weak) => weak.strong_count() > 0,
+            Tracked::Closure(weak) => weak.strong_count() > 0,
+        }
+    }
+
+    fn upgrade(&self) -> Option<Node> {
+        match self {
+            Tracked::Frame(weak) => weak.upgrade().map(Node::Frame),
+            Tracked::Vector(weak) => weak.upgrade().map(Node::Vector),
+            Tracked::HashTable(weak) => weak.upgrade().map(Node::HashTable),
+            Tracked::Closure(weak) => weak.upgrade().map(Node::Closure),
+        }
+    }
+}
+
+/// Registers a new container with the collector.
+pub(crate) fn track(tracked: Tracked) {
+    ALLOCATIONS.with(|allocations| allocations.set(allocations.get() + 1));
+    TRACKED.with(|all| {
+        let mut all = all.borrow_mut();
+        all.push(tracked);
+        // Weak references keep the memory of freed containers, so they are
+        // dropped regularly even while no collection can run.
+        if all.len() >= 2 * THRESHOLD.get() {
+            all.retain(Tracked::is_alive);
+            THRESHOLD.set(all.len().max(MIN_THRESHOLD));
+        }
+    });
+}
+
+/// How many frames, vectors, hash tables and closures with captured
+/// variables were created on this thread.
+pub fn allocations() -> u64 {
+    ALLOCATIONS.with(Cell::get)
+}
+
+/// Whether enough containers were created since the last collection to
+/// make another one worthwhile.
+pub fn is_due() -> bool {
+    TRACKED.with(|all| all.borrow().len() >= THRESHOLD.get())
+}
+
+/// Frees the values only reachable from cycles and returns how many frames,
+/// vectors, hash tables, promises and captured variables were emptied.
+pub fn collect() -> usize {
+    let tracked = TRACKED.with(|all| mem::take(&mut *all.borrow_mut()));
+    let mut heap = Heap::default();
+    for node in tracked.iter().filter_map(Tracked::upgrade) {
+        heap.insert(node);
+    }
+    drop(tracked);
+
+    // Count the references between the nodes, adding those found on the way.
+    let mut i = 0;
+    while i < heap.nodes.len() {
+        let mut children = Vec::new();
+        let complete = heap.nodes[i].children(&mut children);
+        let edges = children.into_iter().map(|child| {
+            let j = heap.insert(child);
+            heap.internal[j] += 1;
+            j
+        }).collect();
+        heap.edges.push(edges);
+        // A node borrowed for modification is in use and its references are
+        // unknown, so it is alive.
+        heap.alive.push(!complete);
+        i += 1;
+    }
+
+    // Everything reachable from the nodes referred to from outside is alive.
+    let mut pending: Vec<usize> = (0..heap.nodes.len())
+        .filter(|&i| heap.alive[i] || heap.nodes[i].strong_count() - 1 > heap.internal[i])
+        .collect();
+    for &i in &pending {
+        heap.alive[i] = true;
+    }
+    while let Some(i) = pending.pop() {
+        for &j in &heap.edges[i] {
+            if !heap.alive[j] {
+                heap.alive[j] = true;
+                pending.push(j);
+            }
+        }
+    }
+
+    let mut freed = 0;
+    let mut garbage = Vec::new();
+    let mut survivors = Vec::new();
+    for (node, alive) in heap.nodes.iter().zip(heap.alive) {
+        if alive {
+            survivors.extend(node.tracked());
+        } else if node.clear(&mut garbage) {
+            freed += 1;
+        }
+    }
+    THRESHOLD.set((2 * survivors.len()).max(MIN_THRESHOLD));
+    TRACKED.with(|all| {
+        let mut all = all.borrow_mut();
+        survivors.append(&mut all);
+        *all = survivors;
+    });
+    // The contents are dropped last, after no node is borrowed anymore.
+    drop(heap.nodes);
+    drop(garbage);
+    freed
+}
+
+/// A shared value found by the collector.
+enum Node {
+    Frame(Rc<RefCell<Frame>>),
+    Vector(Rc<RefCell<Vec<Exp>>>),
+    HashTable(Rc<RefCell<HashMap<HashKey, Exp>>>),
+    Closure(Rc<Closure>),
+    Lambda(Rc<Lambda>),
+    Promise(Rc<RefCell<Promise>>),
+    Error(Rc<ErrorObject>),
+    List(Rc<Vec<Exp>>),
+    /// A variable captured by a compiled closure.
+    Variable(Rc<RefCell<Exp>>),
+}
+
+impl Node {
+    fn address(&self) -> usize {
+        match self {
+            Node::Frame(rc) => Rc::as_ptr(rc) as *const () as usize,
+            Node::Vector(rc) => Rc::as_ptr(rc) as *const () as usize,
+            Node::HashTable(rc) => Rc::as_ptr(rc) as *const () as usize,
+            Node::Closure(rc) => Rc::as_ptr(rc) as *const () as usize,
+            Node::Lambda(rc) => Rc::as_ptr(rc) as *const () as usize,
+            Node::Promise(rc) => Rc::as_ptr(rc) as *const () as usize,
+            Node::Error(rc) => Rc::as_ptr(rc) as *const () as usize,
+            Node::List(rc) => Rc::as_ptr(rc) as *const () as usize,
+            Node::Variable(rc) => Rc::as_ptr(rc) as *const () as usize,
+        }
+    }
+
+    fn strong_count(&self) -> usize {
+        match self {
+            Node::Frame(rc) => Rc::strong_count(rc),
+            Node::Vector(rc) => Rc::strong_count(rc),
+            Node::HashTable(rc) => Rc::strong_count(rc),
+            Node::Closure(rc) => Rc::strong_count(rc),
+            Node::Lambda(rc) => Rc::strong_count(rc),
+            Node::Promise(rc) => Rc::strong_count(rc),
+            Node::Error(rc) => Rc::strong_count(rc),
+            Node::List(rc) => Rc::strong_count(rc),
+            Node::Variable(rc) => Rc::strong_count(rc),
+        }
+    }
+
+    fn tracked(&self) -> Option<Tracked> {
+        match self {
+            Node::Frame(rc) => Some(Tracked::Frame(Rc::downgrade(rc))),
+            Node::Vector(rc) => Some(Tracked::Vector(Rc::downgrade(rc))),
+            Node::HashTable(rc) => Some(Tracked::HashTable(Rc::downgrade(rc))),
+            Node::Closure(rc) => Some(Tracked::Closure(Rc::downgrade(rc))),
+            _ => None,
+        }
+    }
+
+    /// Adds the nodes this node refers to to `children`. Returns false if
+    /// the node is borrowed for modification and cannot be looked into.
+    fn children(&self, children: &mut Vec<Node>) -> bool {
+        match self {
+            Node::Frame(frame) => {
+                let Ok(frame) = frame.try_borrow() else { return false };
+                frame.vars.values().for_each(|value| references(value, children));
+                children.extend(frame.parent.as_ref().map(|parent| Node::Frame(parent.0.clone())));
+            },
+            Node::Vector(vector) => {
+                let Ok(vector) = vector.try_borrow() else { return false };
+                vector.iter().for_each(|value| references(value, children));
+            },
+            Node::HashTable(table) => {
+                let Ok(table) = table.try_borrow() else { return false };
+                table.values().for_each(|value| references(value, children));
+            },
+            Node::Closure(closure) => {
+                children.extend(closure.upvalues.iter().map(|variable| Node::Variable(variable.clone())));
+                children.push(Node::Frame(closure.env.0.clone()));
+            },
+            Node::Lambda(lambda) => children.push(Node::Frame(lambda.env.0.clone())),
+            Node::Promise(promise) => {
+                let Ok(promise) = promise.try_borrow() else { return false };
+                match &*promise {
+                    Promise::Delayed(_, env) => children.push(Node::Frame(env.0.clone())),
+                    Promise::Forced(value) => references(value, children),
+                }
+            },
+            Node::Error(error) => children.push(Node::List(error.irritants.shared().clone())),
+            Node::List(items) => items.iter().for_each(|value| references(value, children)),
+            Node::Variable(variable) => {
+                let Ok(value) = variable.try_borrow() else { return false };
+                references(&value, children);
+            },
+        }
+        true
+    }
+
+    /// Empties a mutable node, moving its contents to `garbage`. Returns
+    /// whether the node was one that could be emptied.
+    fn clear(&self, garbage: &mut Vec<Exp>) -> bool {
+        match self {
+            Node::Frame(frame) => {
+                let mut frame = frame.borrow_mut();
+                garbage.extend(mem::take(&mut frame.vars).into_values());
+                garbage.extend(frame.parent.take().map(Exp::Environment));
+            },
+            Node::Vector(vector) => garbage.append(&mut vector.borrow_mut()),
+            Node::HashTable(table) => garbage.extend(mem::take(&mut *table.borrow_mut()).into_values()),
+            Node::Promise(promise) => {
+                let forced = Promise::Forced(Exp::Atom(Atom::Bool(false)));
+                match mem::replace(&mut *promise.borrow_mut(), forced) {
+                    Promise::Delayed(exp, env) => garbage.extend([exp, Exp::Environment(env)]),
+                    Promise::Forced(value) => garbage.push(value),
+                }
+            },
+            Node::Variable(variable) => garbage.push(variable.replace(Exp::Atom(Atom::Bool(false)))),
+            Node::Closure(_) | Node::Lambda(_) | Node::Error(_) | Node::List(_) => return false,
+        }
+        true
+    }
+}
+
+/// Adds the shared values `exp` refers to to `children`.
+fn references(exp: &Exp, children: &mut Vec<Node>) {
+    let mut pending = vec![exp];
+    while let Some(exp) = pending.pop() {
+        match exp {
+            Exp::List(list) | Exp::Values(list) => children.push(Node::List(list.shared().clone())),
+            Exp::Pair(car, cdr) => pending.extend([&**car, &**cdr]),
+            Exp::Vector(vector) => children.push(Node::Vector(vector.clone())),
+            Exp::HashTable(table) => children.push(Node::HashTable(table.clone())),
+            Exp::Lambda(lambda) | Exp::Macro(lambda) => children.push(Node::Lambda(lambda.clone())),
+            Exp::Closure(closure) => children.push(Node::Closure(closure.clone())),
+            Exp::Environment(env) => children.push(Node::Frame(env.0.clone())),
+            Exp::Error(error) => children.push(Node::Error(error.clone())),
+            Exp::Promise(promise) => children.push(Node::Promise(promise.clone())),
+            // The values captured by native procedures are not known, those
+            // reachable from them stay alive as used from outside.
+            Exp::Atom(_) | Exp::Syntax(_) | Exp::Native(_) | Exp::Builtin(_) | Exp::Continuation(_) | Exp::Port(_) | Exp::Eof => {},
+        }
+    }
+}
+
+/// The nodes found so far, with the number of references to each from the
+/// other nodes.
+#[derive(Default)]
+struct Heap {
+    nodes: Vec<Node>,
+    indices: HashMap<usize, usize>,
+    internal: Vec<usize>,
+    edges: Vec<Vec<usize>>,
+    alive: Vec<bool>,
+}
+
+impl Heap {
+    /// Adds `node` unless it was found before and returns its index.
+    fn insert(&mut self, node: Node) -> usize {
+        let next = self.nodes.len();
+        let index = *self.indices.entry(node.address()).or_insert(next);
+        if index == next {
+            self.nodes.push(node);
+            self.internal.push(0);
+        }
+        index
+    }
+}
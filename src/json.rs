@@ -0,0 +1,86 @@
+//! Conversion between JSON text and brewlis values, for `json-parse` and
+//! `json-stringify`.
+//!
+//! Arrays become lists, objects hash tables with string keys and `null` the
+//! symbol `null`. Going the other way, lists and vectors become arrays and
+//! characters and symbols other than `null` strings.
+
+use std::collections::HashMap;
+
+use num_bigint::BigInt;
+use num_traits::ToPrimitive;
+use serde_json::{Map, Number, Value};
+
+use crate::{error::{BrewlisError, Result, eval_error}, exp::{Atom, Exp, HashKey}, symbol::Symbol};
+
+/// Parses the JSON document `text`.
+pub fn parse(text: &str) -> Result<Exp> {
+    let value: Value = serde_json::from_str(text).map_err(|err| eval_error!("Invalid JSON: {}", err))?;
+    Ok(from_json(value))
+}
+
+/// Formats `exp` as a JSON document.
+pub fn stringify(exp: &Exp) -> Result<String> {
+    Ok(to_json(exp)?.to_string())
+}
+
+fn from_json(value: Value) -> Exp {
+    match value {
+        Value::Null => Exp::Atom(Atom::Symbol(Symbol::from("null"))),
+        Value::Bool(b) => Exp::Atom(Atom::Bool(b)),
+        Value::Number(n) => match (n.as_i64(), n.as_str().parse::<BigInt>()) {
+            (Some(i), _) => Exp::Atom(Atom::Int(i)),
+            (None, Ok(i)) => Exp::Atom(Atom::BigInt(i.into())),
+            (None, Err(_)) => Exp::Atom(Atom::Number(n.as_str().parse().unwrap_or(f64::NAN))),
+        },
+        Value::String(s) => Exp::Atom(Atom::String(s.into())),
+        Value::Array(items) => Exp::List(items.into_iter().map(from_json).collect()),
+        Value::Object(members) => {
+            let table = Exp::hash_table();
+            if let Exp::HashTable(entries) = &table {
+                let members = members.into_iter().map(|(key, value)| (HashKey::String(key.into()), from_json(value)));
+                entries.borrow_mut().extend(members);
+            }
+            table
+        },
+    }
+}
+
+fn to_json(exp: &Exp) -> Result<Value> {
+    match exp {
+        Exp::Atom(Atom::Symbol(s)) if *s == "null" => Ok(Value::Null),
+        Exp::Atom(Atom::Symbol(s)) => Ok(Value::String(s.to_string())),
+        Exp::Atom(Atom::Bool(b)) => Ok(Value::Bool(*b)),
+        Exp::Atom(Atom::Int(i)) => Ok(Value::Number((*i).into())),
+        Exp::Atom(Atom::Number(n)) => number(*n, exp),
+        Exp::Atom(Atom::BigInt(i)) => Ok(Value::Number(i.to_string().parse().map_err(|err| eval_error!("Invalid JSON number: {}", err))?)),
+        Exp::Atom(Atom::Rational(r)) => number(r.to_f64().unwrap_or(f64::NAN), exp),
+        Exp::Atom(Atom::String(s)) => Ok(Value::String(s.to_string())),
+        Exp::Atom(Atom::Char(c)) => Ok(Value::String(c.to_string())),
+        Exp::List(items) => Ok(Value::Array(items.iter().map(to_json).collect::<Result<_>>()?)),
+        Exp::Vector(items) => Ok(Value::Array(items.borrow().iter().map(to_json).collect::<Result<_>>()?)),
+        Exp::HashTable(entries) => object(&entries.borrow()),
+        _ => Err(BrewlisError::type_error("convertible to JSON", exp)),
+    }
+}
+
+/// JSON has no infinite or NaN numbers.
+fn number(n: f64, exp: &Exp) -> Result<Value> {
+    Number::from_f64(n).map(Value::Number).ok_or(BrewlisError::type_error("convertible to JSON", exp))
+}
+
+/// The members end up sorted by key, so a table is always formatted the
+/// same.
+fn object(entries: &HashMap<HashKey, Exp>) -> Result<Value> {
+    let members = entries.iter().map(|(key, value)| {
+        let key = match key {
+            HashKey::String(s) => s.to_string(),
+            HashKey::Symbol(s) => s.to_string(),
+            HashKey::Char(c) => c.to_string(),
+            HashKey::Int(_) | HashKey::BigInt(_) | HashKey::Rational(_) | HashKey::Number(_) => key.to_exp().to_string(),
+            _ => return Err(BrewlisError::type_error("a JSON object key", &key.to_exp())),
+        };
+        Ok((key, to_json(value)?))
+    }).collect::<Result<Map<_, _>>>()?;
+    Ok(Value::Object(members))
+}
@@ -0,0 +1,80 @@
+use logos::Logos;
+
+#[derive(Logos, Debug, PartialEq)]
+#[logos(skip r"[ \t\n\f]+")] // Ignore this regex pattern between tokens
+#[logos(skip r";[^\n]*")] // Line comments
+#[logos(skip r"#\|[^|]*\|+([^|#][^|]*\|+)*#")] // Block comments
+#[logos(skip r"#![^\n]*")] // Shebang lines of executable scripts
+pub enum Token {
+    #[token("(")]
+    ParenOpen,
+    #[token(")")]
+    ParenClose,
+    // The dot before the last element of an improper list like (a b . c)
+    #[token(".")]
+    Dot,
+    #[token("#(")]
+    VectorOpen,
+    #[token("'")]
+    Quote,
+    #[token("`")]
+    Quasiquote,
+    #[token(",")]
+    Unquote,
+    #[token(",@")]
+    UnquoteSplicing,
+
+    #[token("#t")]
+    #[token("#true")]
+    True,
+    #[token("#f")]
+    #[token("#false")]
+    False,
+
+    #[regex("[a-zA-Z][a-zA-Z0-9!?*<>=/_+-]*")]
+    #[token("...")]
+    #[token("_")]
+    // The results of the REPL, $1, $2 and so on, and $ for the latest
+    #[regex(r"\$[0-9]*")]
+    Str,
+
+    // A name qualified by its module like my::utils::helper, the same symbol
+    // as my/utils/helper
+    #[regex("[a-zA-Z][a-zA-Z0-9!?*<>=/_+-]*(::[a-zA-Z][a-zA-Z0-9!?*<>=/_+-]*)+")]
+    StrQualified,
+
+    #[regex("[+-]?([0-9]+[.]?[0-9]*|[.][0-9]+)([eE][+-]?[0-9]+)?")]
+    #[token("+inf.0")]
+    #[token("-inf.0")]
+    #[token("+nan.0")]
+    #[token("-nan.0")]
+    StrFloat,
+
+    #[regex("[+-]?[0-9]+/[0-9]+")]
+    StrRational,
+
+    // A real part and an imaginary part like 3+4i, 2.5-1e3i or +i, or only
+    // an imaginary part like 4i
+    #[regex("([+-]?([0-9]+[.]?[0-9]*|[.][0-9]+)([eE][+-]?[0-9]+)?)?[+-](([0-9]+[.]?[0-9]*|[.][0-9]+)([eE][+-]?[0-9]+)?)?i")]
+    #[regex("([0-9]+[.]?[0-9]*|[.][0-9]+)([eE][+-]?[0-9]+)?i")]
+    // Without the i, which the parser reports as an invalid number
+    #[regex("[+-]?([0-9]+[.]?[0-9]*|[.][0-9]+)([eE][+-]?[0-9]+)?[+-]([0-9]+[.]?[0-9]*|[.][0-9]+)([eE][+-]?[0-9]+)?")]
+    StrComplex,
+
+    // The digits are checked when parsing, so #xFG is an invalid number
+    // rather than #xF followed by the symbol G
+    #[regex("#[xXoObBdD][+-]?[0-9a-zA-Z]+(/[0-9a-zA-Z]+)?")]
+    StrRadix,
+
+    #[regex("[>^<=+*/-]+")]
+    StrOperation,
+
+    #[regex(r#""([^"\\]|\\.)*""#)]
+    StringLiteral,
+
+    #[regex(r"#\\([a-zA-Z]+|.)")]
+    CharLiteral,
+
+    #[regex("#:[a-zA-Z][a-zA-Z0-9!?*<>=/_+-]*")]
+    Keyword,
+}
@@ -0,0 +1,174 @@
+//! Brewlis: a simple Lisp interpreter.
+//!
+//! The [`Interpreter`] bundles a persistent environment with the lexer,
+//! parser and evaluator, so brewlis can be embedded in other programs:
+//!
+//! ```
+//! let mut interpreter = brewlis::Interpreter::new();
+//! interpreter.eval_str("(define x 2)").unwrap();
+//! let result = interpreter.eval_str("(* x 21)").unwrap();
+//! assert_eq!(result, brewlis::Exp::Atom(brewlis::Atom::Int(42)));
+//! ```
+
+pub mod builtins;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod capi;
+pub mod debug;
+pub mod env;
+pub mod error;
+pub mod eval;
+pub mod exp;
+pub mod format;
+pub mod gc;
+pub mod json;
+pub mod lexer;
+pub mod lint;
+pub mod module;
+pub mod numeric;
+pub mod parser;
+pub mod plugin;
+pub mod port;
+pub mod process;
+pub mod random;
+#[cfg(feature = "serde")]
+mod serialize;
+pub mod span;
+pub mod symbol;
+pub mod syntax_rules;
+pub mod testing;
+pub mod time;
+pub mod trace;
+pub mod vm;
+#[cfg(target_arch = "wasm32")]
+pub mod wasm;
+
+pub use env::Env;
+pub use error::{BrewlisError, Result};
+pub use exp::{Atom, Exp, List};
+pub use symbol::Symbol;
+
+pub struct Interpreter {
+    env: Env,
+    /// Whether forms are compiled and run on the bytecode VM.
+    vm: bool,
+}
+
+impl Interpreter {
+    pub fn new() -> Self {
+        Interpreter { env: env::standard_env(), vm: false }
+    }
+
+    /// Creates an interpreter without the procedures of the prelude.
+    pub fn bare() -> Self {
+        Interpreter { env: env::bare_env(), vm: false }
+    }
+
+    /// Makes the interpreter run forms on the bytecode VM instead of the
+    /// tree-walking evaluator.
+    pub fn use_vm(&mut self, enabled: bool) {
+        self.vm = enabled;
+    }
+
+    /// Sets how deeply evaluation may nest before it fails with "maximum
+    /// recursion depth exceeded", for all interpreters of the thread. The
+    /// default is [`eval::DEFAULT_MAX_DEPTH`].
+    pub fn set_max_depth(&mut self, depth: usize) {
+        eval::set_max_depth(depth);
+    }
+
+    /// Makes `sqrt` and logarithms of negative numbers return complex
+    /// numbers instead of NaN, for all interpreters of the thread.
+    pub fn set_complex_results(&mut self, enabled: bool) {
+        numeric::set_complex_results(enabled);
+    }
+
+    /// Allows `system` and `process-output` to run shell commands, for all
+    /// interpreters of the thread. Commands are disabled by default.
+    pub fn allow_exec(&mut self, enabled: bool) {
+        process::set_allow_exec(enabled);
+    }
+
+    /// Sets the list `(command-line-arguments)` returns, for all
+    /// interpreters of the thread.
+    pub fn set_command_line_arguments(&mut self, arguments: Vec<String>) {
+        process::set_arguments(arguments);
+    }
+
+    /// Prints every call of a procedure written in brewlis and its value,
+    /// like `(trace f)` for all procedures, for all interpreters of the
+    /// thread.
+    pub fn set_trace(&mut self, enabled: bool) {
+        trace::set_trace_all(enabled);
+    }
+
+    /// Installs `debugger`, which evaluation calls at `(break)` and while
+    /// stepping, for all interpreters of the thread. See [`debug`].
+    pub fn set_debugger(&mut self, debugger: Option<debug::Debugger>) {
+        debug::set_debugger(debugger);
+    }
+
+    /// Makes evaluation call the debugger where an error is raised, for
+    /// all interpreters of the thread.
+    pub fn set_break_on_error(&mut self, enabled: bool) {
+        debug::set_break_on_error(enabled);
+    }
+
+    /// Binds `name` to a procedure implemented by `function`, which is
+    /// called with the evaluated arguments:
+    ///
+    /// ```
+    /// use brewlis::{Atom, BrewlisError, Exp, Interpreter};
+    ///
+    /// let mut interpreter = Interpreter::new();
+    /// interpreter.register_fn("hypot", |args: &[Exp]| match args {
+    ///     [Exp::Atom(Atom::Number(x)), Exp::Atom(Atom::Number(y))] => Ok(Exp::Atom(Atom::Number(x.hypot(*y)))),
+    ///     [x, _] | [x] => Err(BrewlisError::type_error("a number", x)),
+    ///     _ => Err(BrewlisError::arity("hypot", brewlis::builtins::Arity::Exactly(2), args.len())),
+    /// });
+    /// let result = interpreter.eval_str("(hypot 3.0 4.0)").unwrap();
+    /// assert_eq!(result, Exp::Atom(Atom::Number(5.0)));
+    /// ```
+    pub fn register_fn(&mut self, name: &str, function: impl Fn(&[Exp]) -> Result<Exp> + 'static) {
+        self.env.define(Symbol::from(name), Exp::native(name, function));
+    }
+
+    /// Evaluates the top-level forms of `program` in order and returns the
+    /// value of the last.
+    pub fn eval_str(&mut self, program: &str) -> Result<Exp> {
+        let forms = parser::parse(program)?;
+        let (last, init) = forms.split_last().ok_or(error::parse_error!("No expression found: {}", program))?;
+        for exp in init {
+            self.eval(exp)?;
+        }
+        self.eval(last)
+    }
+
+    /// Evaluates the forms of the file at `path`, like `(load path)`.
+    pub fn load(&mut self, path: &str) -> Result<Exp> {
+        eval::load_with(path, &self.env, if self.vm { vm::eval } else { eval::eval })
+    }
+
+    /// Evaluates `exp` in the environment of the interpreter. Afterwards,
+    /// reference cycles are collected if enough new values were created.
+    pub fn eval(&mut self, exp: &Exp) -> Result<Exp> {
+        let result = if self.vm {
+            vm::eval(exp, &self.env)
+        } else {
+            eval::eval(exp, &self.env)
+        };
+        if gc::is_due() {
+            gc::collect();
+        }
+        result
+    }
+
+    pub fn env(&self) -> &Env {
+        &self.env
+    }
+}
+
+impl Default for Interpreter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,448 @@
+//! The linter of `brewlis lint`.
+//!
+//! It walks the forms of a program without evaluating them and warns
+//! about mistakes that show in the forms alone: symbols bound nowhere,
+//! calls of builtins with the wrong number of arguments, defines that are
+//! never used and `if` forms without an alternative. The arguments of macro calls are not checked, since the
+//! macro decides what they mean.
+
+use std::collections::HashSet;
+
+use crate::{builtins::lookup_builtin, env::Env, error::BrewlisError, eval::nested, exp::{Atom, Exp, List}, module, span::Span, symbol::Symbol};
+
+pub struct Warning {
+    pub message: String,
+    /// The span of the innermost list the warning is about, None for a
+    /// symbol at the top level.
+    pub span: Option<Span>,
+}
+
+struct Binding {
+    name: Symbol,
+    /// The span of the `define` of the binding, None for parameters and
+    /// other bindings which need not be used.
+    define: Option<Option<Span>>,
+    used: bool,
+}
+
+struct Linter<'a> {
+    /// The environment the program is meant to run in, with the prelude.
+    env: &'a Env,
+    /// The bindings of the program in scope, the top level first.
+    scopes: Vec<Vec<Binding>>,
+    macros: HashSet<Symbol>,
+    warnings: Vec<Warning>,
+}
+
+fn symbol(exp: &Exp) -> Option<Symbol> {
+    match exp {
+        Exp::Atom(Atom::Symbol(symbol)) => Some(*symbol),
+        _ => None,
+    }
+}
+
+fn symbols(exps: &[Exp]) -> Vec<Symbol> {
+    exps.iter().filter_map(symbol).collect()
+}
+
+/// The elements of the list or improper list `exp`, like a parameter list
+/// with its rest parameter after the dot.
+fn elements(exp: &Exp) -> Option<Vec<Exp>> {
+    let (mut elements, tail) = exp.improper_list()?;
+    elements.extend(tail.cloned());
+    Some(elements)
+}
+
+/// The symbols of the `(sym init...)` lists of `let` and `do`, with the
+/// rest of each list.
+fn bindings(exp: Option<&Exp>) -> Vec<(Symbol, &[Exp])> {
+    let Some(Exp::List(bindings)) = exp else {
+        return Vec::new();
+    };
+    bindings.iter().filter_map(|binding| match binding {
+        Exp::List(binding) => Some((symbol(binding.first()?)?, &binding[1..])),
+        _ => None,
+    }).collect()
+}
+
+impl Linter<'_> {
+    fn warn(&mut self, message: String, span: Option<&Span>) {
+        self.warnings.push(Warning { message, span: span.cloned() });
+    }
+
+    fn push_scope(&mut self, params: Vec<Symbol>) {
+        self.scopes.push(params.into_iter().map(|name| Binding { name, define: None, used: false }).collect());
+    }
+
+    /// Leaves the innermost scope, warning about its unused defines.
+    fn pop_scope(&mut self) {
+        for binding in self.scopes.pop().expect("a scope") {
+            if let Some(span) = binding.define.filter(|_| !binding.used) {
+                self.warn(format!("'{}' is defined but never used", binding.name), span.as_ref());
+            }
+        }
+    }
+
+    /// Binds the parameter `name` in the innermost scope.
+    fn bind(&mut self, name: Symbol) {
+        self.scopes.last_mut().expect("a scope").push(Binding { name, define: None, used: false });
+    }
+
+    fn define(&mut self, name: Symbol, span: Option<&Span>) {
+        let scope = self.scopes.last_mut().expect("a scope");
+        if !scope.iter().any(|binding| binding.name == name) {
+            scope.push(Binding { name, define: Some(span.cloned()), used: false });
+        }
+    }
+
+    /// Marks the innermost binding of `name` in the program as used.
+    /// Returns false if the program does not bind `name`.
+    fn resolve(&mut self, name: Symbol) -> bool {
+        let binding = self.scopes.iter_mut().rev().find_map(|scope| scope.iter_mut().find(|binding| binding.name == name));
+        binding.map(|binding| binding.used = true).is_some()
+    }
+
+    fn reference(&mut self, name: Symbol, span: Option<&Span>) {
+        if !self.resolve(name) && !self.env.contains(&name) && lookup_builtin(&name).is_none() {
+            self.warn(format!("'{}' is unbound", name), span);
+        }
+    }
+
+    /// Binds the names the list `l` defines or imports in the current
+    /// scope, so that the forms of a body can refer to the defines after
+    /// them.
+    fn declare(&mut self, l: &List) {
+        let span = l.span.as_deref();
+        let Some(head) = l.first().and_then(symbol) else {
+            return;
+        };
+        let name = match (head.as_str(), l.get(1)) {
+            ("define" | "define-macro", Some(signature @ (Exp::List(_) | Exp::Pair(..)))) => elements(signature).and_then(|signature| signature.first().and_then(symbol)),
+            ("define" | "define-syntax", Some(exp)) => symbol(exp),
+            ("define-values", Some(Exp::List(names))) => {
+                symbols(names).into_iter().for_each(|name| self.define(name, span));
+                None
+            },
+            ("begin", _) => {
+                self.body_declarations(&l[1..]);
+                None
+            },
+            // The names a module exports are read from its file
+            ("import", _) => {
+                for name in &l[1..] {
+                    for sym in module::exports(name).unwrap_or_default() {
+                        self.bind(module::qualified(name, sym));
+                        if lookup_builtin(sym.as_str()).is_none() {
+                            self.bind(sym);
+                        }
+                    }
+                }
+                None
+            },
+            _ => None,
+        };
+        if let Some(name) = name {
+            self.define(name, span);
+            if head == "define-macro" || head == "define-syntax" {
+                self.macros.insert(name);
+            }
+        }
+    }
+
+    fn body_declarations(&mut self, exps: &[Exp]) {
+        for exp in exps {
+            if let Exp::List(l) = exp {
+                self.declare(l);
+            }
+        }
+    }
+
+    fn body(&mut self, exps: &[Exp], span: Option<&Span>) {
+        self.body_declarations(exps);
+        exps.iter().for_each(|exp| self.exp(exp, span));
+    }
+
+    /// Checks `body` in a new scope with `params` bound.
+    fn procedure(&mut self, params: Vec<Symbol>, body: &[Exp], span: Option<&Span>) {
+        self.push_scope(params);
+        self.body(body, span);
+        self.pop_scope();
+    }
+
+    /// Checks the procedure with the parameter list `params`, whose
+    /// defaults see the parameters before them.
+    fn lambda(&mut self, params: &[Exp], body: &[Exp], span: Option<&Span>) {
+        self.push_scope(Vec::new());
+        for param in params {
+            let name = match param {
+                Exp::List(optional) if optional.len() == 2 => {
+                    self.exp(&optional[1], span);
+                    symbol(&optional[0])
+                },
+                param => symbol(param),
+            };
+            if let Some(name) = name {
+                self.bind(name);
+            }
+        }
+        self.body(body, span);
+        self.pop_scope();
+    }
+
+    /// Checks `exp`, which is part of the list at `span`.
+    fn exp(&mut self, exp: &Exp, span: Option<&Span>) {
+        match exp {
+            Exp::Atom(Atom::Symbol(name)) => self.reference(*name, span),
+            Exp::List(l) => nested(|| self.list(l, l.span.as_deref().or(span))),
+            _ => {},
+        }
+    }
+
+    /// Marks the bindings of the symbols in the macro argument `exp` as
+    /// used, in case the expansion refers to them.
+    fn mention(&mut self, exp: &Exp) {
+        match exp {
+            Exp::Atom(Atom::Symbol(name)) => {
+                self.resolve(*name);
+            },
+            Exp::List(l) => nested(|| l.iter().for_each(|exp| self.mention(exp))),
+            _ => {},
+        }
+    }
+
+    fn exps(&mut self, exps: &[Exp], span: Option<&Span>) {
+        exps.iter().for_each(|exp| self.exp(exp, span));
+    }
+
+    fn list(&mut self, l: &List, span: Option<&Span>) {
+        let Some(head) = l.first().and_then(symbol) else {
+            return self.exps(l, span);
+        };
+        let args = &l[1..];
+        if self.resolve(head) {
+            match self.macros.contains(&head) {
+                true => args.iter().for_each(|arg| self.mention(arg)),
+                false => self.exps(args, span),
+            }
+            return;
+        }
+        if matches!(self.env.get(&head), Some(Exp::Macro(_) | Exp::Syntax(_))) {
+            return args.iter().for_each(|arg| self.mention(arg));
+        }
+        match lookup_builtin(&head) {
+            Some(builtin) => {
+                if !builtin.arity.accepts(args.len()) {
+                    self.warn(BrewlisError::arity(builtin.name, builtin.arity, args.len()).to_string(), span);
+                }
+                if builtin.special_form {
+                    return self.special_form(head, l, span);
+                }
+            },
+            None if !self.env.contains(&head) => self.warn(format!("'{}' is unbound", head), span),
+            None => {},
+        }
+        self.exps(args, span);
+    }
+
+    fn special_form(&mut self, head: Symbol, l: &List, span: Option<&Span>) {
+        let args = &l[1..];
+        // Defines outside of bodies, like in a when, are declared once they
+        // are reached
+        if matches!(head.as_str(), "define" | "define-macro" | "define-syntax" | "define-values") {
+            self.declare(l);
+        }
+        match head.as_str() {
+            "quote" | "define-syntax" | "break" | "define-module" => {},
+            "import" => {
+                // The names were declared with the body
+                for name in args {
+                    if let Err(err) = module::exports(name) {
+                        self.warn(format!("cannot import {}: {}", name, err), span);
+                    }
+                }
+            },
+            "quasiquote" => args.iter().for_each(|template| self.quasiquote(template, 1, span)),
+            "if" => {
+                if args.len() == 2 {
+                    self.warn("'if' without an alternative returns #f if the test is false, 'when' says so".to_string(), span);
+                }
+                self.exps(args, span);
+            },
+            "define" | "define-macro" => match args.first() {
+                Some(signature @ (Exp::List(_) | Exp::Pair(..))) => {
+                    let signature = elements(signature).unwrap_or_default();
+                    self.lambda(signature.get(1..).unwrap_or_default(), args.get(1..).unwrap_or_default(), span);
+                },
+                _ => self.exps(args.get(1..).unwrap_or_default(), span),
+            },
+            "define-values" => self.exps(args.get(1..).unwrap_or_default(), span),
+            "set!" | "export" | "trace" | "untrace" | "and" | "or" | "when" | "unless" | "while" | "time" | "delay" | "assert-error" => self.exps(args, span),
+            "begin" => self.body(args, span),
+            "define-test" => self.procedure(Vec::new(), args.get(1..).unwrap_or_default(), span),
+            "lambda" => match args.first() {
+                Some(params @ (Exp::List(_) | Exp::Pair(..))) => self.lambda(&elements(params).unwrap_or_default(), args.get(1..).unwrap_or_default(), span),
+                // (lambda args body...)
+                Some(rest @ Exp::Atom(Atom::Symbol(_))) => self.lambda(std::slice::from_ref(rest), args.get(1..).unwrap_or_default(), span),
+                _ => self.exps(args, span),
+            },
+            "let" if args.first().and_then(symbol).is_some() => {
+                // (let name ((sym init)...) body...)
+                let bindings = bindings(args.get(1));
+                bindings.iter().for_each(|(_, init)| self.exps(init, span));
+                let mut params = vec![symbol(&args[0]).expect("a name")];
+                params.extend(bindings.iter().map(|(name, _)| *name));
+                self.procedure(params, args.get(2..).unwrap_or_default(), span);
+            },
+            "let" => {
+                let bindings = bindings(args.first());
+                bindings.iter().for_each(|(_, init)| self.exps(init, span));
+                self.procedure(bindings.iter().map(|(name, _)| *name).collect(), args.get(1..).unwrap_or_default(), span);
+            },
+            "let*" => {
+                // Each init sees the bindings before it
+                self.push_scope(Vec::new());
+                for (name, init) in bindings(args.first()) {
+                    self.exps(init, span);
+                    self.bind(name);
+                }
+                self.body(args.get(1..).unwrap_or_default(), span);
+                self.pop_scope();
+            },
+            "letrec" => {
+                let bindings = bindings(args.first());
+                self.push_scope(bindings.iter().map(|(name, _)| *name).collect());
+                bindings.iter().for_each(|(_, init)| self.exps(init, span));
+                self.body(args.get(1..).unwrap_or_default(), span);
+                self.pop_scope();
+            },
+            "do" => {
+                // (do ((var init step)...) (test result...) body...)
+                let bindings = bindings(args.first());
+                bindings.iter().filter_map(|(_, exps)| exps.first()).for_each(|init| self.exp(init, span));
+                self.push_scope(bindings.iter().map(|(name, _)| *name).collect());
+                bindings.iter().for_each(|(_, exps)| self.exps(exps.get(1..).unwrap_or_default(), span));
+                self.exps(args.get(1..).unwrap_or_default(), span);
+                self.pop_scope();
+            },
+            "cond" => self.clauses(args, false, span),
+            "case" => {
+                self.exps(args.get(..1).unwrap_or_default(), span);
+                self.clauses(args.get(1..).unwrap_or_default(), true, span);
+            },
+            "guard" => {
+                // (guard (var clause...) body...)
+                if let Some(Exp::List(spec)) = args.first() {
+                    self.push_scope(spec.first().and_then(symbol).into_iter().collect());
+                    self.clauses(spec.get(1..).unwrap_or_default(), false, span);
+                    self.pop_scope();
+                }
+                self.body(args.get(1..).unwrap_or_default(), span);
+            },
+            _ => self.exps(args, span),
+        }
+    }
+
+    /// Checks the clauses of `cond`, or of `case` with `data`, whose
+    /// first elements are not evaluated.
+    fn clauses(&mut self, clauses: &[Exp], data: bool, span: Option<&Span>) {
+        for clause in clauses {
+            let Exp::List(clause) = clause else {
+                continue;
+            };
+            let span = clause.span.as_deref().or(span);
+            let else_clause = clause.first() == Some(&Exp::Atom(Atom::Symbol(Symbol::new("else"))));
+            let start = if data || else_clause { 1 } else { 0 };
+            self.exps(clause.get(start..).unwrap_or_default(), span);
+        }
+    }
+
+    /// Checks the unquoted parts of the quasiquote `template`, nested in
+    /// `depth` quasiquotes.
+    fn quasiquote(&mut self, template: &Exp, depth: usize, span: Option<&Span>) {
+        let l = match template {
+            Exp::List(l) => l,
+            Exp::Pair(..) => return elements(template).unwrap_or_default().iter().for_each(|exp| self.quasiquote(exp, depth, span)),
+            _ => return,
+        };
+        let span = l.span.as_deref().or(span);
+        match (l.first().and_then(symbol), l.get(1)) {
+            (Some(head), Some(exp)) if head == "unquote" || head == "unquote-splicing" => match depth {
+                1 => self.exp(exp, span),
+                _ => self.quasiquote(exp, depth - 1, span),
+            },
+            (Some(head), Some(exp)) if head == "quasiquote" => self.quasiquote(exp, depth + 1, span),
+            // `(x . ,y) reads as (x unquote y)
+            _ if l.len() > 2 && symbol(&l[l.len() - 2]).is_some_and(|s| s == "unquote") => {
+                let (l, tail) = l.split_at(l.len() - 2);
+                l.iter().for_each(|exp| self.quasiquote(exp, depth, span));
+                self.quasiquote(&Exp::List(tail.into()), depth, span);
+            },
+            _ => l.iter().for_each(|exp| self.quasiquote(exp, depth, span)),
+        }
+    }
+}
+
+fn is_definition(exp: &Exp) -> bool {
+    let Exp::List(l) = exp else {
+        return false;
+    };
+    l.first().and_then(symbol).is_some_and(|head| matches!(head.as_str(), "define" | "define-values" | "define-macro" | "define-syntax"))
+}
+
+/// Checks the program `forms`, which are meant to run in `env`. Returns
+/// the warnings in the order of the forms they are about.
+///
+/// A program of definitions only is taken as a library for others to
+/// load, so its top-level defines need not be used.
+pub fn lint(forms: &[Exp], env: &Env) -> Vec<Warning> {
+    let mut linter = Linter { env, scopes: vec![Vec::new()], macros: HashSet::new(), warnings: Vec::new() };
+    linter.body(forms, None);
+    if forms.iter().all(is_definition) {
+        linter.scopes[0].iter_mut().for_each(|binding| binding.used = true);
+    }
+    linter.pop_scope();
+    let mut warnings = linter.warnings;
+    warnings.sort_by_key(|warning| warning.span.as_ref().map(|span| span.start));
+    warnings
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{env::standard_env, parser::parse};
+
+    /// The warnings on `program`, which must not make the linter panic.
+    fn warnings(program: &str) -> Vec<String> {
+        lint(&parse(program).unwrap(), &standard_env()).into_iter().map(|warning| warning.message).collect()
+    }
+
+    #[test]
+    fn empty_define_is_reported() {
+        assert!(!warnings("(define)").is_empty());
+    }
+
+    #[test]
+    fn empty_define_macro_is_reported() {
+        assert!(!warnings("(define-macro)").is_empty());
+    }
+
+    #[test]
+    fn empty_define_values_is_reported() {
+        assert!(!warnings("(define-values)").is_empty());
+    }
+
+    #[test]
+    fn empty_define_test_is_reported() {
+        assert!(!warnings("(define-test)").is_empty());
+    }
+
+    #[test]
+    fn empty_case_is_reported() {
+        assert!(!warnings("(case)").is_empty());
+    }
+
+    #[test]
+    fn empty_guard_is_reported() {
+        assert!(!warnings("(guard)").is_empty());
+    }
+}
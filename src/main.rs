@@ -1,338 +1,289 @@
-use std::{collections::HashMap, f64::consts::{PI, E}, io::{stdout, stdin, Write}};
-use anyhow::{Result, anyhow};
+mod color;
+mod debugger;
+mod repl;
+mod test_runner;
 
-use logos::{Logos, Lexer};
+use std::io::{IsTerminal, Read, stdin};
 
-#[derive(Logos, Debug, PartialEq)]
-#[logos(skip r"[ \t\n\f]+")] // Ignore this regex pattern between tokens
-enum Token {
-    #[token("(")]
-    ParenOpen,
-    #[token(")")]
-    ParenClose,
+use anyhow::{Context, Result};
+use clap::{Parser, Subcommand};
+use logos::Logos;
 
-    #[regex("[a-zA-Z]+")]
-    Str,
+use brewlis::{BrewlisError, Interpreter, format, lexer::Token, lint, parser::parse, port, span::Span};
 
-    #[regex("[+-]?([0-9]*[.])?[0-9]+")]
-    StrFloat,
+use color::Palette;
 
-    #[regex("[>^<=+*/-]+")]
-    StrOperation
+/// Brewlis, a simple Lisp interpreter. Without a program it starts the REPL.
+#[derive(Parser)]
+#[command(name = "brewlis", version, args_conflicts_with_subcommands = true)]
+struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+    /// The script to run, read from stdin if it is piped
+    file: Option<String>,
+    /// The arguments of the script, returned by (command-line-arguments)
+    #[arg(trailing_var_arg = true, allow_hyphen_values = true, requires = "file")]
+    args: Vec<String>,
+    /// Evaluates the expression and prints its value
+    #[arg(short, long, value_name = "EXPR", conflicts_with = "file")]
+    eval: Option<String>,
+    /// Checks that the program parses without running it, and exits with a
+    /// failure status if it does not
+    #[arg(long)]
+    check: bool,
+    /// Prints the tokens of the program instead of running it
+    #[arg(long)]
+    dump_tokens: bool,
+    /// Prints the parsed forms of the program instead of running it
+    #[arg(long)]
+    dump_ast: bool,
+    /// Prints without colors
+    #[arg(long)]
+    no_color: bool,
+    /// Starts without the procedures of the prelude
+    #[arg(long)]
+    no_prelude: bool,
+    /// Compiles the program to bytecode and runs it on the VM
+    #[arg(long)]
+    vm: bool,
+    /// How deeply evaluation may nest before it fails
+    #[arg(long, value_name = "FRAMES")]
+    max_depth: Option<usize>,
+    /// Makes sqrt and logarithms of negative numbers complex instead of NaN
+    #[arg(long)]
+    complex: bool,
+    /// Allows scripts to run shell commands with system and process-output
+    #[arg(long)]
+    allow_exec: bool,
+    /// Prints each call of a procedure with its arguments and value
+    #[arg(long)]
+    trace: bool,
+    /// Makes (break) stop in the debugger
+    #[arg(long)]
+    debug: bool,
+    /// Stops in the debugger where an error is raised, implies --debug
+    #[arg(long)]
+    break_on_error: bool,
 }
 
-#[derive(Debug, PartialEq, Clone)]
-enum Atom {
-    Symbol(String),
-    Number(f64),
-    Bool(bool),
+#[derive(Subcommand)]
+enum Command {
+    /// Runs the tests defined with define-test in the .brl files of the
+    /// directories, and exits with a failure status if one fails
+    Test {
+        #[arg(required = true, value_name = "PATH")]
+        paths: Vec<String>,
+    },
+    /// Reports likely mistakes in the files without running them, and exits
+    /// with a failure status if it finds any
+    Lint {
+        #[arg(required = true, value_name = "FILE")]
+        files: Vec<String>,
+    },
+    /// Rewrites the files with consistent indentation and spacing, keeping
+    /// their comments
+    Fmt {
+        #[arg(required_unless_present = "stdin", value_name = "FILE")]
+        files: Vec<String>,
+        /// Formats stdin and prints the result instead of rewriting files
+        #[arg(long, conflicts_with = "files")]
+        stdin: bool,
+        /// How many columns lines may fill before forms are broken up
+        #[arg(long, default_value_t = format::DEFAULT_WIDTH)]
+        width: usize,
+    },
 }
 
-#[derive(Debug, PartialEq, Clone)]
-enum Exp {
-    Atom(Atom),
-    List(List),
+/// Where the program to run comes from.
+enum Program {
+    /// An expression given with -e, whose value is printed.
+    Expression(String),
+    File(String),
+    Stdin(String),
 }
 
-impl Exp {
-    fn extract_symbol(self) -> Result<String> {
-        if let Exp::Atom(Atom::Symbol(s)) = self {
-            Ok(s)
-        }
-        else {
-            Err(anyhow!("Not a symbol: {:?}", self))
+impl Program {
+    /// The name of the program in diagnostics.
+    fn name(&self) -> &str {
+        match self {
+            Program::Expression(_) => "<expression>",
+            Program::File(path) => path,
+            Program::Stdin(_) => "<stdin>",
         }
     }
-    fn extract_number(self) -> Result<f64> {
-        if let Exp::Atom(Atom::Number(n)) = self {
-            Ok(n)
-        }
-        else {
-            Err(anyhow!("Not a number: {:?}", self))
+
+    fn text(&self) -> Result<String> {
+        match self {
+            Program::Expression(text) | Program::Stdin(text) => Ok(text.clone()),
+            Program::File(path) => std::fs::read_to_string(path).with_context(|| format!("Cannot read '{}'", path)),
         }
     }
 }
 
-type List = Vec<Exp>;
-type Env = HashMap<String, Exp>;
-
-fn standard_env() -> Env {
-    let mut env = Env::new();
-    env.insert(String::from("pi"), Exp::Atom(Atom::Number(PI)));
-    env.insert(String::from("e"), Exp::Atom(Atom::Number(E)));
-
-    env
-}
-
-fn proc(proc: &Exp, l: &List, env: &HashMap<String, Exp>) -> Result<Exp>{
-    match proc {
-        Exp::Atom(Atom::Symbol(procname)) => {
-            match procname.as_str() {
-                "^" => {
-                    let l0 = l[0].clone().extract_number()?;
-                    let l1 = l[1].clone().extract_number()?;
-                    Ok(Exp::Atom(Atom::Number(l0.powf(l1))))
-                },
-                "*" => {
-                    let l0 = l[0].clone().extract_number()?;
-                    let l1 = l[1].clone().extract_number()?;
-                    Ok(Exp::Atom(Atom::Number(l0*l1)))
-                },
-                "/" => {
-                    let l0 = l[0].clone().extract_number()?;
-                    let l1 = l[1].clone().extract_number()?;
-                    if l1.abs() < 1e-12 {
-                        return Err(anyhow!("Division by zero"))
-                    }
-                    Ok(Exp::Atom(Atom::Number(l0/l1)))
-                },
-                "+" => {
-                    let l0 = l[0].clone().extract_number()?;
-                    let l1 = l[1].clone().extract_number()?;
-                    Ok(Exp::Atom(Atom::Number(l0+l1)))
-                }
-                "-" => {
-                    let l0 = l[0].clone().extract_number()?;
-                    let l1 = l[1].clone().extract_number()?;
-                    Ok(Exp::Atom(Atom::Number(l0-l1)))
-                }
-                ">" => {
-                    let l0 = l[0].clone().extract_number()?;
-                    let l1 = l[1].clone().extract_number()?;
-                    Ok(Exp::Atom(Atom::Bool(l0>l1)))
-                }
-                "<" => {
-                    let l0 = l[0].clone().extract_number()?;
-                    let l1 = l[1].clone().extract_number()?;
-                    Ok(Exp::Atom(Atom::Bool(l0<l1)))
-                }
-                ">=" => {
-                    let l0 = l[0].clone().extract_number()?;
-                    let l1 = l[1].clone().extract_number()?;
-                    Ok(Exp::Atom(Atom::Bool(l0>=l1)))
-                }
-                "<=" => {
-                    let l0 = l[0].clone().extract_number()?;
-                    let l1 = l[1].clone().extract_number()?;
-                    Ok(Exp::Atom(Atom::Bool(l0<=l1)))
-                }
-                "=" => {
-                    let l0 = l[0].clone().extract_number()?;
-                    let l1 = l[1].clone().extract_number()?;
-                    Ok(Exp::Atom(Atom::Bool(l0==l1)))
-                }
-                "abs" => {
-                    let l0 = l[0].clone().extract_number()?;
-                    Ok(Exp::Atom(Atom::Number(l0.abs())))
-                }
-                "sin" => {
-                    let l0 = l[0].clone().extract_number()?;
-                    Ok(Exp::Atom(Atom::Number(l0.sin())))
-                }
-                "cos" => {
-                    let l0 = l[0].clone().extract_number()?;
-                    Ok(Exp::Atom(Atom::Number(l0.cos())))
-                }
-                "tan" => {
-                    let l0 = l[0].clone().extract_number()?;
-                    Ok(Exp::Atom(Atom::Number(l0.tan())))
-                }
-                "sinh" => {
-                    let l0 = l[0].clone().extract_number()?;
-                    Ok(Exp::Atom(Atom::Number(l0.sinh())))
-                }
-                "cosh" => {
-                    let l0 = l[0].clone().extract_number()?;
-                    Ok(Exp::Atom(Atom::Number(l0.cosh())))
-                }
-                "tanh" => {
-                    let l0 = l[0].clone().extract_number()?;
-                    Ok(Exp::Atom(Atom::Number(l0.tanh())))
-                }
-                "exp" => {
-                    let l0 = l[0].clone().extract_number()?;
-                    Ok(Exp::Atom(Atom::Number(l0.exp())))
-                }
-                "ln" => {
-                    let l0 = l[0].clone().extract_number()?;
-                    Ok(Exp::Atom(Atom::Number(l0.ln())))
-                }
-                "begin" => {
-                    Ok(l.last().ok_or(anyhow!("called 'begin' with empty list"))?.clone())
-                }
-                "car" => {
-                    Ok(l.first().ok_or(anyhow!("called 'car' with empty list"))?.clone())
-                }
-                _ => Err(anyhow!("{}, not in env", procname))
-            }
-        },
-        Exp::List(lmb_list) => {
-            let mut env = env.clone();
-            let mut funcall = List::new();
-            funcall.push(Exp::Atom(Atom::Symbol("begin".to_string())));
-            lmb_list.iter().skip(1 /* lambda */).zip(l).for_each(|(sym, value)|{
-                let mut local_define = List::new();
-                local_define.push(Exp::Atom(Atom::Symbol("define".to_string())));
-                local_define.push(sym.clone());
-                local_define.push(value.clone());
-                funcall.push(Exp::List(local_define));
-            });
-            funcall.push(lmb_list.last().ok_or(anyhow!("Error in lambda expression: No body"))?.clone());
-            Ok(eval(&Exp::List(funcall), &mut env)?)
+fn dump_tokens(program: &str) {
+    let mut lex = Token::lexer(program);
+    while let Some(token) = lex.next() {
+        match token {
+            Ok(token) => println!("{:?} {:?} {:?}", lex.span(), token, lex.slice()),
+            Err(()) => println!("{:?} Error {:?}", lex.span(), lex.slice()),
         }
-        _ => Err(anyhow!("Syntax error at {:?}", proc))
     }
 }
 
-fn parse(program: &str) -> Result<Exp>{
-    let mut lex = Token::lexer(program);
+/// Exits with `code` after writing out buffered output, which the
+/// destructors skipped by exiting would do.
+fn exit(code: i32) -> ! {
+    let _ = port::flush_all();
+    std::process::exit(code);
+}
 
-    read_tokens(&mut lex)?.ok_or(anyhow!("No token found: {}", program))
+/// A diagnostic on `program` starting with the position of `span`, like
+/// those of compilers, so that editors can jump to it.
+fn diagnostic(program: &str, message: &str, span: Option<&Span>) -> String {
+    let position = span.map(|span| span.line_col()).map_or(String::new(), |(line, column)| format!("{}:{}:", line, column));
+    let snippet = span.map_or(String::new(), |span| format!("\n{}", span.location().snippet()));
+    format!("{}:{} {}{}", program, position, message, snippet)
+}
 
+/// Reports `err` and exits with a failure status.
+fn fail(err: &BrewlisError, palette: Palette) -> ! {
+    eprintln!("{}", palette.error(&format!("Error: {}", err.report())));
+    exit(1);
 }
 
-fn read_tokens<'a>(lex: &mut Lexer<'a, Token>) -> Result<Option<Exp>> {
-    match lex.next() {
-        Some(token) => {
-            let token = token.map_err(|_| anyhow!("Unknown token: {}", lex.slice()))?;
-            match token {
-                Token::ParenOpen => {
-                    let mut l = Vec::new();
-                    loop {
-                        let result = read_tokens(lex)?;
-                        if let Some(result) = result {
-                            l.push(result);
-                        }
-                        else {
-                            break;
-                        }
-                    }
-                    Ok(Some(Exp::List(l)))
-                },
-                Token::ParenClose => Ok(None),
-                Token::Str | Token::StrOperation => Ok(Some(Exp::Atom(Atom::Symbol(lex.slice().to_string())))),
-                Token::StrFloat => Ok(Some(Exp::Atom(Atom::Number(lex.slice().parse()?)))),
-                
+fn main() -> Result<()> {
+    let cli = Cli::parse();
+    let palette = Palette::new(cli.no_color);
+    let new_interpreter = move || {
+        let mut interpreter = if cli.no_prelude { Interpreter::bare() } else { Interpreter::new() };
+        interpreter.use_vm(cli.vm);
+        interpreter.set_complex_results(cli.complex);
+        interpreter.allow_exec(cli.allow_exec);
+        interpreter.set_trace(cli.trace);
+        if cli.debug || cli.break_on_error {
+            interpreter.set_debugger(Some(debugger::debugger(palette)));
+            interpreter.set_break_on_error(cli.break_on_error);
+        }
+        interpreter.set_command_line_arguments(cli.args.clone());
+        if let Some(depth) = cli.max_depth {
+            interpreter.set_max_depth(depth);
+        }
+        interpreter
+    };
+    match &cli.command {
+        Some(Command::Test { paths }) => exit(test_runner::run(paths, &new_interpreter, palette)?),
+        Some(Command::Lint { files }) => {
+            let mut failed = false;
+            for file in files {
+                let text = std::fs::read_to_string(file).with_context(|| format!("Cannot read '{}'", file))?;
+                let forms = match parse(&text) {
+                    Ok(forms) => forms,
+                    Err(err) => {
+                        failed = true;
+                        eprintln!("{}", palette.error(&diagnostic(file, &err.to_string(), err.span())));
+                        continue;
+                    },
+                };
+                for warning in lint::lint(&forms, new_interpreter().env()) {
+                    failed = true;
+                    println!("{}", diagnostic(file, &format!("warning: {}", warning.message), warning.span.as_ref()));
+                }
             }
+            exit(if failed { 1 } else { 0 });
         },
-        None => Err(anyhow!("Unexpected EOF")),
-    }
-}
-
-fn eval(exp: &Exp, env: &mut HashMap<String, Exp>) -> Result<Exp> {
-    match exp {
-        Exp::Atom(atom) => {
-            match atom {
-                Atom::Symbol(sym) => {
-                    if env.contains_key(sym) {
-                        return Ok(env[sym].clone())
-                    }
-                    else {
-                        return Ok(exp.clone()); // must be a proc
-                    }
-                },
-                Atom::Number(_) | Atom::Bool(_) => return Ok(Exp::Atom(atom.clone())),
+        Some(Command::Fmt { stdin: true, width, .. }) => {
+            let mut text = String::new();
+            stdin().read_to_string(&mut text)?;
+            match format::format(&text, *width) {
+                Ok(formatted) => print!("{}", formatted),
+                Err(err) => fail(&err, palette),
             }
+            return Ok(());
         },
-        Exp::List(l) => {
-            if let Exp::Atom(Atom::Symbol(sym)) = &l[0] {
-                if sym == "if" {
-                    let test = &l[1];
-                    let conseq = &l[2];
-                    let alt = &l[3];
-                    if eval(&test, env)? == Exp::Atom(Atom::Bool(true)) {
-                        return Ok(eval(conseq, env)?)
-                    } else {
-                        return Ok(eval(alt, env)?)
-                    }
-
-                } else if sym == "define" {
-                    let symbol = l[1].clone().extract_symbol()?;
-                    let exp = &l[2];
-                    let result = eval(&exp, env)?;
-                    env.insert(symbol.clone(), result);
-                    return Ok(Exp::Atom(Atom::Bool(true)))
-                } else if sym == "lambda" {
-                    return Ok(Exp::List(l.clone()))
-                } 
-                else {
-                    let procname = eval(&l[0], env)?;
-                    let args = l.iter().skip(1).map(|li| {
-                        eval(li, env).unwrap()
-                    }).collect::<Vec<_>>();
-                    return proc(&procname, &args, &env);
+        Some(Command::Fmt { files, width, .. }) => {
+            for file in files {
+                let text = std::fs::read_to_string(file).with_context(|| format!("Cannot read '{}'", file))?;
+                let formatted = match format::format(&text, *width) {
+                    Ok(formatted) => formatted,
+                    Err(err) => {
+                        eprintln!("{}", palette.error(&format!("{}: {}", file, err.report())));
+                        exit(1);
+                    },
+                };
+                // Files which are formatted already are left untouched
+                if formatted != text {
+                    std::fs::write(file, formatted).with_context(|| format!("Cannot write '{}'", file))?;
                 }
             }
-            Err(anyhow!("Not implemented command: {:?}", l))
-
+            return Ok(());
         },
+        None => {},
     }
-}
-
-#[allow(unused)]
-fn print(exp: &Exp) {
-    match exp {
-        Exp::Atom(atom) => {
-            match atom {
-                Atom::Symbol(sym) => print!("'{}',", sym),
-                Atom::Number(num) => print!("{},",num),
-                Atom::Bool(b) => print!("{},",b),
-            }
-        },
-        Exp::List(l) => {
-            print!("[");
-            for el in l {
-                print(el);
-            }
-            print!("],");
+    let program = match (cli.eval, cli.file) {
+        (Some(expression), _) => Program::Expression(expression),
+        (None, Some(path)) => Program::File(path),
+        (None, None) if !stdin().is_terminal() => {
+            let mut text = String::new();
+            stdin().read_to_string(&mut text)?;
+            Program::Stdin(text)
         },
+        (None, None) => return repl::repl(&new_interpreter, palette),
+    };
+    if cli.check {
+        if let Err(err) = parse(&program.text()?) {
+            eprintln!("{}", palette.error(&diagnostic(program.name(), &err.to_string(), err.span())));
+            exit(1);
+        }
+        return Ok(());
     }
-}
-
-fn repl() -> Result<()> {
-    let mut env = standard_env();
-    loop {
-        let mut s = String::new();
-        print!("> ");
-        let _=stdout().flush();
-        stdin().read_line(&mut s).expect("Did not enter a correct string");
-        if let Some('\n')=s.chars().next_back() {
-            s.pop();
+    if cli.dump_tokens || cli.dump_ast {
+        let text = program.text()?;
+        if cli.dump_tokens {
+            dump_tokens(&text);
         }
-        if let Some('\r')=s.chars().next_back() {
-            s.pop();
+        if cli.dump_ast {
+            match parse(&text) {
+                Ok(forms) => forms.iter().for_each(|exp| println!("{:?}", exp)),
+                Err(err) => fail(&err, palette),
+            }
         }
-
-        let parsed = parse(&s);
-        match parsed {
-            Ok(parsed) => {
-                let result = eval(&parsed, &mut env);
-                match result {
-                    Ok(result) => println!("{:?}", result),
-                    Err(msg) => {
-                        println!("Evaluation error: {}", msg);
-                        continue;
-                    }
-                }
-            },
-            Err(msg) => {
-                println!("Parsing error: {}", msg);
-                continue;
-            },
+        return Ok(());
+    }
+    let mut interpreter = new_interpreter();
+    // The value of an expression is printed, scripts run for their effects
+    let result = match &program {
+        Program::Expression(text) => interpreter.eval_str(text).map(|result| println!("{}", palette.highlight(&result.to_string()))),
+        Program::File(path) => interpreter.load(path).map(|_| ()),
+        Program::Stdin(text) => interpreter.eval_str(text).map(|_| ()),
+    };
+    if let Err(err) = result {
+        if let Some(code) = err.exit_code() {
+            exit(code);
         }
+        fail(&err, palette);
     }
+    // Ports left open can be kept alive by reference cycles
+    if let Err(err) = port::flush_all() {
+        fail(&err, palette);
+    }
+    Ok(())
 }
 
-fn main() -> Result<()> {
-    let args = std::env::args().collect::<Vec<_>>();
-    if args.len() > 1 {
-        let program = args[1].as_str();
-        let mut env = standard_env();
-        let result = eval(&parse(program)?, &mut env)?;
-        println!("{:?}", result);
-    } else {
-        repl()?;
-    };
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-    Ok(())
+    #[test]
+    fn subcommand_after_file_is_a_script_argument() {
+        let cli = Cli::try_parse_from(["lis-rs", "a.brl", "fmt", "victim.brl"]).unwrap();
+        assert!(cli.command.is_none());
+        assert_eq!(cli.file.as_deref(), Some("a.brl"));
+        assert_eq!(cli.args, ["fmt", "victim.brl"]);
+    }
+
+    #[test]
+    fn subcommand_before_file_is_parsed() {
+        let cli = Cli::try_parse_from(["lis-rs", "fmt", "a.brl"]).unwrap();
+        assert!(matches!(cli.command, Some(Command::Fmt { .. })));
+    }
 }
@@ -0,0 +1,131 @@
+//! Modules: files that start with `(define-module (name...))` and name the
+//! definitions they make visible with `(export sym...)`. `(import (my
+//! utils))` evaluates the file my/utils.brl, relative to the current
+//! directory like `load`, in an environment of its own and binds the
+//! exported names in the importing environment qualified by the module
+//! name, like `my/utils/helper` or `my::utils::helper`. They are bound as
+//! they are too, unless that would shadow a builtin or a binding not
+//! imported from the same module. The other definitions of the module stay
+//! private to it.
+
+use std::{cell::RefCell, fs};
+
+use crate::{builtins::lookup_builtin, env::{Env, standard_env}, error::{BrewlisError, Result, eval_error}, eval::load, exp::{Atom, Exp}, parser::parse, symbol::Symbol};
+
+/// A module being imported.
+struct Loading {
+    name: Exp,
+    /// Whether its file has called define-module.
+    defined: bool,
+    exports: Vec<Symbol>,
+}
+
+thread_local! {
+    /// The modules being imported, the innermost last.
+    static LOADING: RefCell<Vec<Loading>> = const { RefCell::new(Vec::new()) };
+}
+
+/// The file of the module `name`, my/utils.brl for (my utils).
+pub fn path(name: &Exp) -> Result<String> {
+    let parts = match name {
+        Exp::List(parts) if !parts.is_empty() => parts,
+        _ => return Err(BrewlisError::type_error("a module name like (my utils)", name)),
+    };
+    let parts = parts.iter().map(|part| match part {
+        Exp::Atom(Atom::Symbol(part)) => Ok(part.to_string()),
+        _ => Err(BrewlisError::type_error("a module name like (my utils)", name)),
+    }).collect::<Result<Vec<_>>>()?;
+    Ok(format!("{}.brl", parts.join("/")))
+}
+
+/// The qualified name of `sym` exported by the module `name`,
+/// my/utils/helper for helper of (my utils).
+pub fn qualified(name: &Exp, sym: Symbol) -> Symbol {
+    let Exp::List(parts) = name else { return sym };
+    let parts = parts.iter().map(|part| part.to_string()).collect::<Vec<_>>();
+    Symbol::from(format!("{}/{}", parts.join("/"), sym).as_str())
+}
+
+/// Whether the export `sym` of the module `name` can be bound unqualified
+/// in `env`: it is neither a builtin nor bound already, except by an
+/// earlier import of the same module.
+pub fn unqualified(name: &Exp, sym: Symbol, env: &Env) -> bool {
+    match env.get(&sym) {
+        Some(value) => env.get(&qualified(name, sym)).is_some_and(|imported| imported == value),
+        None => lookup_builtin(sym.as_str()).is_none(),
+    }
+}
+
+/// Evaluates the file of the module `name` and binds its exports in `env`.
+pub fn import(name: &Exp, env: &Env) -> Result<()> {
+    let path = path(name)?;
+    LOADING.with(|loading| {
+        let loading = loading.borrow();
+        match loading.iter().position(|module| module.name == *name) {
+            Some(start) => {
+                let cycle = loading[start..].iter().map(|module| module.name.to_string()).collect::<Vec<_>>();
+                Err(eval_error!("import cycle: {} -> {}", cycle.join(" -> "), name))
+            },
+            None => Ok(()),
+        }
+    })?;
+    LOADING.with(|loading| loading.borrow_mut().push(Loading { name: name.clone(), defined: false, exports: Vec::new() }));
+    let module_env = standard_env();
+    let result = load(&path, &module_env);
+    let module = LOADING.with(|loading| loading.borrow_mut().pop()).expect("the module being imported");
+    result?;
+    if !module.defined {
+        return Err(eval_error!("'{}' does not define the module {}", path, name));
+    }
+    for sym in module.exports {
+        let value = module_env.get(&sym).ok_or(eval_error!("module {} exports '{}', which it does not define", name, sym))?;
+        let doc = module_env.doc(&sym);
+        if unqualified(name, sym, env) {
+            env.define_documented(sym, value.clone(), doc.clone());
+        }
+        env.define_documented(qualified(name, sym), value, doc);
+    }
+    Ok(())
+}
+
+/// Declares that the file being evaluated is the module `name`. Outside of
+/// an import, like when the file is run as a script, it does nothing.
+pub fn define(name: &Exp) -> Result<()> {
+    path(name)?;
+    LOADING.with(|loading| match loading.borrow_mut().last_mut() {
+        Some(module) if module.defined => Err(eval_error!("called 'define-module' twice in module {}", module.name)),
+        Some(module) if module.name != *name => Err(eval_error!("imported {} from a file defining the module {}", module.name, name)),
+        Some(module) => {
+            module.defined = true;
+            Ok(())
+        },
+        None => Ok(()),
+    })
+}
+
+/// Adds `names` to the exports of the module being imported.
+pub fn export(names: Vec<Symbol>) {
+    LOADING.with(|loading| {
+        if let Some(module) = loading.borrow_mut().last_mut() {
+            module.exports.extend(names);
+        }
+    });
+}
+
+/// The names the module `name` exports, read from the top level forms of
+/// its file without evaluating them.
+pub fn exports(name: &Exp) -> Result<Vec<Symbol>> {
+    let path = path(name)?;
+    let program = fs::read_to_string(&path).map_err(|err| eval_error!("Cannot read '{}': {}", path, err))?;
+    let mut exports = Vec::new();
+    for form in parse(&program)? {
+        let Exp::List(l) = form else { continue };
+        if l.first() == Some(&Exp::Atom(Atom::Symbol(Symbol::new("export")))) {
+            exports.extend(l[1..].iter().filter_map(|exp| match exp {
+                Exp::Atom(Atom::Symbol(sym)) => Some(*sym),
+                _ => None,
+            }));
+        }
+    }
+    Ok(exports)
+}
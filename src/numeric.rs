@@ -0,0 +1,566 @@
+use std::{cell::Cell, cmp::Ordering, ops, rc::Rc};
+
+use num_bigint::BigInt;
+use num_complex::Complex64;
+use num_rational::BigRational;
+use num_integer::Integer;
+use num_traits::{FromPrimitive, One, Signed, ToPrimitive, Zero};
+
+use crate::{error::{BrewlisError, Result, eval_error}, exp::{Atom, Exp}};
+
+/// The most bits of an exact power, larger ones take too much memory and
+/// time to compute.
+const MAX_POWER_BITS: u64 = 1 << 24;
+
+/// A number taking part in arithmetic.
+///
+/// Operations on exact numbers stay exact: integer results that overflow an
+/// `i64` are promoted to a `BigInt`, quotients that are not integers become
+/// rationals, and both are demoted again once they fit. Any float operand
+/// makes the result a float, any complex operand a complex number, which is
+/// demoted to a float when its imaginary part is zero.
+#[derive(Debug, Clone)]
+pub enum Num {
+    Int(i64),
+    Big(BigInt),
+    Ratio(BigRational),
+    Float(f64),
+    Complex(Complex64),
+}
+
+thread_local! {
+    static COMPLEX_RESULTS: Cell<bool> = const { Cell::new(false) };
+}
+
+/// Makes `sqrt` and logarithms of negative numbers complex instead of NaN,
+/// for all interpreters of the thread.
+pub fn set_complex_results(enabled: bool) {
+    COMPLEX_RESULTS.with(|complex_results| complex_results.set(enabled));
+}
+
+fn complex_results() -> bool {
+    COMPLEX_RESULTS.with(Cell::get)
+}
+
+impl Num {
+    pub fn from_exp(exp: &Exp) -> Result<Num> {
+        match exp {
+            Exp::Atom(Atom::Int(i)) => Ok(Num::Int(*i)),
+            Exp::Atom(Atom::BigInt(i)) => Ok(Num::Big(BigInt::clone(i))),
+            Exp::Atom(Atom::Rational(r)) => Ok(Num::Ratio(BigRational::clone(r))),
+            Exp::Atom(Atom::Number(n)) => Ok(Num::Float(*n)),
+            Exp::Atom(Atom::Complex(c)) => Ok(Num::Complex(*c)),
+            _ => Err(BrewlisError::type_error("a number", exp)),
+        }
+    }
+
+    /// Like `from_exp`, for operations that need an order, like `<` and
+    /// `floor`, and thus reject complex numbers.
+    pub fn from_real(exp: &Exp) -> Result<Num> {
+        match Num::from_exp(exp)? {
+            Num::Complex(_) => Err(BrewlisError::type_error("a real number", exp)),
+            n => Ok(n),
+        }
+    }
+
+    pub fn into_exp(self) -> Exp {
+        match self {
+            Num::Int(i) => Exp::Atom(Atom::Int(i)),
+            Num::Big(i) => Exp::Atom(Atom::BigInt(Rc::new(i))),
+            Num::Ratio(r) => Exp::Atom(Atom::Rational(Rc::new(r))),
+            Num::Float(n) => Exp::Atom(Atom::Number(n)),
+            Num::Complex(c) => Exp::Atom(Atom::Complex(c)),
+        }
+    }
+
+    /// Reads the number `text` written in `radix`: an integer, a fraction
+    /// like `-3/4` or, in radix 10, a decimal like `2.5`, `1e-3` or
+    /// `+inf.0` or a complex number like `3+4i`. A prefix `#x`, `#o`, `#b`
+    /// or `#d` overrides the radix.
+    pub fn parse(text: &str, radix: u32) -> Option<Num> {
+        let (text, radix) = match text.as_bytes() {
+            [b'#', prefix, ..] => {
+                let radix = match prefix.to_ascii_lowercase() {
+                    b'x' => 16,
+                    b'o' => 8,
+                    b'b' => 2,
+                    b'd' => 10,
+                    _ => return None,
+                };
+                (&text[2..], radix)
+            }
+            _ => (text, radix),
+        };
+        if let Some((numerator, denominator)) = text.split_once('/') {
+            let numerator = integer(numerator, radix)?;
+            let denominator = integer(denominator, radix)?;
+            if denominator.is_zero() || denominator.is_negative() {
+                return None;
+            }
+            return Some(Num::from_rational(BigRational::new(numerator, denominator)));
+        }
+        if let Some(i) = integer(text, radix) {
+            return Some(Num::from_bigint(i));
+        }
+        if radix != 10 {
+            return None;
+        }
+        if let Some(text) = text.strip_suffix('i') {
+            // The imaginary part starts at the last sign not of an exponent
+            let start = text.char_indices().rev()
+                .find(|&(i, c)| (c == '+' || c == '-') && !text[..i].ends_with(['e', 'E']))
+                .map_or(0, |(i, _)| i);
+            let (re, im) = text.split_at(start);
+            let re = if re.is_empty() { 0.0 } else { Num::parse(re, 10)?.real()? };
+            let im = match im {
+                "+" => 1.0,
+                "-" => -1.0,
+                _ => Num::parse(im, 10)?.real()?,
+            };
+            return Some(Num::from_complex(Complex64::new(re, im)));
+        }
+        match text {
+            "+inf.0" => Some(Num::Float(f64::INFINITY)),
+            "-inf.0" => Some(Num::Float(f64::NEG_INFINITY)),
+            "+nan.0" | "-nan.0" => Some(Num::Float(f64::NAN)),
+            // Rust also reads words like "inf" as floats
+            _ if text.bytes().all(|b| b.is_ascii_digit() || b"+-.eE".contains(&b)) => text.parse().ok().map(Num::Float),
+            _ => None,
+        }
+    }
+
+    /// Writes the number in `radix`, the syntax `parse` reads. Floats can
+    /// only be written in radix 10.
+    pub fn to_string_radix(&self, radix: u32) -> Result<String> {
+        match self {
+            Num::Int(i) => Ok(BigInt::from(*i).to_str_radix(radix)),
+            Num::Big(i) => Ok(i.to_str_radix(radix)),
+            Num::Ratio(r) => Ok(format!("{}/{}", r.numer().to_str_radix(radix), r.denom().to_str_radix(radix))),
+            Num::Float(_) | Num::Complex(_) if radix == 10 => Ok(self.clone().into_exp().to_string()),
+            Num::Float(_) | Num::Complex(_) => Err(eval_error!("Cannot write the inexact number {} in radix {}", self.clone().into_exp(), radix)),
+        }
+    }
+
+    /// Stores `i` as an `i64` if it fits.
+    pub fn from_bigint(i: BigInt) -> Num {
+        match i.to_i64() {
+            Some(i) => Num::Int(i),
+            None => Num::Big(i),
+        }
+    }
+
+    /// Stores `r` as an integer if its denominator is one.
+    pub fn from_rational(r: BigRational) -> Num {
+        if r.is_integer() {
+            Num::from_bigint(r.to_integer())
+        } else {
+            Num::Ratio(r)
+        }
+    }
+
+    /// Stores `c` as a float if its imaginary part is zero.
+    pub fn from_complex(c: Complex64) -> Num {
+        if c.im == 0.0 {
+            Num::Float(c.re)
+        } else {
+            Num::Complex(c)
+        }
+    }
+
+    /// The value as a float, NaN for complex numbers.
+    pub fn to_f64(&self) -> f64 {
+        match self {
+            Num::Int(i) => *i as f64,
+            Num::Big(i) => i.to_f64().unwrap_or(f64::NAN),
+            Num::Ratio(r) => r.to_f64().unwrap_or(f64::NAN),
+            Num::Float(n) => *n,
+            Num::Complex(_) => f64::NAN,
+        }
+    }
+
+    fn real(&self) -> Option<f64> {
+        match self {
+            Num::Complex(_) => None,
+            _ => Some(self.to_f64()),
+        }
+    }
+
+    pub fn to_complex(&self) -> Complex64 {
+        match self {
+            Num::Complex(c) => *c,
+            _ => Complex64::new(self.to_f64(), 0.0),
+        }
+    }
+
+    fn to_bigint(&self) -> Option<BigInt> {
+        match self {
+            Num::Int(i) => Some(BigInt::from(*i)),
+            Num::Big(i) => Some(i.clone()),
+            Num::Ratio(_) | Num::Float(_) | Num::Complex(_) => None,
+        }
+    }
+
+    fn to_rational(&self) -> Option<BigRational> {
+        match self {
+            Num::Ratio(r) => Some(r.clone()),
+            Num::Float(_) | Num::Complex(_) => None,
+            _ => Some(BigRational::from_integer(self.to_bigint()?)),
+        }
+    }
+
+    /// Converts exact numbers to floats.
+    pub fn to_inexact(&self) -> Num {
+        match self {
+            Num::Complex(_) => self.clone(),
+            _ => Num::Float(self.to_f64()),
+        }
+    }
+
+    /// Applies the operation at the most precise level both operands fit:
+    /// `small` on two `i64`s, `big` on integers, `ratio` on exact numbers
+    /// and `float` or `complex` otherwise. Each exact level falls through to
+    /// the next one by returning `None`, e.g. on overflow. Operations
+    /// without a `complex` level must only be applied to real numbers.
+    fn arith(&self, other: &Num, op: Op) -> Num {
+        if let (Num::Int(a), Num::Int(b)) = (self, other) {
+            if let Some(result) = (op.small)(*a, *b) {
+                return Num::Int(result);
+            }
+        }
+        if let (Some(a), Some(b)) = (self.to_bigint(), other.to_bigint()) {
+            if let Some(result) = (op.big)(&a, &b) {
+                return Num::from_bigint(result);
+            }
+        }
+        if let (Some(a), Some(b)) = (self.to_rational(), other.to_rational()) {
+            if let Some(result) = (op.ratio)(&a, &b) {
+                return Num::from_rational(result);
+            }
+        }
+        if let (Some(complex), Num::Complex(_), _) | (Some(complex), _, Num::Complex(_)) = (op.complex, self, other) {
+            return Num::from_complex(complex(self.to_complex(), other.to_complex()));
+        }
+        Num::Float((op.float)(self.to_f64(), other.to_f64()))
+    }
+
+    fn is_zero(&self) -> bool {
+        match self {
+            Num::Int(i) => *i == 0,
+            Num::Big(i) => i.is_zero(),
+            Num::Ratio(r) => r.is_zero(),
+            Num::Float(n) => n.abs() < 1e-12,
+            Num::Complex(c) => c.norm() < 1e-12,
+        }
+    }
+
+    /// Divides exactly, the quotient of two integers is a rational unless
+    /// it is an integer.
+    pub fn checked_div(&self, other: &Num) -> Result<Num> {
+        if other.is_zero() {
+            return Err(eval_error!("Division by zero"));
+        }
+        Ok(self.arith(other, Op {
+            small: |a, b| if a.checked_rem(b)? == 0 { a.checked_div(b) } else { None },
+            big: |a, b| if (a % b).is_zero() { Some(a / b) } else { None },
+            ratio: |a, b| Some(a / b),
+            float: |a, b| a / b,
+            complex: Some(|a, b| a / b),
+        }))
+    }
+
+    /// Raises to an integer power exactly, other powers are inexact. Exact
+    /// zero to a negative power is a division by zero.
+    pub fn checked_pow(&self, other: &Num) -> Result<Num> {
+        if let (Some(base), Some(exponent)) = (self.to_rational(), other.to_rational()) {
+            if base.is_zero() && exponent.is_negative() {
+                return Err(eval_error!("Division by zero"));
+            }
+            // The power has at least this many bits for each one of the
+            // exponent, none for 0, 1 and -1
+            let bits = base.numer().bits().max(base.denom().bits()).saturating_sub(1);
+            if exponent.is_integer() && bits > 0 && exponent.abs().to_integer().to_u64().is_none_or(|exponent| exponent.saturating_mul(bits) > MAX_POWER_BITS) {
+                return Err(eval_error!("Exponent too large: {}", exponent));
+            }
+        }
+        Ok(self.arith(other, Op {
+            small: |a, b| a.checked_pow(u32::try_from(b).ok()?),
+            big: |a, b| Some(a.pow(b.to_u32()?)),
+            ratio: |a, b| {
+                if !b.is_integer() {
+                    return None;
+                }
+                let exponent = b.to_integer().to_i32()?;
+                if a.is_zero() && exponent < 0 {
+                    return None;
+                }
+                Some(a.pow(exponent))
+            },
+            float: f64::powf,
+            complex: Some(|a, b| a.powc(b)),
+        }))
+    }
+
+    /// Rounds with `float` or `ratio` to an integer, which stays exact for
+    /// exact numbers.
+    fn rounded(&self, float: fn(f64) -> f64, ratio: fn(&BigRational) -> BigRational) -> Num {
+        match self {
+            Num::Int(_) | Num::Big(_) => self.clone(),
+            Num::Ratio(r) => Num::from_rational(ratio(r)),
+            Num::Float(n) => Num::Float(float(*n)),
+            Num::Complex(c) => Num::from_complex(Complex64::new(float(c.re), float(c.im))),
+        }
+    }
+
+    pub fn floor(&self) -> Num {
+        self.rounded(f64::floor, BigRational::floor)
+    }
+
+    pub fn ceil(&self) -> Num {
+        self.rounded(f64::ceil, BigRational::ceil)
+    }
+
+    pub fn truncate(&self) -> Num {
+        self.rounded(f64::trunc, BigRational::trunc)
+    }
+
+    /// Rounds to the nearest integer, halfway cases to the even one.
+    pub fn round(&self) -> Num {
+        self.rounded(f64::round_ties_even, |r| {
+            let floor = r.floor();
+            let half = BigRational::new(1.into(), 2.into());
+            match (r - &floor).cmp(&half) {
+                Ordering::Less => floor,
+                Ordering::Greater => floor + BigRational::one(),
+                Ordering::Equal if floor.to_integer().is_even() => floor,
+                Ordering::Equal => floor + BigRational::one(),
+            }
+        })
+    }
+
+    /// Divides and truncates the quotient towards zero.
+    pub fn quotient(&self, other: &Num) -> Result<Num> {
+        if other.is_zero() {
+            return Err(eval_error!("Division by zero"));
+        }
+        Ok(self.arith(other, Op {
+            small: i64::checked_div,
+            big: |a, b| Some(a / b),
+            ratio: |a, b| Some((a / b).trunc()),
+            float: |a, b| (a / b).trunc(),
+            complex: None,
+        }))
+    }
+
+    /// The value of an exact integer, or of a float without a fraction.
+    pub fn to_integer(&self) -> Option<BigInt> {
+        match self {
+            Num::Float(n) if n.fract() == 0.0 => BigInt::from_f64(*n),
+            _ => self.to_bigint(),
+        }
+    }
+
+    /// The remainder of truncating division, with the sign of `self`.
+    pub fn remainder(&self, other: &Num) -> Result<Num> {
+        if other.is_zero() {
+            return Err(eval_error!("Division by zero"));
+        }
+        Ok(self.arith(other, Op {
+            // The remainder of i64::MIN / -1 overflows, but is 0
+            small: |a, b| Some(a.checked_rem(b).unwrap_or(0)),
+            big: |a, b| Some(a % b),
+            ratio: |a, b| Some(a - b * (a / b).trunc()),
+            float: |a, b| a % b,
+            complex: None,
+        }))
+    }
+
+    /// The remainder of flooring division, with the sign of `other`.
+    pub fn modulo(&self, other: &Num) -> Result<Num> {
+        if other.is_zero() {
+            return Err(eval_error!("Division by zero"));
+        }
+        Ok(self.arith(other, Op {
+            small: |a, b| {
+                let r = a.checked_rem(b).unwrap_or(0);
+                if r != 0 && (r < 0) != (b < 0) { r.checked_add(b) } else { Some(r) }
+            },
+            big: |a, b| Some(a.mod_floor(b)),
+            ratio: |a, b| Some(a - b * (a / b).floor()),
+            float: |a, b| a - b * (a / b).floor(),
+            complex: None,
+        }))
+    }
+
+    /// The square root, exact for exact squares of integers. The roots of
+    /// negative numbers are NaN unless complex results are enabled.
+    pub fn sqrt(&self) -> Num {
+        if let Some(i) = self.to_bigint() {
+            if !i.is_negative() {
+                let root = i.sqrt();
+                if &root * &root == i {
+                    return Num::from_bigint(root);
+                }
+            }
+        }
+        match self.real() {
+            Some(n) if n >= 0.0 || !complex_results() => Num::Float(n.sqrt()),
+            _ => Num::from_complex(self.to_complex().sqrt()),
+        }
+    }
+
+    /// The natural logarithm, NaN for negative numbers unless complex
+    /// results are enabled.
+    pub fn ln(&self) -> Num {
+        match self.real() {
+            Some(n) if n >= 0.0 || !complex_results() => Num::Float(n.ln()),
+            _ => Num::from_complex(self.to_complex().ln()),
+        }
+    }
+
+    /// The logarithm to `base`.
+    pub fn log(&self, base: &Num) -> Num {
+        match (self.ln(), base.ln()) {
+            (Num::Float(a), Num::Float(b)) => Num::Float(a / b),
+            (a, b) => Num::from_complex(a.to_complex() / b.to_complex()),
+        }
+    }
+
+    /// The distance from zero, `abs` for real numbers.
+    pub fn magnitude(&self) -> Num {
+        match self {
+            Num::Complex(c) => Num::Float(c.norm()),
+            _ => self.abs(),
+        }
+    }
+
+    /// The angle to the positive real axis, exactly 0 for positive exact
+    /// numbers.
+    pub fn angle(&self) -> Num {
+        match self {
+            Num::Complex(c) => Num::Float(c.arg()),
+            Num::Float(n) => Num::Float(0.0f64.atan2(*n)),
+            n if *n < Num::Int(0) => Num::Float(std::f64::consts::PI),
+            _ => Num::Int(0),
+        }
+    }
+
+    pub fn real_part(&self) -> Num {
+        match self {
+            Num::Complex(c) => Num::Float(c.re),
+            _ => self.clone(),
+        }
+    }
+
+    /// The imaginary part, exactly 0 for real numbers.
+    pub fn imag_part(&self) -> Num {
+        match self {
+            Num::Complex(c) => Num::Float(c.im),
+            _ => Num::Int(0),
+        }
+    }
+
+    pub fn abs(&self) -> Num {
+        match self {
+            Num::Int(i) => i.checked_abs().map(Num::Int).unwrap_or_else(|| Num::Big(BigInt::from(*i).abs())),
+            Num::Big(i) => Num::Big(i.abs()),
+            Num::Ratio(r) => Num::Ratio(r.abs()),
+            Num::Float(n) => Num::Float(n.abs()),
+            Num::Complex(c) => Num::Float(c.norm()),
+        }
+    }
+}
+
+/// Reads an integer with an optional sign, rejecting what `BigInt` would
+/// also accept, like underscores.
+fn integer(text: &str, radix: u32) -> Option<BigInt> {
+    let digits = text.strip_prefix(['+', '-']).unwrap_or(text);
+    if digits.is_empty() || !digits.chars().all(|c| c.is_digit(radix)) {
+        return None;
+    }
+    BigInt::parse_bytes(text.as_bytes(), radix)
+}
+
+/// An arithmetic operation at each level of the numeric tower, see
+/// [`Num::arith`].
+struct Op {
+    small: fn(i64, i64) -> Option<i64>,
+    big: fn(&BigInt, &BigInt) -> Option<BigInt>,
+    ratio: fn(&BigRational, &BigRational) -> Option<BigRational>,
+    float: fn(f64, f64) -> f64,
+    complex: Option<fn(Complex64, Complex64) -> Complex64>,
+}
+
+impl ops::Add for Num {
+    type Output = Num;
+
+    fn add(self, other: Num) -> Num {
+        self.arith(&other, Op {
+            small: i64::checked_add,
+            big: |a, b| Some(a + b),
+            ratio: |a, b| Some(a + b),
+            float: |a, b| a + b,
+            complex: Some(|a, b| a + b),
+        })
+    }
+}
+
+impl ops::Sub for Num {
+    type Output = Num;
+
+    fn sub(self, other: Num) -> Num {
+        self.arith(&other, Op {
+            small: i64::checked_sub,
+            big: |a, b| Some(a - b),
+            ratio: |a, b| Some(a - b),
+            float: |a, b| a - b,
+            complex: Some(|a, b| a - b),
+        })
+    }
+}
+
+impl ops::Mul for Num {
+    type Output = Num;
+
+    fn mul(self, other: Num) -> Num {
+        self.arith(&other, Op {
+            small: i64::checked_mul,
+            big: |a, b| Some(a * b),
+            ratio: |a, b| Some(a * b),
+            float: |a, b| a * b,
+            complex: Some(|a, b| a * b),
+        })
+    }
+}
+
+impl ops::Neg for Num {
+    type Output = Num;
+
+    fn neg(self) -> Num {
+        match self {
+            Num::Int(i) => i.checked_neg().map(Num::Int).unwrap_or_else(|| Num::Big(-BigInt::from(i))),
+            Num::Big(i) => Num::from_bigint(-i),
+            Num::Ratio(r) => Num::Ratio(-r),
+            Num::Float(n) => Num::Float(-n),
+            Num::Complex(c) => Num::Complex(-c),
+        }
+    }
+}
+
+/// Numbers compare by value, so `1` equals `1.0`. Complex numbers are only
+/// equal or unordered.
+impl PartialEq for Num {
+    fn eq(&self, other: &Num) -> bool {
+        self.partial_cmp(other) == Some(Ordering::Equal)
+    }
+}
+
+impl PartialOrd for Num {
+    fn partial_cmp(&self, other: &Num) -> Option<Ordering> {
+        match (self, other) {
+            (Num::Int(a), Num::Int(b)) => Some(a.cmp(b)),
+            (Num::Complex(_), _) | (_, Num::Complex(_)) => (self.to_complex() == other.to_complex()).then_some(Ordering::Equal),
+            (Num::Float(_), _) | (_, Num::Float(_)) => self.to_f64().partial_cmp(&other.to_f64()),
+            _ => Some(self.to_rational()?.cmp(&other.to_rational()?)),
+        }
+    }
+}
@@ -0,0 +1,234 @@
+use std::rc::Rc;
+
+use logos::{Logos, Lexer};
+
+use crate::{error::{Result, parse_error}, exp::{Atom, Exp, List}, lexer::Token, numeric::Num, span::Span, symbol::Symbol};
+
+/// Reads all top-level forms of `program`.
+pub fn parse(program: &str) -> Result<Vec<Exp>>{
+    let source: Rc<str> = program.into();
+    let mut lex = Token::lexer(program);
+    let mut forms = Vec::new();
+    while let Some(exp) = read_top_level(&mut lex, &source)? {
+        forms.push(exp);
+    }
+    Ok(forms)
+}
+
+/// Reads the first form of `program`. Returns it with the byte offset of
+/// the text after it, or None if `program` has no form.
+pub fn parse_first(program: &str) -> Result<Option<(Exp, usize)>> {
+    let source: Rc<str> = program.into();
+    let mut lex = Token::lexer(program);
+    let exp = read_top_level(&mut lex, &source)?;
+    Ok(exp.map(|exp| (exp, lex.span().end)))
+}
+
+/// Reads the next top-level form, or None at the end of the input.
+fn read_top_level<'a>(lex: &mut Lexer<'a, Token>, source: &Rc<str>) -> Result<Option<Exp>> {
+    let Some(token) = lex.next() else {
+        return Ok(None);
+    };
+    let span = token_span(lex, source);
+    match read_token(token, lex, &span)? {
+        Some(exp) => Ok(Some(exp)),
+        None => Err(parse_error!("Unexpected ')'").with_span(&span)),
+    }
+}
+
+/// The number of lists left open at the end of `program`, so that input
+/// can be read until its forms are complete. Unknown tokens are skipped,
+/// parsing reports them.
+pub fn open_lists(program: &str) -> usize {
+    let mut depth: usize = 0;
+    for token in Token::lexer(program).flatten() {
+        match token {
+            Token::ParenOpen | Token::VectorOpen => depth += 1,
+            Token::ParenClose => depth = depth.saturating_sub(1),
+            _ => {},
+        }
+    }
+    depth
+}
+
+/// The span of the token `lex` just read.
+fn token_span<'a>(lex: &Lexer<'a, Token>, source: &Rc<str>) -> Span {
+    Span::new(source.clone(), lex.span().start, lex.span().end)
+}
+
+/// A form the reader has started but not finished.
+enum Open {
+    /// A list with the span of its '(' and, after a dot, the number of
+    /// elements before the dot.
+    List(Vec<Exp>, Span, Option<usize>),
+    Vector(Vec<Exp>),
+    /// A quote prefix, read as `(name exp)`, with its span.
+    Prefix(&'static str, Span),
+}
+
+/// Reads the form starting with `token`, or returns None for a ')'.
+///
+/// Nested forms are kept on a stack rather than read recursively, so the
+/// nesting depth is limited by the heap only.
+fn read_token<'a>(token: Result<Token, ()>, lex: &mut Lexer<'a, Token>, span: &Span) -> Result<Option<Exp>> {
+    let source = &span.source;
+    let mut open: Vec<Open> = Vec::new();
+    let mut next = Some((token, span.clone()));
+    loop {
+        let (token, span) = match next.take() {
+            Some(next) => next,
+            None => {
+                let Some(token) = lex.next() else {
+                    // The '(' left open is more helpful than the end
+                    if let Some(Open::List(_, start, _)) = open.last() {
+                        return Err(parse_error!("Unexpected EOF, this list is not closed").with_span(start));
+                    }
+                    let end = source.len();
+                    return Err(parse_error!("Unexpected EOF").with_span(&Span::new(source.clone(), end, end)));
+                };
+                (token, token_span(lex, source))
+            },
+        };
+        let mut exp = match token {
+            Ok(Token::ParenOpen) => {
+                open.push(Open::List(Vec::new(), span, None));
+                continue;
+            },
+            Ok(Token::VectorOpen) => {
+                open.push(Open::Vector(Vec::new()));
+                continue;
+            },
+            // 'exp is read as (quote exp), the others alike
+            Ok(Token::Quote) => {
+                open.push(Open::Prefix("quote", span));
+                continue;
+            },
+            Ok(Token::Quasiquote) => {
+                open.push(Open::Prefix("quasiquote", span));
+                continue;
+            },
+            Ok(Token::Unquote) => {
+                open.push(Open::Prefix("unquote", span));
+                continue;
+            },
+            Ok(Token::UnquoteSplicing) => {
+                open.push(Open::Prefix("unquote-splicing", span));
+                continue;
+            },
+            Ok(Token::Dot) => match open.last_mut() {
+                Some(Open::List(l, _, dot @ None)) if !l.is_empty() => {
+                    *dot = Some(l.len());
+                    continue;
+                },
+                _ => return Err(parse_error!("Unexpected '.'").with_span(&span)),
+            },
+            Ok(Token::ParenClose) => match open.pop() {
+                None => return Ok(None),
+                Some(Open::List(l, start, None)) => {
+                    // The list spans from its '(' to its ')'
+                    let span = Span::new(source.clone(), start.start, span.end);
+                    Exp::List(List::with_span(l, span))
+                },
+                Some(Open::List(mut l, start, Some(dot))) => {
+                    if l.len() != dot + 1 {
+                        return Err(parse_error!("Expected one form after '.'").with_span(&Span::new(source.clone(), start.start, span.end)));
+                    }
+                    let tail = l.pop().expect("the form after the dot");
+                    improper_list(l, tail, Span::new(source.clone(), start.start, span.end))
+                },
+                Some(Open::Vector(v)) => Exp::vector(v),
+                Some(Open::Prefix(name, span)) => return Err(parse_error!("Nothing to {} before ')'", name).with_span(&span)),
+            },
+            token => read_atom(token, lex).map_err(|err| err.with_span(&span))?,
+        };
+        // The form completes the prefixes before it
+        loop {
+            match open.last_mut() {
+                None => return Ok(Some(exp)),
+                Some(Open::List(l, _, _) | Open::Vector(l)) => {
+                    l.push(exp);
+                    break;
+                },
+                Some(Open::Prefix(name, _)) => {
+                    exp = Exp::List(vec![Exp::Atom(Atom::Symbol(Symbol::new(name))), exp].into());
+                    open.pop();
+                },
+            }
+        }
+    }
+}
+
+/// The list of `elements` followed by the elements of `tail` if it is a
+/// list, like (a b c) for (a . (b c)), or else the pairs of an improper list.
+fn improper_list(mut elements: Vec<Exp>, tail: Exp, span: Span) -> Exp {
+    match tail {
+        Exp::List(tail) => {
+            elements.extend(tail.iter().cloned());
+            Exp::List(List::with_span(elements, span))
+        },
+        tail => elements.into_iter().rev().fold(tail, |cdr, car| Exp::Pair(Box::new(car), Box::new(cdr))),
+    }
+}
+
+/// Reads an atom, any token but parentheses and quote prefixes.
+fn read_atom<'a>(token: Result<Token, ()>, lex: &mut Lexer<'a, Token>) -> Result<Exp> {
+    match token {
+        Ok(Token::True) => Ok(Exp::Atom(Atom::Bool(true))),
+        Ok(Token::False) => Ok(Exp::Atom(Atom::Bool(false))),
+        Ok(Token::Str | Token::StrOperation) => Ok(Exp::Atom(Atom::Symbol(Symbol::new(lex.slice())))),
+        Ok(Token::StrQualified) => Ok(Exp::Atom(Atom::Symbol(Symbol::new(&lex.slice().replace("::", "/"))))),
+        Ok(Token::StrFloat | Token::StrRational | Token::StrRadix | Token::StrComplex) => {
+            let number = Num::parse(lex.slice(), 10).ok_or_else(|| parse_error!("Invalid number: {}", lex.slice()))?;
+            Ok(number.into_exp())
+        },
+        Ok(Token::CharLiteral) => Ok(Exp::Atom(Atom::Char(char_literal(&lex.slice()[2..])?))),
+        Ok(Token::Keyword) => Ok(Exp::Atom(Atom::Keyword(Symbol::new(&lex.slice()[2..])))),
+        Ok(Token::StringLiteral) => {
+            let slice = lex.slice();
+            Ok(Exp::Atom(Atom::String(unescape(&slice[1..slice.len() - 1])?.into())))
+        },
+        Ok(token) => unreachable!("{:?} is not an atom", token),
+        Err(()) => Err(parse_error!("Unknown token: {}", lex.slice())),
+    }
+}
+
+/// Resolves the name of a character literal without its `#\` prefix.
+fn char_literal(name: &str) -> Result<char> {
+    let mut chars = name.chars();
+    if let (Some(c), None) = (chars.next(), chars.next()) {
+        return Ok(c);
+    }
+    match name {
+        "space" => Ok(' '),
+        "newline" | "linefeed" => Ok('\n'),
+        "tab" => Ok('\t'),
+        "return" => Ok('\r'),
+        "nul" | "null" => Ok('\0'),
+        "alarm" => Ok('\x07'),
+        "backspace" => Ok('\x08'),
+        "escape" => Ok('\x1b'),
+        "delete" => Ok('\x7f'),
+        _ => Err(parse_error!("Unknown character name: #\\{}", name)),
+    }
+}
+
+/// Resolves the escape sequences of a string literal without its quotes.
+fn unescape(literal: &str) -> Result<String> {
+    let mut s = String::with_capacity(literal.len());
+    let mut chars = literal.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            s.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n') => s.push('\n'),
+            Some('t') => s.push('\t'),
+            Some('"') => s.push('"'),
+            Some('\\') => s.push('\\'),
+            Some(c) => return Err(parse_error!("Unknown escape sequence in string: \\{}", c)),
+            None => return Err(parse_error!("Unterminated escape sequence in string")),
+        }
+    }
+    Ok(s)
+}
@@ -0,0 +1,76 @@
+//! Extensions: native procedures loaded from shared libraries.
+//!
+//! An extension is a `cdylib` crate depending on `brewlis` that exports a
+//! registration function named [`ENTRY_POINT`]. `(load-extension path)`
+//! loads the library, calls the function with a [`Registrar`] and binds the
+//! procedures registered with it:
+//!
+//! ```ignore
+//! use brewlis::{Atom, BrewlisError, Exp, plugin::Registrar};
+//!
+//! #[no_mangle]
+//! pub extern "C" fn brewlis_register(registrar: &mut Registrar) {
+//!     registrar.register_fn("double", |args: &[Exp]| match args {
+//!         [Exp::Atom(Atom::Int(n))] => Ok(Exp::Atom(Atom::Int(2 * n))),
+//!         _ => Err(BrewlisError::type_error("an integer", &args[0])),
+//!     });
+//! }
+//! ```
+//!
+//! Values are passed between the interpreter and the extension as Rust
+//! types, so the extension has to be built with the same compiler and the
+//! same version of brewlis as the interpreter loading it. The WebAssembly
+//! build cannot load extensions.
+
+#[cfg(not(target_arch = "wasm32"))]
+use libloading::{Library, Symbol as LibrarySymbol};
+
+use crate::{env::Env, error::{Result, eval_error}, exp::Exp};
+#[cfg(not(target_arch = "wasm32"))]
+use crate::{exp::Atom, symbol::Symbol};
+
+/// The name of the function an extension exports, of type [`RegisterFn`].
+pub const ENTRY_POINT: &str = "brewlis_register";
+
+pub type RegisterFn = extern "C" fn(&mut Registrar);
+
+/// Collects the procedures an extension registers.
+#[derive(Default)]
+pub struct Registrar {
+    procedures: Vec<(String, Exp)>,
+}
+
+impl Registrar {
+    /// Registers `function` under `name`, like `Interpreter::register_fn`.
+    pub fn register_fn(&mut self, name: &str, function: impl Fn(&[Exp]) -> Result<Exp> + 'static) {
+        self.procedures.push((name.to_string(), Exp::native(name, function)));
+    }
+}
+
+/// Loads the extension at `path` and binds its procedures in `env`.
+#[cfg(not(target_arch = "wasm32"))]
+pub fn load_extension(path: &str, env: &Env) -> Result<Exp> {
+    // SAFETY: Loading a library runs its initializers, which brewlis has no
+    // control over, like over any other native code an interpreter runs.
+    let library = unsafe { Library::new(path) }.map_err(|err| eval_error!("Cannot load extension '{}': {}", path, err))?;
+    let mut registrar = Registrar::default();
+    {
+        // SAFETY: The entry point of an extension is documented to have the
+        // type of `RegisterFn`.
+        let register: LibrarySymbol<RegisterFn> = unsafe { library.get(ENTRY_POINT.as_bytes()) }
+            .map_err(|err| eval_error!("'{}' is not a brewlis extension: {}", path, err))?;
+        register(&mut registrar);
+    }
+    // The registered procedures run code of the library, so it stays loaded
+    // as long as the program runs.
+    std::mem::forget(library);
+    for (name, procedure) in registrar.procedures {
+        env.define(Symbol::from(name.as_str()), procedure);
+    }
+    Ok(Exp::Atom(Atom::Bool(true)))
+}
+
+#[cfg(target_arch = "wasm32")]
+pub fn load_extension(path: &str, _env: &Env) -> Result<Exp> {
+    Err(eval_error!("Cannot load extension '{}': not supported by the WebAssembly build", path))
+}
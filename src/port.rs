@@ -0,0 +1,145 @@
+//! Ports, the files opened by `open-input-file` and `open-output-file`.
+//!
+//! A port is closed by `close-port` or when its last reference is dropped.
+//! Output is buffered, the interpreter keeps track of the open output ports
+//! so that [`flush_all`] can write them out when the program ends, also
+//! when a reference cycle keeps a port alive or the process exits without
+//! running destructors, as `exit` does.
+
+use std::{cell::RefCell, fmt, fs::File, io::{BufRead, BufReader, BufWriter, Read, Write}, rc::{Rc, Weak}};
+
+use crate::error::{Result, eval_error};
+
+thread_local! {
+    static OUTPUT_PORTS: RefCell<Vec<Weak<Port>>> = const { RefCell::new(Vec::new()) };
+    /// The ports `with-input-from-file` reads from, the innermost last.
+    static CURRENT_INPUT: RefCell<Vec<Rc<Port>>> = const { RefCell::new(Vec::new()) };
+}
+
+pub struct Port {
+    path: String,
+    input: bool,
+    /// The open file, `None` once the port is closed.
+    stream: RefCell<Option<Stream>>,
+}
+
+enum Stream {
+    Input(BufReader<File>),
+    Output(BufWriter<File>),
+}
+
+impl Port {
+    pub fn open_input(path: &str) -> Result<Rc<Port>> {
+        let file = File::open(path).map_err(|err| eval_error!("Cannot open {}: {}", path, err))?;
+        Ok(Rc::new(Port { path: path.to_string(), input: true, stream: RefCell::new(Some(Stream::Input(BufReader::new(file)))) }))
+    }
+
+    /// Opens the file at `path` for writing, truncating it if it exists.
+    pub fn open_output(path: &str) -> Result<Rc<Port>> {
+        let file = File::create(path).map_err(|err| eval_error!("Cannot open {}: {}", path, err))?;
+        let port = Rc::new(Port { path: path.to_string(), input: false, stream: RefCell::new(Some(Stream::Output(BufWriter::new(file)))) });
+        OUTPUT_PORTS.with(|ports| {
+            let mut ports = ports.borrow_mut();
+            ports.retain(|port| port.strong_count() > 0);
+            ports.push(Rc::downgrade(&port));
+        });
+        Ok(port)
+    }
+
+    pub fn is_input(&self) -> bool {
+        self.input
+    }
+
+    fn with_input<T>(&self, f: impl FnOnce(&mut BufReader<File>) -> std::io::Result<T>) -> Result<T> {
+        match &mut *self.stream.borrow_mut() {
+            Some(Stream::Input(reader)) => f(reader).map_err(|err| eval_error!("Cannot read from {}: {}", self.path, err)),
+            Some(Stream::Output(_)) => Err(eval_error!("Cannot read from the output port {}", self.path)),
+            None => Err(eval_error!("Cannot read from the closed port {}", self.path)),
+        }
+    }
+
+    /// The next line without its line break, `None` at the end of the file.
+    pub fn read_line(&self) -> Result<Option<String>> {
+        let mut line = String::new();
+        if self.with_input(|reader| reader.read_line(&mut line))? == 0 {
+            return Ok(None);
+        }
+        if line.ends_with('\n') {
+            line.pop();
+            if line.ends_with('\r') {
+                line.pop();
+            }
+        }
+        Ok(Some(line))
+    }
+
+    /// The rest of the file, empty at its end.
+    pub fn read_all(&self) -> Result<String> {
+        let mut text = String::new();
+        self.with_input(|reader| reader.read_to_string(&mut text))?;
+        Ok(text)
+    }
+
+    pub fn write_str(&self, text: &str) -> Result<()> {
+        match &mut *self.stream.borrow_mut() {
+            Some(Stream::Output(writer)) => writer.write_all(text.as_bytes()).map_err(|err| eval_error!("Cannot write to {}: {}", self.path, err)),
+            Some(Stream::Input(_)) => Err(eval_error!("Cannot write to the input port {}", self.path)),
+            None => Err(eval_error!("Cannot write to the closed port {}", self.path)),
+        }
+    }
+
+    fn flush(&self) -> Result<()> {
+        if let Some(Stream::Output(writer)) = &mut *self.stream.borrow_mut() {
+            writer.flush().map_err(|err| eval_error!("Cannot write to {}: {}", self.path, err))?;
+        }
+        Ok(())
+    }
+
+    /// Writes out buffered output and closes the file. Closing a closed
+    /// port does nothing.
+    pub fn close(&self) -> Result<()> {
+        self.flush()?;
+        self.stream.borrow_mut().take();
+        Ok(())
+    }
+}
+
+impl fmt::Debug for Port {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Port").field("path", &self.path).field("input", &self.input).finish()
+    }
+}
+
+impl fmt::Display for Port {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "#<{}-port {}>", if self.input { "input" } else { "output" }, self.path)
+    }
+}
+
+/// Ports are the same only if they are one object.
+impl PartialEq for Port {
+    fn eq(&self, other: &Port) -> bool {
+        std::ptr::eq(self, other)
+    }
+}
+
+/// Writes out the buffered output of all open output ports.
+pub fn flush_all() -> Result<()> {
+    let ports: Vec<Rc<Port>> = OUTPUT_PORTS.with(|ports| ports.borrow().iter().filter_map(Weak::upgrade).collect());
+    ports.iter().try_for_each(|port| port.flush())
+}
+
+/// The port `read-line` and `read-all` read from without a port argument,
+/// `None` for stdin.
+pub fn current_input() -> Option<Rc<Port>> {
+    CURRENT_INPUT.with(|ports| ports.borrow().last().cloned())
+}
+
+/// Calls `f` with `port` as the current input port, then closes it.
+pub fn with_input<T>(port: Rc<Port>, f: impl FnOnce() -> Result<T>) -> Result<T> {
+    CURRENT_INPUT.with(|ports| ports.borrow_mut().push(port.clone()));
+    let result = f();
+    CURRENT_INPUT.with(|ports| ports.borrow_mut().pop());
+    port.close()?;
+    result
+}
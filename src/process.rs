@@ -0,0 +1,53 @@
+//! The process brewlis runs in: its command line arguments and running
+//! shell commands with `system` and `process-output`.
+//!
+//! Commands can do anything the user running brewlis can, so they are
+//! disabled unless the embedding program or `--allow-exec` enables them.
+
+use std::{cell::{Cell, RefCell}, process::{Command, Stdio}};
+
+use crate::error::{Result, eval_error};
+
+thread_local! {
+    static ALLOW_EXEC: Cell<bool> = const { Cell::new(false) };
+    static ARGUMENTS: RefCell<Vec<String>> = const { RefCell::new(Vec::new()) };
+}
+
+/// Sets the arguments of the script, those after its path on the command
+/// line.
+pub fn set_arguments(arguments: Vec<String>) {
+    ARGUMENTS.with(|args| *args.borrow_mut() = arguments);
+}
+
+pub fn arguments() -> Vec<String> {
+    ARGUMENTS.with(|args| args.borrow().clone())
+}
+
+/// Allows or forbids running commands, for all interpreters of the thread.
+pub fn set_allow_exec(enabled: bool) {
+    ALLOW_EXEC.with(|allow_exec| allow_exec.set(enabled));
+}
+
+/// The command running `command` with the shell of the platform.
+fn shell(command: &str) -> Result<Command> {
+    if !ALLOW_EXEC.with(Cell::get) {
+        return Err(eval_error!("Running commands is disabled, enable it with --allow-exec"));
+    }
+    let mut shell = if cfg!(windows) { Command::new("cmd") } else { Command::new("sh") };
+    shell.arg(if cfg!(windows) { "/C" } else { "-c" }).arg(command);
+    Ok(shell)
+}
+
+/// Runs `command` with the output going to stdout and returns its exit
+/// code.
+pub fn system(command: &str) -> Result<i64> {
+    let status = shell(command)?.status().map_err(|err| eval_error!("Cannot run {:?}: {}", command, err))?;
+    status.code().map(i64::from).ok_or(eval_error!("Command {:?} was terminated by a signal", command))
+}
+
+/// Runs `command` and returns what it wrote to stdout, whatever its exit
+/// code.
+pub fn output(command: &str) -> Result<String> {
+    let output = shell(command)?.stderr(Stdio::inherit()).output().map_err(|err| eval_error!("Cannot run {:?}: {}", command, err))?;
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
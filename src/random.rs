@@ -0,0 +1,88 @@
+//! The random number generator of `random`, xoshiro256**.
+//!
+//! It is seeded from the clock on first use, or with `random-seed!`. The
+//! sequence following a seed is the same in every build, so runs of a
+//! script that seeds the generator are reproducible.
+
+use std::cell::RefCell;
+
+use chrono::Utc;
+
+use crate::{error::{BrewlisError, Result}, exp::{Atom, Exp}};
+
+thread_local! {
+    static RNG: RefCell<Option<Rng>> = const { RefCell::new(None) };
+}
+
+struct Rng {
+    state: [u64; 4],
+}
+
+impl Rng {
+    /// Expands `seed` to the state with SplitMix64, which never gives the
+    /// all-zero state.
+    fn new(seed: u64) -> Rng {
+        let mut seed = seed;
+        let mut next = || {
+            seed = seed.wrapping_add(0x9e3779b97f4a7c15);
+            let mut z = seed;
+            z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+            z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+            z ^ (z >> 31)
+        };
+        Rng { state: [next(), next(), next(), next()] }
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        let s = &mut self.state;
+        let result = s[1].wrapping_mul(5).rotate_left(7).wrapping_mul(9);
+        let t = s[1] << 17;
+        s[2] ^= s[0];
+        s[3] ^= s[1];
+        s[1] ^= s[2];
+        s[0] ^= s[3];
+        s[2] ^= t;
+        s[3] = s[3].rotate_left(45);
+        result
+    }
+
+    /// A float in [0, 1) from the upper 53 bits.
+    fn next_f64(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    /// An integer in [0, n), without the bias of taking the remainder.
+    fn below(&mut self, n: u64) -> u64 {
+        let zone = u64::MAX - u64::MAX % n;
+        loop {
+            let x = self.next_u64();
+            if x < zone {
+                return x % n;
+            }
+        }
+    }
+}
+
+fn with_rng<T>(f: impl FnOnce(&mut Rng) -> T) -> T {
+    RNG.with(|rng| {
+        let mut rng = rng.borrow_mut();
+        let rng = rng.get_or_insert_with(|| Rng::new(Utc::now().timestamp_nanos_opt().unwrap_or_default() as u64));
+        f(rng)
+    })
+}
+
+/// Restarts the sequence of random numbers from `seed`.
+pub fn seed(seed: u64) {
+    RNG.with(|rng| *rng.borrow_mut() = Some(Rng::new(seed)));
+}
+
+/// `(random)` returns a float in [0, 1), `(random n)` a number in [0, n) of
+/// the exactness of `n`.
+pub fn random(limit: Option<&Exp>) -> Result<Exp> {
+    match limit {
+        None => Ok(Exp::Atom(Atom::Number(with_rng(Rng::next_f64)))),
+        Some(Exp::Atom(Atom::Int(n))) if *n > 0 => Ok(Exp::Atom(Atom::Int(with_rng(|rng| rng.below(*n as u64)) as i64))),
+        Some(Exp::Atom(Atom::Number(n))) if *n > 0.0 => Ok(Exp::Atom(Atom::Number(with_rng(Rng::next_f64) * n))),
+        Some(limit) => Err(BrewlisError::type_error("a positive number", limit)),
+    }
+}
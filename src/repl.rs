@@ -0,0 +1,208 @@
+use std::{borrow::Cow, path::PathBuf};
+
+use anyhow::Result;
+use rustyline::{Context, Editor, Helper, Hinter, Validator, completion::Completer, error::ReadlineError, highlight::{CmdKind, Highlighter}};
+
+use brewlis::{Env, Exp, Interpreter, Symbol, builtins::{BUILTINS, help_text}, parser::{open_lists, parse}, port};
+
+use crate::color::Palette;
+
+/// Completes symbols from the builtins and the bindings of the REPL
+/// environment, and highlights the input.
+#[derive(Helper, Hinter, Validator)]
+struct ReplHelper {
+    env: Env,
+    palette: Palette,
+}
+
+impl Highlighter for ReplHelper {
+    fn highlight<'l>(&self, line: &'l str, _pos: usize) -> Cow<'l, str> {
+        Cow::Owned(self.palette.highlight(line))
+    }
+
+    fn highlight_prompt<'b, 's: 'b, 'p: 'b>(&'s self, prompt: &'p str, _default: bool) -> Cow<'b, str> {
+        Cow::Owned(self.palette.prompt(prompt))
+    }
+
+    fn highlight_char(&self, _line: &str, _pos: usize, _kind: CmdKind) -> bool {
+        true
+    }
+}
+
+impl Completer for ReplHelper {
+    type Candidate = String;
+
+    fn complete(&self, line: &str, pos: usize, _ctx: &Context<'_>) -> rustyline::Result<(usize, Vec<String>)> {
+        // The symbol before the cursor starts after the last delimiter
+        let start = line[..pos].char_indices()
+            .rfind(|(_, c)| c.is_whitespace() || "()'`,\"".contains(*c))
+            .map_or(0, |(i, c)| i + c.len_utf8());
+        let prefix = &line[start..pos];
+        let mut candidates: Vec<String> = BUILTINS.iter()
+            .map(|b| b.name.to_string())
+            .chain(self.env.symbols().iter().map(|sym| sym.to_string()))
+            .filter(|name| name.starts_with(prefix))
+            .collect();
+        candidates.sort();
+        candidates.dedup();
+        Ok((start, candidates))
+    }
+}
+
+/// The file in the home directory the REPL history is kept in.
+const HISTORY_FILE: &str = ".brewlis_history";
+
+fn history_path() -> Option<PathBuf> {
+    std::env::var_os("HOME").map(|home| PathBuf::from(home).join(HISTORY_FILE))
+}
+
+/// Binds `value`, the `count`th result of the session, to `$count` and to
+/// `$`. `*` would shadow multiplication.
+fn remember(interpreter: &Interpreter, count: usize, value: &Exp) {
+    interpreter.env().define(Symbol::from(format!("${}", count).as_str()), value.clone());
+    interpreter.env().define(Symbol::from("$"), value.clone());
+}
+
+/// The REPL commands with their descriptions, listed by :help.
+const COMMANDS: &[(&str, &str)] = &[
+    (":env", "list the bindings of the environment"),
+    (":doc name", "describe the definition or builtin name"),
+    (":load file", "evaluate the forms of file"),
+    (":reset", "restore the initial environment, discarding all definitions"),
+    (":help", "show this list"),
+    (":quit", "leave the REPL"),
+];
+
+/// Runs the REPL command `command`, given without its colon. `:reset` sets
+/// up the interpreter again with `new_interpreter`. Returns false if the REPL
+/// should end.
+fn run_command(command: &str, interpreter: &mut Interpreter, new_interpreter: &dyn Fn() -> Interpreter, palette: Palette) -> bool {
+    let (name, arg) = command.split_once(char::is_whitespace).unwrap_or((command, ""));
+    match (name, arg.trim()) {
+        ("env", "") => {
+            let env = interpreter.env();
+            let mut symbols = env.symbols();
+            symbols.sort_by(|a, b| a.as_str().cmp(b.as_str()));
+            for sym in symbols {
+                if let Some(value) = env.get(&sym) {
+                    println!("{} = {}", sym, palette.highlight(&value.to_string()));
+                }
+            }
+        },
+        ("doc", "") => println!("Usage: :doc name"),
+        ("doc", name) => match help_text(Some(name), interpreter.env()) {
+            Ok(text) => println!("{}", text),
+            Err(msg) => println!("{}", palette.error(&msg.to_string())),
+        },
+        ("load", "") => println!("Usage: :load file"),
+        ("load", path) => match interpreter.load(path) {
+            Ok(_) => println!("Loaded {}", path),
+            Err(msg) => println!("{}", palette.error(&format!("Evaluation error: {}", msg.report()))),
+        },
+        ("reset", "") => *interpreter = new_interpreter(),
+        ("help", "") => {
+            for (command, description) in COMMANDS {
+                println!("{:<12} {}", command, description);
+            }
+        },
+        ("quit", "") => return false,
+        _ => println!("{}", palette.error(&format!("Unknown command :{}, see :help", command))),
+    }
+    true
+}
+
+pub fn repl(new_interpreter: &dyn Fn() -> Interpreter, palette: Palette) -> Result<()> {
+    let mut interpreter = new_interpreter();
+    let mut editor = Editor::new()?;
+    editor.set_helper(Some(ReplHelper { env: interpreter.env().clone(), palette }));
+    let history = history_path();
+    if let Some(path) = &history {
+        // There is no history file before the first session
+        let _ = editor.load_history(path);
+    }
+    let mut s = String::new();
+    let mut exit_code = None;
+    let mut results = 0;
+    'session: loop {
+        // Lines are collected until all lists are closed
+        let prompt = if s.is_empty() { "> " } else { "... " };
+        match editor.readline(prompt) {
+            Ok(line) => {
+                if !s.is_empty() {
+                    s.push('\n');
+                }
+                s.push_str(&line);
+            },
+            // Ctrl-C discards the input, Ctrl-D ends the session
+            Err(ReadlineError::Interrupted) => {
+                s.clear();
+                continue;
+            },
+            Err(ReadlineError::Eof) => break,
+            Err(err) => return Err(err.into()),
+        };
+        if open_lists(&s) > 0 {
+            continue;
+        }
+        let s = std::mem::take(&mut s);
+        if !s.trim().is_empty() {
+            editor.add_history_entry(s.as_str())?;
+        }
+        if let Some(command) = s.trim().strip_prefix(':') {
+            if !run_command(command, &mut interpreter, new_interpreter, palette) {
+                break;
+            }
+            // :reset replaced the environment
+            if let Some(helper) = editor.helper_mut() {
+                if helper.env != *interpreter.env() {
+                    helper.env = interpreter.env().clone();
+                    results = 0;
+                }
+            }
+            continue;
+        }
+
+        let parsed = parse(&s);
+        match parsed {
+            Ok(parsed) => {
+                // Each form is evaluated and printed in turn, up to the
+                // first error
+                for exp in &parsed {
+                    match interpreter.eval(exp) {
+                        Ok(result) => {
+                            results += 1;
+                            remember(&interpreter, results, &result);
+                            println!("{}", palette.highlight(&result.to_string()));
+                        },
+                        Err(err) if err.exit_code().is_some() => {
+                            exit_code = err.exit_code();
+                            break 'session;
+                        }
+                        Err(msg) => {
+                            println!("{}", palette.error(&format!("Evaluation error: {}", msg.report())));
+                            break;
+                        }
+                    }
+                }
+            },
+            Err(msg) => {
+                println!("{}", palette.error(&format!("Parsing error: {}", msg.report())));
+                continue;
+            },
+        }
+    }
+    if let Some(path) = &history {
+        if let Err(err) = editor.save_history(path) {
+            eprintln!("Could not save the history to {}: {}", path.display(), err);
+        }
+    }
+    // Ports left open can be kept alive by reference cycles, and exit skips
+    // the destructors anyway
+    if let Err(err) = port::flush_all() {
+        eprintln!("{}", palette.error(&format!("Error: {}", err.report())));
+    }
+    if let Some(code) = exit_code {
+        std::process::exit(code);
+    }
+    Ok(())
+}
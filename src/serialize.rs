@@ -0,0 +1,81 @@
+//! serde support for values, enabled by the `serde` feature.
+//!
+//! Data is serialized by value: atoms, lists, pairs, vectors, hash tables,
+//! error objects and multiple values. Procedures, macros, environments,
+//! continuations and promises refer to evaluation state and fail to
+//! serialize, as do vectors and hash tables containing themselves. Values
+//! shared by several references are serialized once for each and not
+//! shared after deserializing.
+
+use std::{cell::RefCell, collections::{HashMap, HashSet}, rc::Rc};
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer, ser};
+
+use crate::{exp::{Exp, HashKey, List}, gc, symbol::Symbol};
+
+thread_local! {
+    /// The vectors and hash tables being serialized, to detect cycles.
+    static SERIALIZING: RefCell<HashSet<usize>> = RefCell::new(HashSet::new());
+}
+
+impl Serialize for Symbol {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.as_str())
+    }
+}
+
+impl<'de> Deserialize<'de> for Symbol {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let name = String::deserialize(deserializer)?;
+        Ok(Symbol::from(name.as_str()))
+    }
+}
+
+impl Serialize for List {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(self.iter())
+    }
+}
+
+impl<'de> Deserialize<'de> for List {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        Ok(Vec::<Exp>::deserialize(deserializer)?.into())
+    }
+}
+
+/// Serializes the contents of a vector or hash table, unless it is already
+/// being serialized further out.
+fn shared<T, S: Serializer>(value: &Rc<RefCell<T>>, serializer: S) -> Result<S::Ok, S::Error>
+where
+    T: Serialize,
+{
+    let address = Rc::as_ptr(value) as *const () as usize;
+    if !SERIALIZING.with(|serializing| serializing.borrow_mut().insert(address)) {
+        return Err(ser::Error::custom("cannot serialize a vector or hash table containing itself"));
+    }
+    let result = value.borrow().serialize(serializer);
+    SERIALIZING.with(|serializing| serializing.borrow_mut().remove(&address));
+    result
+}
+
+pub(crate) fn serialize_vector<S: Serializer>(vector: &Rc<RefCell<Vec<Exp>>>, serializer: S) -> Result<S::Ok, S::Error> {
+    shared(vector, serializer)
+}
+
+pub(crate) fn serialize_hash_table<S: Serializer>(table: &Rc<RefCell<HashMap<HashKey, Exp>>>, serializer: S) -> Result<S::Ok, S::Error> {
+    shared(table, serializer)
+}
+
+/// Deserializes a vector registered with the collector of cycles, like
+/// `Exp::vector`.
+pub(crate) fn deserialize_vector<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Rc<RefCell<Vec<Exp>>>, D::Error> {
+    let vector = Rc::new(RefCell::new(Vec::deserialize(deserializer)?));
+    gc::track(gc::Tracked::Vector(Rc::downgrade(&vector)));
+    Ok(vector)
+}
+
+pub(crate) fn deserialize_hash_table<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Rc<RefCell<HashMap<HashKey, Exp>>>, D::Error> {
+    let table = Rc::new(RefCell::new(HashMap::deserialize(deserializer)?));
+    gc::track(gc::Tracked::HashTable(Rc::downgrade(&table)));
+    Ok(table)
+}
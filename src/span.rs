@@ -0,0 +1,65 @@
+use std::{fmt, rc::Rc};
+
+/// A byte range of the source text an expression was read from.
+#[derive(Clone)]
+pub struct Span {
+    pub source: Rc<str>,
+    pub start: usize,
+    pub end: usize,
+}
+
+impl fmt::Debug for Span {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // The source is left out, it is usually much longer than the span
+        write!(f, "Span({}..{})", self.start, self.end)
+    }
+}
+
+impl Span {
+    pub fn new(source: Rc<str>, start: usize, end: usize) -> Span {
+        Span { source, start, end }
+    }
+
+    /// The 1-based line and column of the start of the span.
+    pub fn line_col(&self) -> (usize, usize) {
+        let before = &self.source[..self.start];
+        let line = before.matches('\n').count() + 1;
+        let column = before.chars().rev().take_while(|&c| c != '\n').count() + 1;
+        (line, column)
+    }
+
+    /// The location of the span, for attaching to an error.
+    pub fn location(&self) -> Location {
+        let (line, column) = self.line_col();
+        let line_start = self.source[..self.start].rfind('\n').map_or(0, |i| i + 1);
+        let line_end = self.source[self.start..].find('\n').map_or(self.source.len(), |i| self.start + i);
+        let text = self.source[line_start..line_end].to_string();
+        // Spans reaching over several lines are only marked up to the end
+        // of the first
+        let width = self.source[self.start..self.end.clamp(self.start, line_end)].chars().count().max(1);
+        Location { line, column, text, width }
+    }
+}
+
+/// Where in the source an error happened: the line and column and the text
+/// of the line.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Location {
+    pub line: usize,
+    pub column: usize,
+    text: String,
+    width: usize,
+}
+
+impl Location {
+    /// The line of source text with the erroneous part marked by carets.
+    pub fn snippet(&self) -> String {
+        format!("{}\n{}{}", self.text, " ".repeat(self.column - 1), "^".repeat(self.width))
+    }
+}
+
+impl fmt::Display for Location {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "at line {}, column {}", self.line, self.column)
+    }
+}
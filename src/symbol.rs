@@ -0,0 +1,83 @@
+use std::{cell::RefCell, collections::HashSet, fmt, hash::{Hash, Hasher}, ops::Deref};
+
+thread_local! {
+    static SYMBOLS: RefCell<HashSet<&'static str>> = RefCell::new(HashSet::new());
+}
+
+/// An interned symbol name.
+///
+/// All symbols with the same name share one allocation, so symbols are
+/// copied as a pointer and comparing two symbols compares pointers. Names
+/// are never freed, like the names of a symbol table.
+#[derive(Clone, Copy)]
+pub struct Symbol(&'static str);
+
+impl Symbol {
+    pub fn new(name: &str) -> Symbol {
+        SYMBOLS.with(|symbols| {
+            let mut symbols = symbols.borrow_mut();
+            if let Some(symbol) = symbols.get(name) {
+                return Symbol(symbol);
+            }
+            let symbol: &'static str = Box::leak(name.into());
+            symbols.insert(symbol);
+            Symbol(symbol)
+        })
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        self.0
+    }
+}
+
+impl Deref for Symbol {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        self.0
+    }
+}
+
+impl PartialEq for Symbol {
+    fn eq(&self, other: &Self) -> bool {
+        std::ptr::eq(self.0, other.0)
+    }
+}
+
+impl Eq for Symbol {}
+
+impl PartialEq<str> for Symbol {
+    fn eq(&self, other: &str) -> bool {
+        self.0 == other
+    }
+}
+
+impl PartialEq<&str> for Symbol {
+    fn eq(&self, other: &&str) -> bool {
+        self.0 == *other
+    }
+}
+
+impl Hash for Symbol {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        std::ptr::hash(self.0.as_ptr(), state)
+    }
+}
+
+impl From<&str> for Symbol {
+    fn from(name: &str) -> Symbol {
+        Symbol::new(name)
+    }
+}
+
+impl fmt::Debug for Symbol {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(self.0, f)
+    }
+}
+
+impl fmt::Display for Symbol {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.0)
+    }
+}
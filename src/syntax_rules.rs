@@ -0,0 +1,273 @@
+use std::{cell::Cell, collections::{HashMap, HashSet}, fmt};
+
+use crate::{error::{Result, eval_error}, exp::{Atom, Exp, List}, symbol::Symbol};
+
+const ELLIPSIS: &str = "...";
+const WILDCARD: &str = "_";
+
+/// A macro defined by `define-syntax` with `syntax-rules`.
+///
+/// Symbols a template binds with `let`, `lambda`, `do` or `define` are
+/// renamed in each expansion, so that these bindings cannot capture the
+/// caller's variables. Free symbols of the template keep their names.
+pub struct SyntaxRules {
+    literals: Vec<Symbol>,
+    rules: Vec<(Exp, Exp)>,
+}
+
+impl fmt::Debug for SyntaxRules {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SyntaxRules")
+            .field("literals", &self.literals)
+            .field("rules", &self.rules)
+            .finish()
+    }
+}
+
+impl PartialEq for SyntaxRules {
+    fn eq(&self, other: &Self) -> bool {
+        std::ptr::eq(self, other)
+    }
+}
+
+/// What a pattern variable matched, with one level of nesting per ellipsis
+/// following it in the pattern.
+#[derive(Debug, Clone)]
+enum Binding {
+    One(Exp),
+    Many(Vec<Binding>),
+}
+
+type Bindings = HashMap<Symbol, Binding>;
+
+thread_local! {
+    static EXPANSIONS: Cell<u64> = const { Cell::new(0) };
+}
+
+fn is_symbol(exp: &Exp, name: &str) -> bool {
+    matches!(exp, Exp::Atom(Atom::Symbol(s)) if s == name)
+}
+
+impl SyntaxRules {
+    /// Reads `(syntax-rules (literal...) (pattern template)...)`.
+    pub fn new(spec: &Exp) -> Result<SyntaxRules> {
+        let Exp::List(spec) = spec else {
+            return Err(eval_error!("Not a syntax-rules form: {}", spec));
+        };
+        if !spec.first().is_some_and(|head| is_symbol(head, "syntax-rules")) {
+            return Err(eval_error!("Expected syntax-rules, got {}", spec.first().map_or_else(|| "nothing".to_string(), Exp::to_string)));
+        }
+        let Some(Exp::List(literals)) = spec.get(1) else {
+            return Err(eval_error!("syntax-rules expects a list of literals"));
+        };
+        let literals = literals.iter().map(|l| l.clone().extract_symbol()).collect::<Result<Vec<_>>>()?;
+        let rules = spec[2..].iter().map(|rule| match rule {
+            Exp::List(rule) if rule.len() == 2 => Ok((rule[0].clone(), rule[1].clone())),
+            _ => Err(eval_error!("Not a syntax rule: {}", rule)),
+        }).collect::<Result<Vec<_>>>()?;
+        Ok(SyntaxRules { literals, rules })
+    }
+
+    /// Expands the macro call `form` with the first matching rule.
+    pub fn expand(&self, form: &List) -> Result<Exp> {
+        for (pattern, template) in &self.rules {
+            // The keyword position of the pattern is ignored
+            let Exp::List(pattern) = pattern else {
+                return Err(eval_error!("Not a syntax rule pattern: {}", pattern));
+            };
+            let mut bindings = Bindings::new();
+            if pattern.is_empty() || !self.match_list(&pattern[1..], &form[1..], &mut bindings)? {
+                continue;
+            }
+            let expansion = EXPANSIONS.with(|count| {
+                count.set(count.get() + 1);
+                count.get()
+            });
+            let mut binders = HashSet::new();
+            template_binders(template, &mut binders);
+            // % cannot appear in symbols read by the lexer
+            let renames = binders.into_iter()
+                .filter(|symbol| !bindings.contains_key(symbol) && *symbol != ELLIPSIS)
+                .map(|symbol| {
+                    let renamed = Symbol::new(&format!("{}%{}", symbol, expansion));
+                    (symbol, renamed)
+                })
+                .collect();
+            return self.instantiate(template, &bindings, &renames, false);
+        }
+        Err(eval_error!("No syntax rule matches {}", Exp::List(form.clone())))
+    }
+
+    fn match_pattern(&self, pattern: &Exp, form: &Exp, bindings: &mut Bindings) -> Result<bool> {
+        match pattern {
+            Exp::Atom(Atom::Symbol(s)) if s == WILDCARD => Ok(true),
+            Exp::Atom(Atom::Symbol(s)) if self.literals.contains(s) => Ok(is_symbol(form, s)),
+            Exp::Atom(Atom::Symbol(s)) => {
+                bindings.insert(*s, Binding::One(form.clone()));
+                Ok(true)
+            },
+            Exp::List(pattern) => match form {
+                Exp::List(form) => self.match_list(pattern, form, bindings),
+                _ => Ok(false),
+            },
+            _ => Ok(pattern == form),
+        }
+    }
+
+    fn match_list(&self, pattern: &[Exp], form: &[Exp], bindings: &mut Bindings) -> Result<bool> {
+        let Some(ellipsis) = pattern.iter().position(|p| is_symbol(p, ELLIPSIS)) else {
+            if pattern.len() != form.len() {
+                return Ok(false);
+            }
+            for (p, f) in pattern.iter().zip(form) {
+                if !self.match_pattern(p, f, bindings)? {
+                    return Ok(false);
+                }
+            }
+            return Ok(true);
+        };
+        if ellipsis == 0 {
+            return Err(eval_error!("Ellipsis without a preceding pattern"));
+        }
+        let (before, repeated, after) = (&pattern[..ellipsis - 1], &pattern[ellipsis - 1], &pattern[ellipsis + 1..]);
+        if form.len() < before.len() + after.len() {
+            return Ok(false);
+        }
+        let repeats = form.len() - before.len() - after.len();
+        if !self.match_list(before, &form[..before.len()], bindings)?
+            || !self.match_list(after, &form[form.len() - after.len()..], bindings)? {
+            return Ok(false);
+        }
+        let mut matches = Vec::with_capacity(repeats);
+        for f in &form[before.len()..before.len() + repeats] {
+            let mut repeat_bindings = Bindings::new();
+            if !self.match_pattern(repeated, f, &mut repeat_bindings)? {
+                return Ok(false);
+            }
+            matches.push(repeat_bindings);
+        }
+        for var in self.pattern_vars(repeated) {
+            let repeat = matches.iter_mut().map(|m| m.remove(&var).unwrap_or(Binding::Many(Vec::new()))).collect();
+            bindings.insert(var, Binding::Many(repeat));
+        }
+        Ok(true)
+    }
+
+    fn pattern_vars(&self, pattern: &Exp) -> Vec<Symbol> {
+        match pattern {
+            Exp::Atom(Atom::Symbol(s)) if s != WILDCARD && s != ELLIPSIS && !self.literals.contains(s) => vec![*s],
+            Exp::List(l) => l.iter().flat_map(|p| self.pattern_vars(p)).collect(),
+            _ => Vec::new(),
+        }
+    }
+
+    /// Fills the pattern variables into `template`. Symbols in `quoted`
+    /// parts are data and keep their names.
+    fn instantiate(&self, template: &Exp, bindings: &Bindings, renames: &HashMap<Symbol, Symbol>, quoted: bool) -> Result<Exp> {
+        match template {
+            Exp::Atom(Atom::Symbol(s)) => match bindings.get(s) {
+                Some(Binding::One(exp)) => Ok(exp.clone()),
+                Some(Binding::Many(_)) => Err(eval_error!("Pattern variable {} used without ellipsis", s)),
+                None if quoted => Ok(template.clone()),
+                None => Ok(Exp::Atom(Atom::Symbol(*renames.get(s).unwrap_or(s)))),
+            },
+            Exp::List(template) => {
+                let quoted = match template.first() {
+                    Some(Exp::Atom(Atom::Symbol(s))) if s == "quote" || s == "quasiquote" => true,
+                    Some(Exp::Atom(Atom::Symbol(s))) if s == "unquote" || s == "unquote-splicing" => false,
+                    _ => quoted,
+                };
+                let mut result = List::new();
+                let mut i = 0;
+                while i < template.len() {
+                    let element = &template[i];
+                    if template.get(i + 1).is_some_and(|next| is_symbol(next, ELLIPSIS)) {
+                        for repeat_bindings in self.repeat(element, bindings)? {
+                            result.push(self.instantiate(element, &repeat_bindings, renames, quoted)?);
+                        }
+                        i += 2;
+                    } else {
+                        result.push(self.instantiate(element, bindings, renames, quoted)?);
+                        i += 1;
+                    }
+                }
+                Ok(Exp::List(result))
+            },
+            _ => Ok(template.clone()),
+        }
+    }
+
+    /// The bindings for each repetition of `template`, which is followed by
+    /// an ellipsis.
+    fn repeat(&self, template: &Exp, bindings: &Bindings) -> Result<Vec<Bindings>> {
+        let vars = self.template_vars(template, bindings);
+        let mut len = None;
+        for var in &vars {
+            if let Some(Binding::Many(repeat)) = bindings.get(var) {
+                if len.is_some_and(|len| len != repeat.len()) {
+                    return Err(eval_error!("Pattern variables under the same ellipsis matched different lengths"));
+                }
+                len = Some(repeat.len());
+            }
+        }
+        let len = len.ok_or(eval_error!("Ellipsis in template without a pattern variable: {}", template))?;
+        Ok((0..len).map(|i| {
+            let mut repeat_bindings = bindings.clone();
+            for var in &vars {
+                if let Some(Binding::Many(repeat)) = bindings.get(var) {
+                    repeat_bindings.insert(*var, repeat[i].clone());
+                }
+            }
+            repeat_bindings
+        }).collect())
+    }
+
+    fn template_vars(&self, template: &Exp, bindings: &Bindings) -> Vec<Symbol> {
+        match template {
+            Exp::Atom(Atom::Symbol(s)) if bindings.contains_key(s) => vec![*s],
+            Exp::List(l) => l.iter().flat_map(|t| self.template_vars(t, bindings)).collect(),
+            _ => Vec::new(),
+        }
+    }
+}
+
+/// Collects the symbols `template` binds with `let`, `lambda`, `do` or
+/// `define`.
+fn template_binders(template: &Exp, binders: &mut HashSet<Symbol>) {
+    let Exp::List(l) = template else {
+        return;
+    };
+    let mut symbols = |exp: Option<&Exp>| match exp {
+        Some(Exp::Atom(Atom::Symbol(s))) => {
+            binders.insert(*s);
+        },
+        Some(Exp::List(l)) => {
+            for exp in l {
+                // Either a parameter or the variable of a (sym init) binding
+                let symbol = match exp {
+                    Exp::List(binding) => binding.first(),
+                    exp => Some(exp),
+                };
+                if let Some(Exp::Atom(Atom::Symbol(s))) = symbol {
+                    binders.insert(*s);
+                }
+            }
+        },
+        _ => {},
+    };
+    match l.first() {
+        Some(Exp::Atom(Atom::Symbol(s))) if s == "quote" => return,
+        Some(Exp::Atom(Atom::Symbol(s))) if s == "let" && matches!(l.get(1), Some(Exp::Atom(_))) => {
+            // Named let
+            symbols(l.get(1));
+            symbols(l.get(2));
+        },
+        Some(Exp::Atom(Atom::Symbol(s))) if ["let", "let*", "letrec", "do", "lambda", "define", "define-values"].contains(&s.as_str()) => {
+            symbols(l.get(1));
+        },
+        _ => {},
+    }
+    for exp in l {
+        template_binders(exp, binders);
+    }
+}
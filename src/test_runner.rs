@@ -0,0 +1,65 @@
+//! `brewlis test`, which runs the tests defined with `define-test` in a
+//! directory of brewlis files.
+
+use std::{fs, path::{Path, PathBuf}};
+
+use anyhow::{Context, Result};
+
+use brewlis::{Interpreter, testing};
+
+use crate::color::Palette;
+
+/// Adds the `.brl` files in `path` and the directories below it to `files`,
+/// or `path` itself if it is a file.
+fn find_files(path: &Path, files: &mut Vec<PathBuf>) -> Result<()> {
+    if !path.is_dir() {
+        files.push(path.to_path_buf());
+        return Ok(());
+    }
+    for entry in fs::read_dir(path).with_context(|| format!("Cannot read '{}'", path.display()))? {
+        let path = entry?.path();
+        if path.is_dir() {
+            find_files(&path, files)?;
+        } else if path.extension().is_some_and(|extension| extension == "brl") {
+            files.push(path);
+        }
+    }
+    Ok(())
+}
+
+/// Loads each test file in a new interpreter and runs the tests it
+/// defines. Returns the exit status, 1 if a test failed or a file could
+/// not be loaded.
+pub fn run(paths: &[String], new_interpreter: &dyn Fn() -> Interpreter, palette: Palette) -> Result<i32> {
+    let mut files = Vec::new();
+    for path in paths {
+        find_files(Path::new(path), &mut files)?;
+    }
+    files.sort();
+    let (mut passed, mut failed) = (0, 0);
+    for file in &files {
+        let path = file.display().to_string();
+        let mut interpreter = new_interpreter();
+        // Tests of an earlier file that failed to load are not run again
+        testing::take();
+        if let Err(err) = interpreter.load(&path) {
+            failed += 1;
+            println!("{}", palette.error(&format!("FAIL {}: {}", path, err.report())));
+            continue;
+        }
+        for test in testing::take() {
+            match test.run(interpreter.env()) {
+                Ok(()) => {
+                    passed += 1;
+                    println!("{}", palette.success(&format!("PASS {}: {}", path, test.name)));
+                },
+                Err(err) => {
+                    failed += 1;
+                    println!("{}", palette.error(&format!("FAIL {}: {}: {}", path, test.name, err.report())));
+                },
+            }
+        }
+    }
+    println!("{} passed, {} failed", passed, failed);
+    Ok(if failed == 0 { 0 } else { 1 })
+}
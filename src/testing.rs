@@ -0,0 +1,63 @@
+//! Tests written in brewlis.
+//!
+//! `(define-test name body...)` registers a test instead of running it, so
+//! a file of tests can be loaded first and its tests run afterwards, by
+//! `(run-tests)` or by `brewlis test`. A test passes if its body returns
+//! and fails with the error it raised otherwise.
+
+use std::cell::RefCell;
+
+use crate::{env::Env, error::Result, eval, exp::Exp};
+
+thread_local! {
+    /// The tests defined and not run yet, in the order of their definition.
+    static TESTS: RefCell<Vec<Test>> = const { RefCell::new(Vec::new()) };
+}
+
+pub struct Test {
+    pub name: String,
+    /// A procedure without parameters evaluating the body of the test.
+    body: Exp,
+}
+
+impl Test {
+    pub fn run(&self, env: &Env) -> Result<()> {
+        eval::proc(&self.body, &[], env).map(|_| ())
+    }
+}
+
+/// Registers the test `name`, replacing an earlier test of that name.
+pub(crate) fn register(name: String, body: Exp) {
+    TESTS.with(|tests| {
+        let mut tests = tests.borrow_mut();
+        match tests.iter_mut().find(|test| test.name == name) {
+            Some(test) => test.body = body,
+            None => tests.push(Test { name, body }),
+        }
+    });
+}
+
+/// Removes the registered tests and returns them in the order they were
+/// defined.
+pub fn take() -> Vec<Test> {
+    TESTS.with(|tests| std::mem::take(&mut *tests.borrow_mut()))
+}
+
+/// Runs the registered tests, writing a line for each and a summary with
+/// `write`. Returns whether all passed; `exit` ends the run.
+pub(crate) fn run_all(env: &Env, mut write: impl FnMut(&str) -> Result<()>) -> Result<bool> {
+    let mut failed = 0;
+    let tests = take();
+    for test in &tests {
+        match test.run(env) {
+            Ok(()) => write(&format!("PASS {}\n", test.name))?,
+            Err(err) if err.exit_code().is_some() => return Err(err),
+            Err(err) => {
+                failed += 1;
+                write(&format!("FAIL {}: {}\n", test.name, err.report()))?;
+            },
+        }
+    }
+    write(&format!("{} passed, {} failed\n", tests.len() - failed, failed))?;
+    Ok(failed == 0)
+}
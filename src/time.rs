@@ -0,0 +1,50 @@
+//! The date and time builtins, and the measurements of `(time exp)`.
+//!
+//! Times are numbers of seconds since the Unix epoch, so time arithmetic is
+//! arithmetic on numbers: a duration is the difference of two times and
+//! `(+ t 3600)` is an hour after `t`.
+
+use std::fmt::Write;
+
+use chrono::{DateTime, Local, Utc};
+
+use crate::{error::{Result, eval_error}, eval, exp::{Atom, Exp}, gc, vm};
+
+/// The current time as an exact number of whole seconds.
+pub fn current_seconds() -> Exp {
+    Exp::Atom(Atom::Int(Utc::now().timestamp()))
+}
+
+/// The current time as a float with a fraction of a second.
+pub fn current_time() -> Exp {
+    let now = Utc::now();
+    Exp::Atom(Atom::Number(now.timestamp() as f64 + f64::from(now.timestamp_subsec_nanos()) / 1e9))
+}
+
+/// Formats `time` in the local time zone with the `strftime` directives of
+/// `template`, like `%Y-%m-%d %H:%M:%S`.
+pub fn format(time: f64, template: &str) -> Result<String> {
+    if !time.is_finite() {
+        return Err(eval_error!("Time out of range: {}", time));
+    }
+    let seconds = time.floor();
+    let nanos = ((time - seconds) * 1e9) as u32;
+    let time = DateTime::from_timestamp(seconds as i64, nanos).ok_or(eval_error!("Time out of range: {}", time))?;
+    let mut formatted = String::new();
+    write!(formatted, "{}", time.with_timezone(&Local).format(template))
+        .map_err(|_| eval_error!("Invalid time format: {:?}", template))?;
+    Ok(formatted)
+}
+
+/// Calls `f` for `(time exp)` and writes how long it took with `write`:
+/// the wall-clock time, the expressions the tree-walker evaluated, the
+/// instructions the VM ran and the containers created.
+pub(crate) fn timed(f: impl FnOnce() -> Result<Exp>, write: impl FnOnce(&str) -> Result<()>) -> Result<Exp> {
+    let (evaluations, instructions, allocations) = (eval::evaluations(), vm::instructions(), gc::allocations());
+    let start = Utc::now();
+    let value = f()?;
+    let elapsed = (Utc::now() - start).num_microseconds().unwrap_or(i64::MAX) as f64 / 1000.0;
+    write(&format!("time: {:.3} ms, {} evaluations, {} instructions, {} allocations\n", elapsed,
+        eval::evaluations() - evaluations, vm::instructions() - instructions, gc::allocations() - allocations))?;
+    Ok(value)
+}
@@ -0,0 +1,106 @@
+//! Tracing of procedure calls.
+//!
+//! `(trace f)` makes calls of the procedure `f` print its arguments when
+//! they start and its value when they return, indented by how many traced
+//! calls they are nested in. `--trace` does the same for every procedure
+//! written in brewlis, builtins are not traced. Traced calls are not in
+//! tail position, so traced loops use stack space.
+
+use std::{cell::{Cell, RefCell}, rc::Rc};
+
+use crate::{error::{BrewlisError, Result}, exp::Exp, symbol::Symbol};
+
+thread_local! {
+    static TRACE_ALL: Cell<bool> = const { Cell::new(false) };
+    /// The procedures traced by `trace` with the names they were traced as.
+    /// They are kept alive until untraced.
+    static TRACED: RefCell<Vec<(Exp, Symbol)>> = const { RefCell::new(Vec::new()) };
+    /// Whether anything is traced, checked on every call.
+    static ACTIVE: Cell<bool> = const { Cell::new(false) };
+    /// The traced calls running.
+    static DEPTH: Cell<usize> = const { Cell::new(0) };
+}
+
+fn update_active() {
+    let active = TRACE_ALL.with(Cell::get) || TRACED.with(|traced| !traced.borrow().is_empty());
+    ACTIVE.with(|cell| cell.set(active));
+}
+
+/// Traces the calls of all procedures written in brewlis, for all
+/// interpreters of the thread.
+pub fn set_trace_all(enabled: bool) {
+    TRACE_ALL.with(|trace_all| trace_all.set(enabled));
+    update_active();
+}
+
+fn same_procedure(a: &Exp, b: &Exp) -> bool {
+    match (a, b) {
+        (Exp::Lambda(a), Exp::Lambda(b)) => Rc::ptr_eq(a, b),
+        (Exp::Closure(a), Exp::Closure(b)) => Rc::ptr_eq(a, b),
+        _ => false,
+    }
+}
+
+/// Traces the calls of `procedure` as `name`.
+pub(crate) fn trace(procedure: &Exp, name: Symbol) -> Result<()> {
+    if !matches!(procedure, Exp::Lambda(_) | Exp::Closure(_)) {
+        return Err(BrewlisError::type_error("a procedure written in brewlis", procedure));
+    }
+    TRACED.with(|traced| {
+        let mut traced = traced.borrow_mut();
+        traced.retain(|(traced, _)| !same_procedure(traced, procedure));
+        traced.push((procedure.clone(), name));
+    });
+    update_active();
+    Ok(())
+}
+
+/// Stops tracing `procedure`, or every procedure traced by `trace` without
+/// one.
+pub(crate) fn untrace(procedure: Option<&Exp>) {
+    TRACED.with(|traced| match procedure {
+        Some(procedure) => traced.borrow_mut().retain(|(traced, _)| !same_procedure(traced, procedure)),
+        None => traced.borrow_mut().clear(),
+    });
+    update_active();
+}
+
+/// The name to show calls of `procedure` with if they are traced. Without
+/// a name from `trace`, it is `operator`, the name the procedure was called
+/// by or defined as.
+pub(crate) fn traced_name(procedure: &Exp, operator: Option<Symbol>) -> Option<String> {
+    if !ACTIVE.with(Cell::get) || !matches!(procedure, Exp::Lambda(_) | Exp::Closure(_)) {
+        return None;
+    }
+    let traced = TRACED.with(|traced| traced.borrow().iter().find(|(traced, _)| same_procedure(traced, procedure)).map(|(_, name)| *name));
+    match (traced, operator) {
+        (Some(name), _) => Some(name.to_string()),
+        (None, _) if !TRACE_ALL.with(Cell::get) => None,
+        (None, Some(operator)) => Some(operator.to_string()),
+        (None, None) => Some(procedure.to_string()),
+    }
+}
+
+fn indentation(depth: usize) -> String {
+    "  ".repeat(depth)
+}
+
+/// The line announcing a traced call, which is nested in the traced calls
+/// running.
+pub(crate) fn enter(name: &str, args: &[Exp]) -> String {
+    let depth = DEPTH.with(|depth| depth.replace(depth.get() + 1));
+    let args = args.iter().map(|arg| format!(" {}", arg)).collect::<String>();
+    format!("{}({}{})\n", indentation(depth), name, args)
+}
+
+/// The line announcing the value of the innermost traced call.
+pub(crate) fn leave(value: &Exp) -> String {
+    unwind();
+    let depth = DEPTH.with(Cell::get);
+    format!("{}=> {}\n", indentation(depth), value)
+}
+
+/// Ends the innermost traced call after it failed.
+pub(crate) fn unwind() {
+    DEPTH.with(|depth| depth.set(depth.get().saturating_sub(1)));
+}
@@ -0,0 +1,789 @@
+//! A bytecode compiler and stack-based virtual machine, an alternative to
+//! the tree-walking evaluator of [`crate::eval`].
+//!
+//! Top-level forms are compiled to flat code: every procedure gets a table
+//! of constants, its parameters and local variables live in numbered slots
+//! of its frame, variables of enclosing procedures are captured as upvalues
+//! and conditionals become jumps. Forms the compiler does not handle, like
+//! macros, `guard` or `quasiquote`, are evaluated by the tree-walker
+//! instead, and the VM calls builtins through it.
+
+use std::{cell::{Cell, RefCell}, fmt, rc::Rc};
+
+use crate::{builtins::Arity, env::Env, gc, error::{BrewlisError, Result, eval_error}, eval::{self, is_true}, exp::{Atom, Exp}, span::Span, symbol::Symbol, trace};
+
+thread_local! {
+    /// The instructions run since the thread started, reported by `time`.
+    static INSTRUCTIONS: Cell<u64> = const { Cell::new(0) };
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Op {
+    /// Pushes a constant.
+    Const(usize),
+    /// Pushes the value of a local slot.
+    Local(usize),
+    /// Pushes the value of a captured variable.
+    Upvalue(usize),
+    /// Pushes the value of the global named by a constant, or the symbol
+    /// itself if it is unbound.
+    Global(usize),
+    /// Pops a value into a local slot.
+    StoreLocal(usize),
+    StoreUpvalue(usize),
+    /// Pops a value and defines the global named by a constant.
+    DefineGlobal(usize),
+    /// Pops a value and assigns the global named by a constant.
+    SetGlobal(usize),
+    Pop,
+    Jump(usize),
+    /// Pops a value and jumps if it is false.
+    JumpIfFalse(usize),
+    /// Jumps if the value on top is true, without popping it.
+    JumpIfTrue(usize),
+    /// Jumps if the value on top is false, without popping it.
+    JumpIfFalseKeep(usize),
+    /// Creates a closure of a nested procedure.
+    Closure(usize),
+    /// Calls the procedure below the given number of arguments.
+    Call(usize),
+    /// Like `Call`, but replaces the current frame if it calls a closure.
+    TailCall(usize),
+    Return,
+}
+
+/// Where a closure finds a captured variable when it is created: in a slot
+/// of the enclosing frame or among the upvalues of the enclosing closure.
+#[derive(Debug, Clone, Copy)]
+enum Capture {
+    Local(usize),
+    Upvalue(usize),
+}
+
+/// The compiled code of a procedure.
+#[derive(Debug, Default)]
+pub struct Proto {
+    code: Vec<Op>,
+    /// The span of the innermost form each op was compiled from.
+    spans: Vec<Option<Span>>,
+    constants: Vec<Exp>,
+    protos: Vec<Rc<Proto>>,
+    params: usize,
+    /// Whether the slot after the parameters takes the list of the
+    /// remaining arguments.
+    rest: bool,
+    slots: usize,
+    captures: Vec<Capture>,
+    /// The name the procedure was defined as, shown when its calls are
+    /// traced.
+    name: Option<Symbol>,
+}
+
+/// A compiled procedure with its captured variables.
+pub struct Closure {
+    proto: Rc<Proto>,
+    pub(crate) upvalues: Vec<Rc<RefCell<Exp>>>,
+    /// The environment globals are looked up in.
+    pub(crate) env: Env,
+}
+
+impl Closure {
+    pub(crate) fn name(&self) -> Option<Symbol> {
+        self.proto.name
+    }
+
+    pub(crate) fn arity(&self) -> Arity {
+        match self.proto.rest {
+            true => Arity::AtLeast(self.proto.params),
+            false => Arity::Exactly(self.proto.params),
+        }
+    }
+}
+
+impl fmt::Debug for Closure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Closure").field("proto", &self.proto).finish()
+    }
+}
+
+impl PartialEq for Closure {
+    fn eq(&self, other: &Self) -> bool {
+        std::ptr::eq(self, other)
+    }
+}
+
+/// A form the compiler leaves to the tree-walking evaluator.
+struct Unsupported;
+
+/// The state of compiling one procedure.
+struct Function {
+    proto: Proto,
+    /// The local variables in scope with their slots, innermost last.
+    locals: Vec<(Symbol, usize)>,
+    /// The names of the captured variables, in the order of `captures`.
+    captured: Vec<Symbol>,
+    /// Where the locals of the innermost body start in `locals`.
+    body_start: usize,
+}
+
+struct Compiler<'a> {
+    /// The procedures being compiled, the innermost last. The first is the
+    /// top-level form, whose definitions outside of any scope are global.
+    functions: Vec<Function>,
+    span: Option<Span>,
+    env: &'a Env,
+}
+
+enum Var {
+    Local(usize),
+    Upvalue(usize),
+    Global,
+}
+
+fn special_form(sym: &Symbol) -> bool {
+    matches!(sym.as_str(), "if" | "when" | "unless" | "quote" | "quasiquote" | "begin" | "define" | "define-values"
+        | "set!" | "define-macro" | "define-syntax" | "define-test" | "assert-error" | "delay" | "lambda" | "and" | "or"
+        | "cond" | "case" | "guard" | "trace" | "untrace" | "time" | "help" | "define-module" | "export" | "import" | "break" | "while" | "do" | "let" | "let*" | "letrec")
+}
+
+/// The names defined by the internal `(define name ...)` and
+/// `(define (name params...) ...)` forms of `body`, also those in a
+/// `begin`, which defines in the body around it.
+fn defined_names(body: &[Exp]) -> Vec<Symbol> {
+    let mut names = Vec::new();
+    for exp in body {
+        match exp {
+            Exp::List(l) if matches!(l.first(), Some(Exp::Atom(Atom::Symbol(s))) if s == "begin") => names.extend(defined_names(&l[1..])),
+            exp => names.extend(defined_name(exp)),
+        }
+    }
+    names
+}
+
+fn defined_name(exp: &Exp) -> Option<Symbol> {
+    let Exp::List(l) = exp else { return None };
+    match (l.first(), l.get(1)) {
+        (Some(Exp::Atom(Atom::Symbol(s))), Some(Exp::Atom(Atom::Symbol(name)))) if s == "define" => Some(*name),
+        (Some(Exp::Atom(Atom::Symbol(s))), Some(Exp::List(signature))) if s == "define" => match signature.first() {
+            Some(Exp::Atom(Atom::Symbol(name))) => Some(*name),
+            _ => None,
+        },
+        // (define (name . rest) ...)
+        (Some(Exp::Atom(Atom::Symbol(s))), Some(Exp::Pair(name, _))) if s == "define" => match name.as_ref() {
+            Exp::Atom(Atom::Symbol(name)) => Some(*name),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+fn symbols(exps: &[Exp]) -> std::result::Result<Vec<Symbol>, Unsupported> {
+    exps.iter().map(|exp| match exp {
+        Exp::Atom(Atom::Symbol(s)) => Ok(*s),
+        _ => Err(Unsupported),
+    }).collect()
+}
+
+/// The required parameters `exps` and the rest parameter `rest` after the
+/// dot of a parameter list. Optional parameters are left to the
+/// tree-walker, which evaluates their defaults.
+fn params(exps: &[Exp], rest: Option<&Exp>) -> std::result::Result<(Vec<Symbol>, Option<Symbol>), Unsupported> {
+    let rest = match rest {
+        Some(Exp::Atom(Atom::Symbol(rest))) => Some(*rest),
+        Some(_) => return Err(Unsupported),
+        None => None,
+    };
+    Ok((symbols(exps)?, rest))
+}
+
+/// Splits the `((sym init) ...)` bindings of a `let` form.
+fn bindings(exp: Option<&Exp>) -> std::result::Result<Vec<(Symbol, &Exp)>, Unsupported> {
+    let Some(Exp::List(bindings)) = exp else { return Err(Unsupported) };
+    bindings.iter().map(|binding| match binding {
+        Exp::List(binding) if binding.len() == 2 => match &binding[0] {
+            Exp::Atom(Atom::Symbol(s)) => Ok((*s, &binding[1])),
+            _ => Err(Unsupported),
+        },
+        _ => Err(Unsupported),
+    }).collect()
+}
+
+type Compiled = std::result::Result<(), Unsupported>;
+
+impl Compiler<'_> {
+    fn function(&mut self) -> &mut Function {
+        self.functions.last_mut().expect("compiling a function")
+    }
+
+    fn emit(&mut self, op: Op) -> usize {
+        let span = self.span.clone();
+        let proto = &mut self.function().proto;
+        proto.code.push(op);
+        proto.spans.push(span);
+        proto.code.len() - 1
+    }
+
+    /// Points the jump at `index` to the next op.
+    fn patch(&mut self, index: usize) {
+        let proto = &mut self.function().proto;
+        let target = proto.code.len();
+        match &mut proto.code[index] {
+            Op::Jump(t) | Op::JumpIfFalse(t) | Op::JumpIfTrue(t) | Op::JumpIfFalseKeep(t) => *t = target,
+            op => unreachable!("patched {:?}", op),
+        }
+    }
+
+    fn constant(&mut self, exp: Exp) -> usize {
+        let constants = &mut self.function().proto.constants;
+        constants.push(exp);
+        constants.len() - 1
+    }
+
+    fn declare(&mut self, name: Symbol) -> usize {
+        let function = self.function();
+        let slot = function.proto.slots;
+        function.proto.slots += 1;
+        function.locals.push((name, slot));
+        slot
+    }
+
+    /// Whether definitions are global here: in the top-level form, outside
+    /// of any local scope.
+    fn at_top_level(&self) -> bool {
+        self.functions.len() == 1 && self.functions[0].locals.is_empty()
+    }
+
+    fn resolve(&mut self, depth: usize, name: &Symbol) -> Var {
+        let function = &self.functions[depth];
+        if let Some((_, slot)) = function.locals.iter().rev().find(|(local, _)| local == name) {
+            return Var::Local(*slot);
+        }
+        if let Some(i) = function.captured.iter().position(|captured| captured == name) {
+            return Var::Upvalue(i);
+        }
+        if depth == 0 {
+            return Var::Global;
+        }
+        let capture = match self.resolve(depth - 1, name) {
+            Var::Local(slot) => Capture::Local(slot),
+            Var::Upvalue(i) => Capture::Upvalue(i),
+            Var::Global => return Var::Global,
+        };
+        let function = &mut self.functions[depth];
+        function.captured.push(*name);
+        function.proto.captures.push(capture);
+        Var::Upvalue(function.captured.len() - 1)
+    }
+
+    fn store(&mut self, name: &Symbol) {
+        match self.resolve(self.functions.len() - 1, name) {
+            Var::Local(slot) => self.emit(Op::StoreLocal(slot)),
+            Var::Upvalue(i) => self.emit(Op::StoreUpvalue(i)),
+            Var::Global => {
+                let name = self.constant(Exp::Atom(Atom::Symbol(*name)));
+                self.emit(Op::SetGlobal(name))
+            },
+        };
+    }
+
+    fn compile(&mut self, exp: &Exp, tail: bool) -> Compiled {
+        let l = match exp {
+            Exp::Atom(Atom::Symbol(sym)) => {
+                match self.resolve(self.functions.len() - 1, sym) {
+                    Var::Local(slot) => self.emit(Op::Local(slot)),
+                    Var::Upvalue(i) => self.emit(Op::Upvalue(i)),
+                    Var::Global => {
+                        let name = self.constant(exp.clone());
+                        self.emit(Op::Global(name))
+                    },
+                };
+                return Ok(());
+            },
+            Exp::List(l) => l,
+            exp => {
+                let constant = self.constant(exp.clone());
+                self.emit(Op::Const(constant));
+                return Ok(());
+            },
+        };
+        let outer_span = self.span.clone();
+        if let Some(span) = &l.span {
+            self.span = Some(Span::clone(span));
+        }
+        let result = eval::nested(|| self.compile_list(l, tail));
+        self.span = outer_span;
+        result
+    }
+
+    fn compile_list(&mut self, l: &[Exp], tail: bool) -> Compiled {
+        let head = l.first().ok_or(Unsupported)?;
+        if let Exp::Atom(Atom::Symbol(sym)) = head {
+            if special_form(sym) {
+                return self.compile_special_form(sym.as_str(), l, tail);
+            }
+            // Macros are expanded by the tree-walker
+            if matches!(self.resolve(self.functions.len() - 1, sym), Var::Global)
+                && matches!(self.env.get(sym), Some(Exp::Macro(_) | Exp::Syntax(_))) {
+                return Err(Unsupported);
+            }
+        }
+        for exp in l {
+            self.compile(exp, false)?;
+        }
+        self.emit(if tail { Op::TailCall(l.len() - 1) } else { Op::Call(l.len() - 1) });
+        Ok(())
+    }
+
+    fn compile_special_form(&mut self, sym: &str, l: &[Exp], tail: bool) -> Compiled {
+        match sym {
+            "quote" => {
+                let constant = self.constant(l.get(1).ok_or(Unsupported)?.clone());
+                self.emit(Op::Const(constant));
+            },
+            "if" => {
+                self.compile(l.get(1).ok_or(Unsupported)?, false)?;
+                let to_alternative = self.emit(Op::JumpIfFalse(0));
+                self.compile(l.get(2).ok_or(Unsupported)?, tail)?;
+                let to_end = self.emit(Op::Jump(0));
+                self.patch(to_alternative);
+                match l.get(3) {
+                    Some(alternative) => self.compile(alternative, tail)?,
+                    None => self.false_value(),
+                }
+                self.patch(to_end);
+            },
+            "when" | "unless" => {
+                self.compile(l.get(1).ok_or(Unsupported)?, false)?;
+                if sym == "unless" {
+                    let to_false = self.emit(Op::JumpIfFalse(0));
+                    self.false_value();
+                    let to_end = self.emit(Op::Jump(0));
+                    self.patch(to_false);
+                    self.compile_sequence(&l[2..], tail)?;
+                    self.patch(to_end);
+                } else {
+                    let to_false = self.emit(Op::JumpIfFalse(0));
+                    self.compile_sequence(&l[2..], tail)?;
+                    let to_end = self.emit(Op::Jump(0));
+                    self.patch(to_false);
+                    self.false_value();
+                    self.patch(to_end);
+                }
+            },
+            "begin" => self.compile_sequence(&l[1..], tail)?,
+            "and" | "or" => {
+                // Stops at the first false value, or at the first true one
+                let Some((last, init)) = l[1..].split_last() else {
+                    let constant = self.constant(Exp::Atom(Atom::Bool(sym == "and")));
+                    self.emit(Op::Const(constant));
+                    return Ok(());
+                };
+                let mut to_end = Vec::new();
+                for exp in init {
+                    self.compile(exp, false)?;
+                    to_end.push(self.emit(if sym == "or" { Op::JumpIfTrue(0) } else { Op::JumpIfFalseKeep(0) }));
+                    self.emit(Op::Pop);
+                }
+                self.compile(last, tail)?;
+                for jump in to_end {
+                    self.patch(jump);
+                }
+            },
+            "cond" => {
+                let mut to_end = Vec::new();
+                for clause in &l[1..] {
+                    let Exp::List(clause) = clause else { return Err(Unsupported) };
+                    let test = clause.first().ok_or(Unsupported)?;
+                    let body = &clause[1..];
+                    if *test == Exp::Atom(Atom::Symbol(Symbol::new("else"))) {
+                        if body.is_empty() {
+                            let constant = self.constant(Exp::Atom(Atom::Bool(true)));
+                            self.emit(Op::Const(constant));
+                        } else {
+                            self.compile_sequence(body, tail)?;
+                        }
+                        for jump in to_end {
+                            self.patch(jump);
+                        }
+                        return Ok(());
+                    }
+                    self.compile(test, false)?;
+                    if body.is_empty() {
+                        // A clause without expressions returns the value of its test
+                        to_end.push(self.emit(Op::JumpIfTrue(0)));
+                        self.emit(Op::Pop);
+                    } else {
+                        let to_next = self.emit(Op::JumpIfFalse(0));
+                        self.compile_sequence(body, tail)?;
+                        to_end.push(self.emit(Op::Jump(0)));
+                        self.patch(to_next);
+                    }
+                }
+                self.false_value();
+                for jump in to_end {
+                    self.patch(jump);
+                }
+            },
+            "define" => {
+                let (name, value) = match l.get(1) {
+                    Some(signature @ (Exp::List(_) | Exp::Pair(..))) => {
+                        let (signature, rest) = signature.improper_list().ok_or(Unsupported)?;
+                        let Some((Exp::Atom(Atom::Symbol(name)), params)) = signature.split_first() else { return Err(Unsupported) };
+                        let (params, rest) = self::params(params, rest)?;
+                        self.compile_lambda(Some(*name), &params, rest, &l[2..])?;
+                        (*name, None)
+                    },
+                    // Documented values are left to the tree-walker, which
+                    // keeps the documentation
+                    Some(Exp::Atom(Atom::Symbol(_))) if l.len() > 3 => return Err(Unsupported),
+                    Some(Exp::Atom(Atom::Symbol(name))) => (*name, Some(l.get(2).ok_or(Unsupported)?)),
+                    _ => return Err(Unsupported),
+                };
+                if let Some(value) = value {
+                    self.compile(value, false)?;
+                }
+                if self.at_top_level() {
+                    let name = self.constant(Exp::Atom(Atom::Symbol(name)));
+                    self.emit(Op::DefineGlobal(name));
+                } else {
+                    // Internal definitions were declared when their body
+                    // was entered. Others, like one in an if, bind a name
+                    // in the body at run time, which the tree-walker does.
+                    let function = self.function();
+                    if !function.locals[function.body_start..].iter().any(|(local, _)| *local == name) {
+                        return Err(Unsupported);
+                    }
+                    self.store(&name);
+                }
+                self.true_value();
+            },
+            "set!" => {
+                let Some(Exp::Atom(Atom::Symbol(name))) = l.get(1) else { return Err(Unsupported) };
+                self.compile(l.get(2).ok_or(Unsupported)?, false)?;
+                self.store(name);
+                self.true_value();
+            },
+            "lambda" => {
+                match l.get(1) {
+                    Some(params @ (Exp::List(_) | Exp::Pair(..))) => {
+                        let (params, rest) = params.improper_list().ok_or(Unsupported)?;
+                        let (params, rest) = self::params(&params, rest)?;
+                        self.compile_lambda(None, &params, rest, &l[2..])?;
+                    },
+                    // (lambda args body...)
+                    Some(rest @ Exp::Atom(Atom::Symbol(_))) => {
+                        let (params, rest) = self::params(&[], Some(rest))?;
+                        self.compile_lambda(None, &params, rest, &l[2..])?;
+                    },
+                    _ => return Err(Unsupported),
+                }
+            },
+            "let" if matches!(l.get(1), Some(Exp::Atom(Atom::Symbol(_)))) => {
+                // (let name ((sym init)...) body...) calls the procedure name
+                let Exp::Atom(Atom::Symbol(name)) = &l[1] else { unreachable!() };
+                let bindings = bindings(l.get(2))?;
+                let scope = self.function().locals.len();
+                let slot = self.declare(*name);
+                let params = bindings.iter().map(|(symbol, _)| *symbol).collect::<Vec<_>>();
+                self.compile_lambda(Some(*name), &params, None, &l[3..])?;
+                self.emit(Op::StoreLocal(slot));
+                self.emit(Op::Local(slot));
+                for (_, init) in &bindings {
+                    self.compile(init, false)?;
+                }
+                self.emit(if tail { Op::TailCall(bindings.len()) } else { Op::Call(bindings.len()) });
+                self.function().locals.truncate(scope);
+            },
+            "let" | "let*" | "letrec" => {
+                let bindings = bindings(l.get(1))?;
+                let scope = self.function().locals.len();
+                match sym {
+                    "let" => {
+                        for (_, init) in &bindings {
+                            self.compile(init, false)?;
+                        }
+                        let slots = bindings.iter().map(|(symbol, _)| self.declare(*symbol)).collect::<Vec<_>>();
+                        for slot in slots.into_iter().rev() {
+                            self.emit(Op::StoreLocal(slot));
+                        }
+                    },
+                    "let*" => {
+                        for (symbol, init) in &bindings {
+                            self.compile(init, false)?;
+                            let slot = self.declare(*symbol);
+                            self.emit(Op::StoreLocal(slot));
+                        }
+                    },
+                    _ => {
+                        let slots = bindings.iter().map(|(symbol, _)| self.declare(*symbol)).collect::<Vec<_>>();
+                        for ((_, init), slot) in bindings.iter().zip(slots) {
+                            self.compile(init, false)?;
+                            self.emit(Op::StoreLocal(slot));
+                        }
+                    },
+                }
+                self.compile_body(&l[2..], tail)?;
+                self.function().locals.truncate(scope);
+            },
+            "time" => {
+                // (time exp) calls the builtin with (lambda () exp), so exp
+                // is timed on the VM
+                let constant = self.constant(Exp::Builtin(Symbol::new("time")));
+                self.emit(Op::Const(constant));
+                self.compile_lambda(None, &[], None, &[l.get(1).ok_or(Unsupported)?.clone()])?;
+                self.emit(if tail { Op::TailCall(1) } else { Op::Call(1) });
+            },
+            _ => return Err(Unsupported),
+        }
+        Ok(())
+    }
+
+    /// Compiles the expressions of `begin` and the like, leaving the value
+    /// of the last.
+    fn compile_sequence(&mut self, body: &[Exp], tail: bool) -> Compiled {
+        let (last, init) = body.split_last().ok_or(Unsupported)?;
+        for exp in init {
+            self.compile(exp, false)?;
+            self.emit(Op::Pop);
+        }
+        self.compile(last, tail)
+    }
+
+    /// Compiles a procedure or `let` body in a new scope that holds its
+    /// internal definitions.
+    fn compile_body(&mut self, body: &[Exp], tail: bool) -> Compiled {
+        let scope = self.function().locals.len();
+        let body_start = std::mem::replace(&mut self.function().body_start, scope);
+        for name in defined_names(body) {
+            self.declare(name);
+        }
+        let compiled = self.compile_sequence(body, tail);
+        let function = self.function();
+        function.locals.truncate(scope);
+        function.body_start = body_start;
+        compiled
+    }
+
+    fn compile_lambda(&mut self, name: Option<Symbol>, params: &[Symbol], rest: Option<Symbol>, body: &[Exp]) -> Compiled {
+        if eval::docstring(body).is_some() {
+            return Err(Unsupported);
+        }
+        let proto = Proto { params: params.len(), rest: rest.is_some(), name, ..Proto::default() };
+        self.functions.push(Function { proto, locals: Vec::new(), captured: Vec::new(), body_start: 0 });
+        for param in params.iter().chain(&rest) {
+            self.declare(*param);
+        }
+        let compiled = self.compile_body(body, true);
+        let function = self.functions.pop().expect("compiling a function");
+        compiled?;
+        let mut proto = function.proto;
+        proto.code.push(Op::Return);
+        proto.spans.push(self.span.clone());
+        let protos = &mut self.function().proto.protos;
+        protos.push(Rc::new(proto));
+        let index = protos.len() - 1;
+        self.emit(Op::Closure(index));
+        Ok(())
+    }
+
+    fn false_value(&mut self) {
+        let constant = self.constant(Exp::Atom(Atom::Bool(false)));
+        self.emit(Op::Const(constant));
+    }
+
+    fn true_value(&mut self) {
+        let constant = self.constant(Exp::Atom(Atom::Bool(true)));
+        self.emit(Op::Const(constant));
+    }
+}
+
+/// Compiles a top-level form to a procedure without parameters, or returns
+/// `None` if it has to be evaluated by the tree-walker.
+fn compile(exp: &Exp, env: &Env) -> Option<Rc<Closure>> {
+    let mut compiler = Compiler {
+        functions: vec![Function { proto: Proto::default(), locals: Vec::new(), captured: Vec::new(), body_start: 0 }],
+        span: None,
+        env,
+    };
+    compiler.compile(exp, true).ok()?;
+    compiler.emit(Op::Return);
+    let function = compiler.functions.pop().expect("compiling a function");
+    Some(Rc::new(Closure { proto: Rc::new(function.proto), upvalues: Vec::new(), env: env.clone() }))
+}
+
+/// Evaluates `exp` in `env` on the VM.
+pub fn eval(exp: &Exp, env: &Env) -> Result<Exp> {
+    match compile(exp, env) {
+        Some(closure) => call(&closure, &[]),
+        None => eval::eval(exp, env),
+    }
+}
+
+struct Frame {
+    closure: Rc<Closure>,
+    pc: usize,
+    slots: Vec<Rc<RefCell<Exp>>>,
+}
+
+impl Frame {
+    fn new(closure: Rc<Closure>, mut args: Vec<Exp>) -> Result<Frame> {
+        let proto = &closure.proto;
+        let arity = closure.arity();
+        if !arity.accepts(args.len()) {
+            let name = closure.name().map_or("lambda".to_string(), |name| name.to_string());
+            return Err(BrewlisError::arity(&name, arity, args.len()));
+        }
+        if proto.rest {
+            let rest = args.split_off(proto.params);
+            args.push(Exp::List(rest.into()));
+        }
+        // Internal definitions start out as #f
+        let mut slots = args.into_iter().map(|arg| Rc::new(RefCell::new(arg))).collect::<Vec<_>>();
+        let unbound = closure.proto.slots - slots.len();
+        slots.extend((0..unbound).map(|_| Rc::new(RefCell::new(Exp::Atom(Atom::Bool(false))))));
+        Ok(Frame { closure, pc: 0, slots })
+    }
+}
+
+pub fn instructions() -> u64 {
+    INSTRUCTIONS.with(Cell::get)
+}
+
+/// Calls a compiled procedure with `args`.
+pub fn call(closure: &Rc<Closure>, args: &[Exp]) -> Result<Exp> {
+    eval::nested(|| run(closure, args))
+}
+
+fn run(closure: &Rc<Closure>, args: &[Exp]) -> Result<Exp> {
+    let mut frames = vec![Frame::new(closure.clone(), args.to_vec())?];
+    let mut stack: Vec<Exp> = Vec::new();
+    loop {
+        let frame = frames.last_mut().expect("a frame");
+        let pc = frame.pc;
+        frame.pc += 1;
+        let op = frame.closure.proto.code[pc];
+        INSTRUCTIONS.with(|instructions| instructions.set(instructions.get() + 1));
+        let proto = frame.closure.proto.clone();
+        let result = match op {
+            Op::Const(i) => {
+                stack.push(proto.constants[i].clone());
+                Ok(())
+            },
+            Op::Local(slot) => {
+                stack.push(frame.slots[slot].borrow().clone());
+                Ok(())
+            },
+            Op::Upvalue(i) => {
+                stack.push(frame.closure.upvalues[i].borrow().clone());
+                Ok(())
+            },
+            Op::Global(i) => {
+                let name = &proto.constants[i];
+                let Exp::Atom(Atom::Symbol(sym)) = name else { unreachable!("global names are symbols") };
+                eval::variable(sym, &frame.closure.env).map(|value| stack.push(value))
+            },
+            Op::StoreLocal(slot) => {
+                *frame.slots[slot].borrow_mut() = stack.pop().expect("a value");
+                Ok(())
+            },
+            Op::StoreUpvalue(i) => {
+                *frame.closure.upvalues[i].borrow_mut() = stack.pop().expect("a value");
+                Ok(())
+            },
+            Op::DefineGlobal(i) => {
+                let Exp::Atom(Atom::Symbol(sym)) = &proto.constants[i] else { unreachable!("global names are symbols") };
+                frame.closure.env.define(*sym, stack.pop().expect("a value"));
+                Ok(())
+            },
+            Op::SetGlobal(i) => {
+                let Exp::Atom(Atom::Symbol(sym)) = &proto.constants[i] else { unreachable!("global names are symbols") };
+                if frame.closure.env.set(sym, stack.pop().expect("a value")) {
+                    Ok(())
+                } else {
+                    Err(eval_error!("called 'set!' on unbound symbol {}", sym))
+                }
+            },
+            Op::Pop => {
+                stack.pop();
+                Ok(())
+            },
+            Op::Jump(target) => {
+                frame.pc = target;
+                Ok(())
+            },
+            Op::JumpIfFalse(target) => {
+                if !is_true(&stack.pop().expect("a value")) {
+                    frame.pc = target;
+                }
+                Ok(())
+            },
+            Op::JumpIfTrue(target) => {
+                if is_true(stack.last().expect("a value")) {
+                    frame.pc = target;
+                }
+                Ok(())
+            },
+            Op::JumpIfFalseKeep(target) => {
+                if !is_true(stack.last().expect("a value")) {
+                    frame.pc = target;
+                }
+                Ok(())
+            },
+            Op::Closure(i) => {
+                let proto = proto.protos[i].clone();
+                let upvalues = proto.captures.iter().map(|capture| match capture {
+                    Capture::Local(slot) => frame.slots[*slot].clone(),
+                    Capture::Upvalue(i) => frame.closure.upvalues[*i].clone(),
+                }).collect();
+                let closure = Rc::new(Closure { proto, upvalues, env: frame.closure.env.clone() });
+                // Only closures capturing variables can be part of a cycle.
+                if !closure.upvalues.is_empty() {
+                    gc::track(gc::Tracked::Closure(Rc::downgrade(&closure)));
+                }
+                stack.push(Exp::Closure(closure));
+                Ok(())
+            },
+            Op::Call(argc) | Op::TailCall(argc) => {
+                let args = stack.split_off(stack.len() - argc);
+                let procedure = stack.pop().expect("a procedure");
+                match procedure {
+                    // Traced calls are written by the tree-walker
+                    Exp::Closure(ref closure) if trace::traced_name(&procedure, closure.name()).is_some() => {
+                        let env = frame.closure.env.clone();
+                        eval::proc(&procedure, &args, &env).map(|value| stack.push(value))
+                    },
+                    Exp::Closure(closure) => match Frame::new(closure, args) {
+                        Ok(callee) if matches!(op, Op::TailCall(_)) => {
+                            *frame = callee;
+                            Ok(())
+                        },
+                        Ok(_) if frames.len() >= eval::max_depth() => Err(eval::depth_exceeded()),
+                        Ok(callee) => {
+                            frames.push(callee);
+                            Ok(())
+                        },
+                        Err(err) => Err(err),
+                    },
+                    procedure => {
+                        let env = frame.closure.env.clone();
+                        eval::proc(&procedure, &args, &env).map(|value| stack.push(value))
+                    },
+                }
+            },
+            Op::Return => {
+                frames.pop();
+                if frames.is_empty() {
+                    return Ok(stack.pop().expect("a return value"));
+                }
+                Ok(())
+            },
+        };
+        if let Err(err) = result {
+            let frame = frames.last().expect("a frame");
+            return Err(match &frame.closure.proto.spans[pc] {
+                Some(span) => err.with_span(span),
+                None => err,
+            });
+        }
+    }
+}
@@ -0,0 +1,45 @@
+//! JavaScript bindings of the WebAssembly build, for running brewlis in the
+//! browser.
+
+use wasm_bindgen::prelude::*;
+
+use crate::{Interpreter, eval::capture_output};
+
+/// Evaluates `source` in a new interpreter, see `Session::eval`. It is
+/// exported as `evaluate`, JavaScript reserves `eval`.
+#[wasm_bindgen(js_name = evaluate)]
+pub fn eval(source: &str) -> String {
+    Session::new().eval(source)
+}
+
+/// An interpreter keeping its definitions from one evaluation to the next.
+#[wasm_bindgen]
+pub struct Session {
+    interpreter: Interpreter,
+}
+
+#[wasm_bindgen]
+impl Session {
+    #[wasm_bindgen(constructor)]
+    pub fn new() -> Session {
+        Session { interpreter: Interpreter::new() }
+    }
+
+    /// Evaluates the forms of `source` and returns what they printed,
+    /// followed by the value of the last form or the error that stopped
+    /// evaluation.
+    pub fn eval(&mut self, source: &str) -> String {
+        let (result, mut output) = capture_output(|| self.interpreter.eval_str(source));
+        match result {
+            Ok(value) => output.push_str(&value.to_string()),
+            Err(err) => output.push_str(&format!("Error: {}", err.report())),
+        }
+        output
+    }
+}
+
+impl Default for Session {
+    fn default() -> Self {
+        Session::new()
+    }
+}